@@ -182,6 +182,16 @@ impl ConsensusService {
                 },
                 max_disjoint_headers: 1024,
                 max_requests_per_block: NonZeroU32::new(3).unwrap(),
+                max_fork_ancestry_searches: NonZeroU32::new(8).unwrap(),
+                max_consecutive_too_old_best_announces: None,
+                max_pending_grandpa_commits: 64,
+                max_header_size: 1024 * 1024,
+                max_blocks_ahead_of_finalized: None,
+                request_failures_before_ban: NonZeroU32::new(1).unwrap(),
+                aura_max_future_drift: Duration::from_secs(30),
+                verification_queue_capacity: 0,
+                source_saturation_threshold: None,
+                randomness_seed: rand::random(),
                 download_ahead_blocks: {
                     // Assuming a verification speed of 1k blocks/sec and a 99th download time
                     // percentile of two second, the number of blocks to download ahead of time
@@ -210,6 +220,7 @@ impl ConsensusService {
                         })
                         .unwrap()
                     },
+                    extra_runtime_environment_keys: Vec::new(),
                 }),
             });
 
@@ -493,13 +504,22 @@ impl SyncBackground {
 
                             let id = *self.peers_source_id_map.get(&peer_id).unwrap();
                             // TODO: log the outcome
-                            match self.sync.block_announce(id, header.scale_encoding_vec(), is_best) {
+                            let now_from_unix_epoch = SystemTime::now()
+                                .duration_since(SystemTime::UNIX_EPOCH)
+                                .unwrap();
+                            match self.sync.block_announce(
+                                id,
+                                header.scale_encoding_vec(),
+                                is_best,
+                                now_from_unix_epoch,
+                            ) {
                                 all::BlockAnnounceOutcome::HeaderVerify => {},
                                 all::BlockAnnounceOutcome::TooOld { .. } => {},
                                 all::BlockAnnounceOutcome::AlreadyInChain => {},
                                 all::BlockAnnounceOutcome::NotFinalizedChain => {},
                                 all::BlockAnnounceOutcome::Discarded => {},
                                 all::BlockAnnounceOutcome::StoredForLater {} => {},
+                                all::BlockAnnounceOutcome::SelfParent => {},
                                 all::BlockAnnounceOutcome::InvalidHeader(_) => unreachable!(),
                             }
                         },
@@ -758,6 +778,9 @@ impl SyncBackground {
             self.block_author_sync_source,
             block.scale_encoded_header.clone(),
             true, // Since the new block is a child of the current best block, it always becomes the new best.
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap(),
         ) {
             all::BlockAnnounceOutcome::HeaderVerify
             | all::BlockAnnounceOutcome::StoredForLater
@@ -765,7 +788,8 @@ impl SyncBackground {
             all::BlockAnnounceOutcome::TooOld { .. }
             | all::BlockAnnounceOutcome::AlreadyInChain
             | all::BlockAnnounceOutcome::NotFinalizedChain
-            | all::BlockAnnounceOutcome::InvalidHeader(_) => unreachable!(),
+            | all::BlockAnnounceOutcome::InvalidHeader(_)
+            | all::BlockAnnounceOutcome::SelfParent => unreachable!(),
         }
 
         debug_assert!(self.authored_block.is_none());
@@ -932,6 +956,8 @@ impl SyncBackground {
                     break;
                 }
                 all::ProcessOne::VerifyWarpSyncFragment(_) => unreachable!(),
+                // No extra runtime environment key is passed at initialization.
+                all::ProcessOne::BestRuntimeOutdated(_) => unreachable!(),
                 all::ProcessOne::VerifyBodyHeader(verify) => {
                     let hash_to_verify = verify.hash();
                     let height_to_verify = verify.height();
@@ -1108,6 +1134,7 @@ impl SyncBackground {
                             all::FinalityProofVerifyOutcome::NewFinalized {
                                 finalized_blocks,
                                 updates_best_block,
+                                ..
                             },
                         ) => {
                             span.record("outcome", &"success");
@@ -1163,7 +1190,11 @@ impl SyncBackground {
                             database_set_finalized(&self.database, new_finalized_hash).await;
                             continue;
                         }
-                        (sync_out, all::FinalityProofVerifyOutcome::GrandpaCommitPending) => {
+                        (
+                            sync_out,
+                            all::FinalityProofVerifyOutcome::GrandpaCommitPending
+                            | all::FinalityProofVerifyOutcome::JustificationPending,
+                        ) => {
                             span.record("outcome", &"pending");
                             self.sync = sync_out;
                             continue;
@@ -57,6 +57,16 @@ pub(super) async fn start_standalone_chain<TPlat: Platform>(
             },
             max_disjoint_headers: 1024,
             max_requests_per_block: NonZeroU32::new(3).unwrap(),
+            max_fork_ancestry_searches: NonZeroU32::new(8).unwrap(),
+            max_consecutive_too_old_best_announces: None,
+            max_pending_grandpa_commits: 64,
+            max_header_size: 1024 * 1024,
+            max_blocks_ahead_of_finalized: None,
+            request_failures_before_ban: NonZeroU32::new(1).unwrap(),
+            aura_max_future_drift: Duration::from_secs(30),
+            verification_queue_capacity: 0,
+            source_saturation_threshold: None,
+            randomness_seed: rand::random(),
             download_ahead_blocks: {
                 // Verifying a block mostly consists in:
                 //
@@ -773,8 +783,8 @@ impl<TPlat: Platform> Task<TPlat> {
                         sync,
                         all::FinalityProofVerifyOutcome::NewFinalized {
                             updates_best_block,
+                            updates_finalized_runtime,
                             finalized_blocks,
-                            ..
                         },
                     ) => {
                         self.sync = sync;
@@ -791,10 +801,7 @@ impl<TPlat: Platform> Task<TPlat> {
                         self.network_up_to_date_finalized = false;
                         // Invalidate the cache of the runtime of the finalized blocks if any
                         // of the finalized blocks indicates that a runtime update happened.
-                        if finalized_blocks
-                            .iter()
-                            .any(|b| b.header.digest.has_runtime_environment_updated())
-                        {
+                        if updates_finalized_runtime {
                             self.known_finalized_runtime = None;
                         }
                         self.dispatch_all_subscribers(Notification::Finalized {
@@ -806,7 +813,8 @@ impl<TPlat: Platform> Task<TPlat> {
                     (
                         sync,
                         all::FinalityProofVerifyOutcome::AlreadyFinalized
-                        | all::FinalityProofVerifyOutcome::GrandpaCommitPending,
+                        | all::FinalityProofVerifyOutcome::GrandpaCommitPending
+                        | all::FinalityProofVerifyOutcome::JustificationPending,
                     ) => {
                         self.sync = sync;
                     }
@@ -849,6 +857,7 @@ impl<TPlat: Platform> Task<TPlat> {
 
             // Can't verify header and body in non-full mode.
             all::ProcessOne::VerifyBodyHeader(_) => unreachable!(),
+            all::ProcessOne::BestRuntimeOutdated(_) => unreachable!(),
         }
 
         (self, true)
@@ -1026,6 +1035,7 @@ impl<TPlat: Platform> Task<TPlat> {
                     sync_source_id,
                     decoded.scale_encoded_header.to_owned(),
                     decoded.is_best,
+                    TPlat::now_from_unix_epoch(),
                 ) {
                     all::BlockAnnounceOutcome::HeaderVerify
                     | all::BlockAnnounceOutcome::AlreadyInChain => {
@@ -1077,6 +1087,18 @@ impl<TPlat: Platform> Task<TPlat> {
                     all::BlockAnnounceOutcome::InvalidHeader(_) => {
                         // Log messages are already printed above.
                     }
+                    all::BlockAnnounceOutcome::SelfParent => {
+                        log::debug!(
+                            target: &self.log_target,
+                            "Sync => SelfParent"
+                        );
+
+                        log::warn!(
+                            target: &self.log_target,
+                            "Block announce header from {} indicates itself as its own parent",
+                            peer_id
+                        );
+                    }
                 }
             }
 
@@ -97,7 +97,19 @@ pub struct Config {
     /// even if `true` is passed, a recognized consensus engine must always be present.
     /// Consequently, both `true` and `false` guarantee that the number of authorable blocks over
     /// the network is bounded.
+    ///
+    /// Digest items that are recognized but don't relate to block authoring, such as BEEFY
+    /// items, are accepted no matter the value of this flag.
     pub allow_unknown_consensus_engines: bool,
+
+    /// Maximum duration by which the slot of a block is allowed to be in the future compared to
+    /// the current time, in order to tolerate clock drifts. Blocks further in the future fail to
+    /// verify with [`crate::verify::header_only::Error::TooFarInFuture`] or
+    /// [`crate::verify::header_body::Error::TooFarInFuture`].
+    ///
+    /// Only applies to chains that use the Aura consensus engine. The slot of blocks using the
+    /// Babe consensus engine isn't compared with the current time.
+    pub aura_max_future_drift: Duration,
 }
 
 /// Holds state about the current state of the chain for the purpose of verifying headers.
@@ -168,6 +180,7 @@ impl<T> NonFinalizedTree<T> {
                 current_best: None,
                 block_number_bytes: config.block_number_bytes,
                 allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+                aura_max_future_drift: config.aura_max_future_drift,
             })),
         }
     }
@@ -214,6 +227,19 @@ impl<T> NonFinalizedTree<T> {
             .map(|(_, b)| (&b.header).into())
     }
 
+    /// Destroys the tree and returns the header and user data of all known non-finalized blocks
+    /// in the chain.
+    ///
+    /// The returned items are guaranteed to be in an order in which the parents are found before
+    /// their children.
+    pub fn into_iter_ancestry_order(self) -> impl Iterator<Item = (header::Header, T)> {
+        self.inner
+            .unwrap()
+            .blocks
+            .into_iter_ancestry_order()
+            .map(|(_, b)| (b.header, b.user_data))
+    }
+
     /// Reserves additional capacity for at least `additional` new blocks without allocating.
     pub fn reserve(&mut self, additional: usize) {
         let inner = self.inner.as_mut().unwrap();
@@ -382,6 +408,45 @@ impl<T> NonFinalizedTree<T> {
             .contains_key(hash)
     }
 
+    /// Returns the height and hash of the lowest common ancestor of the two given blocks, which
+    /// can be either the finalized block or blocks stored in the [`NonFinalizedTree`].
+    ///
+    /// If one block is an ancestor of the other, it is returned. Returns `None` if either block
+    /// can't be found.
+    pub fn common_ancestor(&self, hash1: &[u8; 32], hash2: &[u8; 32]) -> Option<(u64, [u8; 32])> {
+        let inner = self.inner.as_ref().unwrap();
+
+        let node1 = if *hash1 == inner.finalized_block_hash {
+            None
+        } else {
+            Some(*inner.blocks_by_hash.get(hash1)?)
+        };
+        let node2 = if *hash2 == inner.finalized_block_hash {
+            None
+        } else {
+            Some(*inner.blocks_by_hash.get(hash2)?)
+        };
+
+        // All the blocks of the tree descend from the finalized block. If the two blocks have
+        // no common ancestor within the tree, the finalized block is their common ancestor.
+        match (node1, node2) {
+            (Some(node1), Some(node2)) => match inner.blocks.common_ancestor(node1, node2) {
+                Some(ancestor) => {
+                    let block = inner.blocks.get(ancestor).unwrap();
+                    Some((block.header.number, block.hash))
+                }
+                None => Some((
+                    inner.finalized_block_header.number,
+                    inner.finalized_block_hash,
+                )),
+            },
+            _ => Some((
+                inner.finalized_block_header.number,
+                inner.finalized_block_hash,
+            )),
+        }
+    }
+
     /// Gives access to the user data of a block stored by the [`NonFinalizedTree`], identified
     /// by its hash.
     ///
@@ -460,6 +525,8 @@ struct NonFinalizedTreeInner<T> {
     block_number_bytes: usize,
     /// See [`Config::allow_unknown_consensus_engines`].
     allow_unknown_consensus_engines: bool,
+    /// See [`Config::aura_max_future_drift`].
+    aura_max_future_drift: Duration,
 }
 
 /// State of the consensus of the finalized block.
@@ -602,6 +602,21 @@ impl<'c, T> FinalityApply<'c, T> {
             .user_data
     }
 
+    /// Returns the hash of the block about to be justified.
+    pub fn block_hash(&self) -> &[u8; 32] {
+        &self.chain.blocks.get(self.to_finalize).unwrap().hash
+    }
+
+    /// Returns the height of the block about to be justified.
+    pub fn block_height(&self) -> u64 {
+        self.chain
+            .blocks
+            .get(self.to_finalize)
+            .unwrap()
+            .header
+            .number
+    }
+
     /// Returns true if the block to be finalized is the current best block.
     pub fn is_current_best_block(&self) -> bool {
         Some(self.to_finalize) == self.chain.current_best
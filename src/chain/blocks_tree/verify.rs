@@ -268,6 +268,7 @@ impl<T> NonFinalizedTreeInner<T> {
                     }
                 },
                 allow_unknown_consensus_engines: context.chain.allow_unknown_consensus_engines,
+                aura_max_future_drift: context.chain.aura_max_future_drift,
                 block_header: (&context.header).into(), // TODO: inefficiency ; in case of header only verify we do an extra allocation to build the context above
                 parent_block_header: parent_block_header.into(),
             })
@@ -703,6 +704,7 @@ impl<T> BodyVerifyRuntimeRequired<T> {
             parent_runtime,
             consensus: config_consensus,
             allow_unknown_consensus_engines: self.context.chain.allow_unknown_consensus_engines,
+            aura_max_future_drift: self.context.chain.aura_max_future_drift,
             now_from_unix_epoch: self.now_from_unix_epoch,
             block_header: (&self.context.header).into(),
             parent_block_header: parent_block_header.into(),
@@ -1057,6 +1059,39 @@ impl<'c, T> HeaderInsert<'c, T> {
         From::from(&self.context.as_ref().unwrap().header)
     }
 
+    /// Returns the Sr25519 public key of the authority that has authored the block about to be
+    /// inserted. Since the seal of the block has been verified, this authority is known to have
+    /// signed the block.
+    pub fn author_public_key(&self) -> [u8; 32] {
+        let context = self.context.as_ref().unwrap();
+        let digest = header::DigestRef::from(&context.header.digest);
+
+        match (&context.consensus, self.consensus.as_ref().unwrap()) {
+            (VerifyConsensusSpecific::Aura { authorities_list }, _) => {
+                // With Aura, the author of a block is entirely determined by its slot.
+                let slot_number = digest.aura_pre_runtime().unwrap().slot_number;
+                let index =
+                    usize::try_from(slot_number % u64::try_from(authorities_list.len()).unwrap())
+                        .unwrap();
+                authorities_list[index].public_key
+            }
+            (
+                VerifyConsensusSpecific::Babe { .. },
+                BlockConsensus::Babe {
+                    current_epoch,
+                    next_epoch,
+                },
+            ) => {
+                // The current epoch of the block is the one the block belongs to.
+                let epoch = current_epoch.as_ref().unwrap_or(next_epoch);
+                let index = digest.babe_pre_runtime().unwrap().authority_index();
+                epoch.authorities[usize::try_from(index).unwrap()].public_key
+            }
+            // A successful verification implies that the consensus engine is known.
+            _ => unreachable!(),
+        }
+    }
+
     /// Destroys the object without inserting the block in the chain. Returns the block header.
     pub fn into_header(mut self) -> header::Header {
         let context = self.context.take().unwrap();
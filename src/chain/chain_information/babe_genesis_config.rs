@@ -16,7 +16,7 @@
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
 use crate::{
-    executor::{host, vm},
+    executor::{self, host, vm},
     header,
 };
 
@@ -90,6 +90,34 @@ impl BabeGenesisConfiguration {
             }
         }
     }
+
+    /// Similar to [`BabeGenesisConfiguration::from_virtual_machine_prototype`], but also
+    /// retrieves the runtime version by calling `Core_version` on the same virtual machine
+    /// prototype.
+    ///
+    /// Building a [`crate::chain::chain_information::ChainInformation`] typically requires both
+    /// the consensus configuration and the runtime version. This function avoids having to
+    /// pass the virtual machine prototype around between the two calls.
+    ///
+    /// Returns back the same virtual machine prototype as was passed as parameter.
+    pub fn from_virtual_machine_prototype_with_core_version(
+        vm: host::HostVmPrototype,
+        genesis_storage_access: impl FnMut(&[u8]) -> Option<Vec<u8>>,
+    ) -> (
+        Result<(Self, executor::CoreVersion), FromVmPrototypeError>,
+        host::HostVmPrototype,
+    ) {
+        let (config, vm) = Self::from_virtual_machine_prototype(vm, genesis_storage_access);
+        let config = match config {
+            Ok(c) => c,
+            Err(err) => return (Err(err), vm),
+        };
+
+        match executor::core_version(vm) {
+            (Ok(version), vm) => (Ok((config, version)), vm),
+            (Err(err), vm) => (Err(FromVmPrototypeError::CoreVersion(err)), vm),
+        }
+    }
 }
 
 /// Error when retrieving the BABE configuration.
@@ -104,6 +132,10 @@ pub enum FromVmPrototypeError {
     HostFunctionNotAllowed,
     /// Error while decoding the output of the virtual machine.
     OutputDecode,
+    /// Error while retrieving the runtime version. Only returned by
+    /// [`BabeGenesisConfiguration::from_virtual_machine_prototype_with_core_version`].
+    #[display(fmt = "Error while retrieving the runtime version: {}", _0)]
+    CoreVersion(executor::CoreVersionError),
 }
 
 impl FromVmPrototypeError {
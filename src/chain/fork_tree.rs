@@ -51,6 +51,7 @@
 //! assert!(tree.get(node2).is_some());
 //! ```
 
+use alloc::vec::Vec;
 use core::{fmt, iter};
 
 /// Tree of nodes. Each node contains a value of type `T`.
@@ -136,6 +137,18 @@ impl<T> ForkTree<T> {
         .map(move |idx| (idx, &self.nodes[idx.0].data))
     }
 
+    /// Destroys the tree and returns all the node values. The returned items are guaranteed to
+    /// be in an order in which the parents are found before their children.
+    pub fn into_iter_ancestry_order(mut self) -> impl Iterator<Item = (NodeIndex, T)> {
+        let order = self
+            .iter_ancestry_order()
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        order
+            .into_iter()
+            .map(move |idx| (idx, self.nodes.remove(idx.0).data))
+    }
+
     fn ancestry_order_next(&self, node_index: NodeIndex) -> Option<NodeIndex> {
         debug_assert!(!self.nodes[node_index.0].is_prune_target_ancestor);
 
@@ -758,5 +771,28 @@ mod tests {
         assert_eq!(tree.common_ancestor(node0, node1), None);
     }
 
+    #[test]
+    fn into_iter_ancestry_order_parents_first() {
+        let mut tree = ForkTree::new();
+
+        let node0 = tree.insert(None, 0);
+        let node1 = tree.insert(Some(node0), 1);
+        let _node2 = tree.insert(Some(node1), 2);
+        let node3 = tree.insert(None, 3);
+        let _node4 = tree.insert(Some(node3), 4);
+        let _node5 = tree.insert(Some(node0), 5);
+
+        let expected = tree
+            .iter_ancestry_order()
+            .map(|(_, v)| *v)
+            .collect::<Vec<_>>();
+        let values = tree
+            .into_iter_ancestry_order()
+            .map(|(_, v)| v)
+            .collect::<Vec<_>>();
+        assert_eq!(values, expected);
+        assert_eq!(values.len(), 6);
+    }
+
     // TODO: add more testing for the order of elements returned by `prune_ancestors`
 }
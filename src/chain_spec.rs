@@ -47,8 +47,9 @@ use alloc::{
     string::{String, ToString as _},
     vec::Vec,
 };
-use core::num::NonZeroU64;
+use core::{num::NonZeroU64, ops};
 
+mod genesis_builder;
 mod light_sync_state;
 mod structs;
 
@@ -68,9 +69,18 @@ impl ChainSpec {
         // TODO: we don't support child tries in the genesis block
         assert!(match &client_spec.genesis {
             structs::Genesis::Raw(genesis) => genesis.children_default.is_empty(),
-            structs::Genesis::StateRootHash(_) => true,
+            structs::Genesis::StateRootHash(_)
+            | structs::Genesis::Runtime(_)
+            | structs::Genesis::RuntimeGenesis(_) => true,
         });
 
+        // Exactly one of `patch` and `config` must be present.
+        if let structs::Genesis::RuntimeGenesis(genesis) = &client_spec.genesis {
+            if genesis.patch.is_some() == genesis.config.is_some() {
+                return Err(ParseError(ParseErrorInner::RuntimeGenesisConfigConflict));
+            }
+        }
+
         // Make sure that the light sync state can be successfully decoded.
         if let Some(light_sync_state) = &client_spec.light_sync_state {
             light_sync_state.decode()?;
@@ -79,6 +89,92 @@ impl ChainSpec {
         Ok(ChainSpec { client_spec })
     }
 
+    /// Checks whether the chain spec is usable, without building the genesis block or
+    /// compiling the runtime.
+    ///
+    /// Contrary to the other methods of [`ChainSpec`], which silently fall back to default
+    /// values or ignore invalid entries, all the problems found are reported.
+    ///
+    /// > **Note**: The format of the hashes found in the list of fork blocks and bad blocks is
+    /// >           already enforced by [`ChainSpec::from_json_bytes`]. Only their consistency is
+    /// >           checked here.
+    pub fn validate(&self) -> Result<(), Vec<SpecProblem>> {
+        let mut problems = Vec::new();
+
+        match self.genesis_kind() {
+            GenesisKind::Raw | GenesisKind::StateRootHash => {}
+            GenesisKind::Runtime | GenesisKind::RuntimeGenesis => {
+                problems.push(SpecProblem::UnsupportedGenesisKind)
+            }
+        }
+
+        if let Some(block_number_bytes) = self.client_spec.block_number_bytes {
+            if !(1..=8).contains(&block_number_bytes) {
+                problems.push(SpecProblem::InvalidBlockNumberBytes(block_number_bytes));
+            }
+        }
+
+        for bootnode in self.boot_nodes() {
+            if let Bootnode::UnrecognizedFormat(addr) = bootnode {
+                problems.push(SpecProblem::InvalidBootnode(addr.to_owned()));
+            }
+        }
+
+        if let Some(protocol_id) = &self.client_spec.protocol_id {
+            if !is_valid_protocol_name_component(protocol_id) {
+                problems.push(SpecProblem::InvalidProtocolId(protocol_id.clone()));
+            }
+        }
+
+        if let Some(fork_id) = &self.client_spec.fork_id {
+            if !is_valid_protocol_name_component(fork_id) {
+                problems.push(SpecProblem::InvalidForkId(fork_id.clone()));
+            }
+        }
+
+        for endpoint in self.telemetry_endpoints() {
+            let endpoint = endpoint.as_ref();
+            let is_valid = endpoint.parse::<libp2p::Multiaddr>().is_ok()
+                || ["ws://", "wss://"].iter().any(|scheme| {
+                    endpoint
+                        .strip_prefix(scheme)
+                        .map_or(false, |rest| !rest.is_empty() && !rest.starts_with('/'))
+                });
+            if !is_valid {
+                problems.push(SpecProblem::InvalidTelemetryEndpoint(endpoint.to_owned()));
+            }
+        }
+
+        if let Some(fork_blocks) = &self.client_spec.fork_blocks {
+            for (index, (height, hash)) in fork_blocks.iter().enumerate() {
+                if fork_blocks[..index]
+                    .iter()
+                    .any(|(h, other)| h == height && other != hash)
+                {
+                    problems.push(SpecProblem::ConflictingForkBlocks { height: *height });
+                }
+
+                if self
+                    .client_spec
+                    .bad_blocks
+                    .as_ref()
+                    .map_or(false, |bad_blocks| bad_blocks.contains(hash))
+                {
+                    problems.push(SpecProblem::BadForkBlock {
+                        height: *height,
+                        hash: hash.0,
+                    });
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
     /// Builds the [`ChainInformation`] corresponding to the genesis block contained in this chain
     /// spec.
     ///
@@ -92,6 +188,9 @@ impl ChainSpec {
             GenesisStorage::TrieRootHash(_) => {
                 return Err(FromGenesisStorageError::UnknownStorageItems)
             }
+            GenesisStorage::Unsupported => {
+                return Err(FromGenesisStorageError::UnsupportedGenesisKind)
+            }
         };
 
         let wasm_code = genesis_storage
@@ -197,29 +296,9 @@ impl ChainSpec {
             finalized_block_header: {
                 let state_root = match self.genesis_storage() {
                     GenesisStorage::TrieRootHash(hash) => *hash,
+                    GenesisStorage::Unsupported => unreachable!(),
                     GenesisStorage::Items(genesis_storage) => {
-                        let mut calculation = trie::calculate_root::root_merkle_value(None);
-
-                        loop {
-                            match calculation {
-                                trie::calculate_root::RootMerkleValueCalculation::Finished {
-                                    hash,
-                                    ..
-                                } => break hash,
-                                trie::calculate_root::RootMerkleValueCalculation::AllKeys(keys) => {
-                                    calculation = keys.inject(
-                                        genesis_storage.iter().map(|(k, _)| k.iter().copied()),
-                                    );
-                                }
-                                trie::calculate_root::RootMerkleValueCalculation::StorageValue(
-                                    val,
-                                ) => {
-                                    let key: alloc::vec::Vec<u8> = val.key().collect();
-                                    let value = genesis_storage.value(&key[..]);
-                                    calculation = val.inject(state_version, value);
-                                }
-                            }
-                        }
+                        genesis_storage.trie_root_hash(state_version)
                     }
                 };
 
@@ -334,14 +413,101 @@ impl ChainSpec {
             .map(|p| (p.relay_chain.as_str(), p.para_id))
     }
 
+    /// Returns the form in which the genesis block is described in the chain spec.
+    ///
+    /// Only [`GenesisKind::Raw`] and [`GenesisKind::StateRootHash`] are supported by smoldot.
+    /// A chain spec of kind [`GenesisKind::Runtime`] must first be converted into a raw chain
+    /// spec, for example using the `build-spec --raw` command of Substrate. The storage of a
+    /// chain spec of kind [`GenesisKind::RuntimeGenesis`] can be built using
+    /// [`ChainSpec::build_runtime_genesis_storage`].
+    pub fn genesis_kind(&self) -> GenesisKind {
+        match &self.client_spec.genesis {
+            structs::Genesis::Raw(_) => GenesisKind::Raw,
+            structs::Genesis::StateRootHash(_) => GenesisKind::StateRootHash,
+            structs::Genesis::Runtime(_) => GenesisKind::Runtime,
+            structs::Genesis::RuntimeGenesis(_) => GenesisKind::RuntimeGenesis,
+        }
+    }
+
     /// Gives access to what is known about the storage of the genesis block of the chain.
     pub fn genesis_storage(&self) -> GenesisStorage {
         match &self.client_spec.genesis {
             structs::Genesis::Raw(raw) => GenesisStorage::Items(GenesisStorageItems { raw }),
             structs::Genesis::StateRootHash(hash) => GenesisStorage::TrieRootHash(&hash.0),
+            structs::Genesis::Runtime(_) | structs::Genesis::RuntimeGenesis(_) => {
+                GenesisStorage::Unsupported
+            }
         }
     }
 
+    /// Builds the list of storage items of the genesis block of a chain spec of kind
+    /// [`GenesisKind::RuntimeGenesis`], by executing the `GenesisBuilder` runtime API of the
+    /// runtime found in the chain spec.
+    ///
+    /// Returns the storage items ordered by key, including `:code`. The items can then be used
+    /// to build a raw chain spec.
+    ///
+    /// > **Note**: This executes the runtime, which can take a long time.
+    pub fn build_runtime_genesis_storage(
+        &self,
+    ) -> Result<GenesisStorageEntries, BuildGenesisStorageError> {
+        let genesis = match &self.client_spec.genesis {
+            structs::Genesis::RuntimeGenesis(genesis) => genesis,
+            _ => return Err(BuildGenesisStorageError::NotRuntimeGenesis),
+        };
+
+        let config = match (&genesis.patch, &genesis.config) {
+            (Some(patch), None) => genesis_builder::GenesisConfig::Patch(patch.get()),
+            (None, Some(config)) => genesis_builder::GenesisConfig::Full(config.get()),
+            // Checked when parsing the chain spec.
+            _ => unreachable!(),
+        };
+
+        genesis_builder::build_genesis_storage(&genesis.code.0, config)
+    }
+
+    /// Returns the value associated to the given key in the storage of the genesis block.
+    ///
+    /// Returns `None` if there is no value corresponding to that key, or if the chain spec
+    /// doesn't contain the list of storage items of the genesis block (see
+    /// [`GenesisStorage::TrieRootHash`]).
+    ///
+    /// Contrary to iterating over [`GenesisStorageItems::iter`], this doesn't require scanning
+    /// the entire genesis storage.
+    pub fn genesis_storage_value(&self, key: &[u8]) -> Option<&[u8]> {
+        match &self.client_spec.genesis {
+            structs::Genesis::Raw(raw) => raw.top.get(key).map(|value| &value.0[..]),
+            structs::Genesis::StateRootHash(_)
+            | structs::Genesis::Runtime(_)
+            | structs::Genesis::RuntimeGenesis(_) => None,
+        }
+    }
+
+    /// Returns the list of keys and values of the storage of the genesis block whose key starts
+    /// with the given prefix. The items are ordered lexicographically by key.
+    ///
+    /// The returned iterator is empty if the chain spec doesn't contain the list of storage
+    /// items of the genesis block (see [`GenesisStorage::TrieRootHash`]).
+    pub fn genesis_storage_prefix<'a>(
+        &'a self,
+        prefix: &'a [u8],
+    ) -> impl Iterator<Item = (&'a [u8], &'a [u8])> + 'a {
+        let top = match &self.client_spec.genesis {
+            structs::Genesis::Raw(raw) => Some(&raw.top),
+            structs::Genesis::StateRootHash(_)
+            | structs::Genesis::Runtime(_)
+            | structs::Genesis::RuntimeGenesis(_) => None,
+        };
+
+        // Since the keys are ordered, all the keys that start with `prefix` are found right
+        // after `prefix`.
+        top.into_iter().flat_map(move |top| {
+            top.range::<[u8], _>((ops::Bound::Included(prefix), ops::Bound::Unbounded))
+                .take_while(move |(key, _)| key.0.starts_with(prefix))
+                .map(|(key, value)| (&key.0[..], &value.0[..]))
+        })
+    }
+
     /// Returns a list of arbitrary properties contained in the chain specs, such as the name of
     /// the token or the number of decimals.
     ///
@@ -367,6 +533,51 @@ impl ChainSpec {
     }
 }
 
+/// Returns `true` if the given string can be used as a component of the name of a networking
+/// protocol, such as the protocol id or the fork id.
+fn is_valid_protocol_name_component(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_graphic() && c != '/')
+}
+
+/// Problem found by [`ChainSpec::validate`].
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum SpecProblem {
+    /// Chain specification describes the genesis block in a non-raw form, which isn't
+    /// supported by [`ChainSpec::as_chain_information`]. See [`GenesisKind::Runtime`] and
+    /// [`GenesisKind::RuntimeGenesis`]. The latter can be turned into a list of storage items
+    /// with [`ChainSpec::build_runtime_genesis_storage`].
+    UnsupportedGenesisKind,
+    /// Number of bytes of the block number isn't between 1 and 8.
+    #[display(fmt = "Invalid number of bytes of the block number: {}", _0)]
+    InvalidBlockNumberBytes(u8),
+    /// Address of a bootnode couldn't be parsed. See [`Bootnode::UnrecognizedFormat`].
+    #[display(fmt = "Invalid bootnode address: {}", _0)]
+    InvalidBootnode(String),
+    /// Protocol id is empty or contains characters that can't be used in a protocol name.
+    #[display(fmt = "Invalid protocol id: {:?}", _0)]
+    InvalidProtocolId(String),
+    /// Fork id is empty or contains characters that can't be used in a protocol name.
+    #[display(fmt = "Invalid fork id: {:?}", _0)]
+    InvalidForkId(String),
+    /// Telemetry endpoint is neither a multiaddress nor a WebSocket URL.
+    #[display(fmt = "Invalid telemetry endpoint: {}", _0)]
+    InvalidTelemetryEndpoint(String),
+    /// Multiple different fork blocks are found at the same height.
+    #[display(fmt = "Multiple different fork blocks at height {}", height)]
+    ConflictingForkBlocks {
+        /// Height of the fork blocks.
+        height: u64,
+    },
+    /// A fork block is also in the list of bad blocks.
+    #[display(fmt = "Fork block at height {} is also a bad block", height)]
+    BadForkBlock {
+        /// Height of the fork block.
+        height: u64,
+        /// Hash of the fork block.
+        hash: [u8; 32],
+    },
+}
+
 /// See [`ChainSpec::boot_nodes`].
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Bootnode<'a> {
@@ -381,6 +592,11 @@ pub enum Bootnode<'a> {
         ///
         /// The format can be found in the libp2p specification:
         /// <https://github.com/libp2p/specs/blob/master/peer-ids/peer-ids.md>
+        ///
+        /// Both the legacy form of peer ids (`Qm...`, where the public key is hashed using
+        /// SHA-256) and the more recent form (`12D3...`, where the public key is directly
+        /// embedded) are accepted in chain specifications. The peer id is always returned as
+        /// the bytes of its multihash, no matter which form was used.
         peer_id: Vec<u8>,
     },
 
@@ -391,6 +607,23 @@ pub enum Bootnode<'a> {
     UnrecognizedFormat(&'a str),
 }
 
+/// See [`ChainSpec::genesis_kind`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GenesisKind {
+    /// The chain spec contains the list of storage items of the genesis block.
+    Raw,
+    /// The chain spec only contains the hash of the root node of the trie of the genesis
+    /// storage.
+    StateRootHash,
+    /// The chain spec contains a runtime-specific configuration of the genesis block, and the
+    /// genesis storage can only be built by executing the runtime. This form isn't supported.
+    Runtime,
+    /// The chain spec contains the runtime code of the genesis block and a JSON configuration
+    /// of the genesis block. The genesis storage can be built with
+    /// [`ChainSpec::build_runtime_genesis_storage`].
+    RuntimeGenesis,
+}
+
 /// See [`ChainSpec::genesis_storage`].
 pub enum GenesisStorage<'a> {
     /// The items of the genesis storage are known.
@@ -398,6 +631,9 @@ pub enum GenesisStorage<'a> {
     /// The items of the genesis storage are unknown, but we know the hash of the root node
     /// of the trie.
     TrieRootHash(&'a [u8; 32]),
+    /// The genesis storage is described in a form that isn't supported. See
+    /// [`GenesisKind::Runtime`] and [`GenesisKind::RuntimeGenesis`].
+    Unsupported,
 }
 
 impl<'a> GenesisStorage<'a> {
@@ -405,14 +641,14 @@ impl<'a> GenesisStorage<'a> {
     pub fn into_genesis_items(self) -> Option<GenesisStorageItems<'a>> {
         match self {
             GenesisStorage::Items(items) => Some(items),
-            GenesisStorage::TrieRootHash(_) => None,
+            GenesisStorage::TrieRootHash(_) | GenesisStorage::Unsupported => None,
         }
     }
 
     /// Returns `Some` for [`GenesisStorage::TrieRootHash`], and `None` otherwise.
     pub fn into_trie_root_hash(self) -> Option<&'a [u8; 32]> {
         match self {
-            GenesisStorage::Items(_) => None,
+            GenesisStorage::Items(_) | GenesisStorage::Unsupported => None,
             GenesisStorage::TrieRootHash(hash) => Some(hash),
         }
     }
@@ -435,6 +671,35 @@ impl<'a> GenesisStorageItems<'a> {
     pub fn value(&self, key: &[u8]) -> Option<&[u8]> {
         self.raw.top.get(key).map(|value| &value.0[..])
     }
+
+    /// Calculates the hash of the root node of the trie of the genesis storage.
+    ///
+    /// If the genesis storage is empty, this returns the hash of an empty trie.
+    pub fn trie_root_hash(&self, state_version: trie::TrieEntryVersion) -> [u8; 32] {
+        // Hand-written chain specs might not contain any storage item. This is handled
+        // explicitly rather than relying on the root calculation handling this corner case.
+        if self.raw.top.is_empty() {
+            return trie::empty_trie_merkle_value();
+        }
+
+        let mut calculation = trie::calculate_root::root_merkle_value(None);
+
+        loop {
+            match calculation {
+                trie::calculate_root::RootMerkleValueCalculation::Finished { hash, .. } => {
+                    break hash
+                }
+                trie::calculate_root::RootMerkleValueCalculation::AllKeys(keys) => {
+                    calculation = keys.inject(self.iter().map(|(k, _)| k.iter().copied()));
+                }
+                trie::calculate_root::RootMerkleValueCalculation::StorageValue(val) => {
+                    let key: Vec<u8> = val.key().collect();
+                    let value = self.value(&key[..]);
+                    calculation = val.inject(state_version, value);
+                }
+            }
+        }
+    }
 }
 
 pub struct LightSyncState {
@@ -518,12 +783,50 @@ impl LightSyncState {
 #[display(fmt = "Failed to parse chain spec")]
 pub struct ParseError(ParseErrorInner);
 
+impl ParseError {
+    /// Returns `true` if the genesis of the chain spec is in the `runtimeGenesis` form and
+    /// contains either both or neither of `patch` and `config`.
+    pub fn is_runtime_genesis_config_conflict(&self) -> bool {
+        matches!(self.0, ParseErrorInner::RuntimeGenesisConfigConflict)
+    }
+}
+
 #[derive(Debug, derive_more::Display)]
 enum ParseErrorInner {
     Serde(serde_json::Error),
+    /// Exactly one of `patch` and `config` must be present in a `runtimeGenesis`.
+    #[display(fmt = "Exactly one of `patch` and `config` must be present in `runtimeGenesis`")]
+    RuntimeGenesisConfigConflict,
     Other,
 }
 
+/// List of `(key, value)` storage items, ordered by key, returned by
+/// [`ChainSpec::build_runtime_genesis_storage`].
+pub type GenesisStorageEntries = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Error potentially returned by [`ChainSpec::build_runtime_genesis_storage`].
+#[derive(Debug, derive_more::Display)]
+pub enum BuildGenesisStorageError {
+    /// Chain specification isn't of kind [`GenesisKind::RuntimeGenesis`].
+    NotRuntimeGenesis,
+    /// Error when initializing the virtual machine.
+    #[display(fmt = "Error when initializing the virtual machine: {}", _0)]
+    VmInitialization(executor::host::NewErr),
+    /// Error when starting a runtime call.
+    #[display(fmt = "Error when starting a runtime call: {}", _0)]
+    VmStart(executor::host::StartErr),
+    /// Error during the execution of a runtime call.
+    #[display(fmt = "Error during the execution of a runtime call: {}", _0)]
+    Execution(executor::runtime_host::ErrorDetail),
+    /// Default genesis configuration returned by the runtime isn't valid JSON.
+    InvalidDefaultConfig,
+    /// Runtime has failed to build the genesis storage from the configuration.
+    #[display(fmt = "Runtime has failed to build the genesis storage: {}", _0)]
+    BuildConfig(String),
+    /// Output of the runtime call that builds the genesis storage couldn't be decoded.
+    InvalidBuildConfigOutput,
+}
+
 /// Error when building the chain information from the genesis storage.
 #[derive(Debug, derive_more::Display)]
 pub enum FromGenesisStorageError {
@@ -553,11 +856,21 @@ pub enum FromGenesisStorageError {
     MultipleConsensusAlgorithms,
     /// Chain specification doesn't contain the list of storage items.
     UnknownStorageItems,
+    /// Chain specification describes the genesis block in a non-raw form. It must first be
+    /// converted to a raw chain specification.
+    #[display(
+        fmt = "Chain specification isn't in raw form. Convert it using `build-spec --raw` first."
+    )]
+    UnsupportedGenesisKind,
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Bootnode, ChainSpec};
+    use super::{
+        trie, Bootnode, BuildGenesisStorageError, ChainSpec, FromGenesisStorageError, GenesisKind,
+        SpecProblem,
+    };
+    use crate::executor::{host, vm};
 
     #[test]
     fn can_decode_polkadot_genesis() {
@@ -593,4 +906,202 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn boot_nodes_peer_id_forms() {
+        let spec = ChainSpec::from_json_bytes(
+            r#"{
+                "name": "Test",
+                "id": "test",
+                "bootNodes": [
+                    "/dns/example.com/tcp/30333/p2p/QmSoLnSGccFuZQJzRadHn95W2CrSFmZuTdDWP8HXaHca9z",
+                    "/dns/example.com/tcp/30333/p2p/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN"
+                ],
+                "genesis": {
+                    "stateRootHash": "0x0000000000000000000000000000000000000000000000000000000000000000"
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            spec.boot_nodes().collect::<Vec<_>>(),
+            vec![
+                Bootnode::Parsed {
+                    multiaddr: "/dns/example.com/tcp/30333".into(),
+                    peer_id: vec![
+                        18, 32, 66, 69, 41, 74, 170, 23, 155, 99, 63, 254, 34, 25, 39, 250, 232,
+                        237, 221, 215, 77, 188, 53, 126, 115, 182, 105, 82, 11, 58, 25, 207, 156,
+                        69
+                    ]
+                },
+                Bootnode::Parsed {
+                    multiaddr: "/dns/example.com/tcp/30333".into(),
+                    peer_id: vec![
+                        0, 36, 8, 1, 18, 32, 59, 106, 39, 188, 206, 182, 164, 45, 98, 163, 168,
+                        208, 42, 111, 13, 115, 101, 50, 21, 119, 29, 226, 67, 166, 58, 192, 72,
+                        161, 139, 89, 218, 41
+                    ]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn non_raw_genesis_detected() {
+        let spec = ChainSpec::from_json_bytes(
+            r#"{
+                "name": "Test",
+                "id": "test",
+                "bootNodes": [],
+                "genesis": { "runtime": { "system": { "code": "0x00" } } }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(spec.genesis_kind(), GenesisKind::Runtime);
+        assert!(spec.genesis_storage().into_genesis_items().is_none());
+        assert!(spec.genesis_storage().into_trie_root_hash().is_none());
+        assert!(matches!(
+            spec.as_chain_information(),
+            Err(FromGenesisStorageError::UnsupportedGenesisKind)
+        ));
+    }
+
+    #[test]
+    fn runtime_genesis_detected() {
+        let spec = ChainSpec::from_json_bytes(
+            r#"{
+                "name": "Test",
+                "id": "test",
+                "bootNodes": [],
+                "genesis": { "runtimeGenesis": { "code": "0x00", "patch": { "balances": {} } } }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(spec.genesis_kind(), GenesisKind::RuntimeGenesis);
+        assert!(spec.genesis_storage().into_genesis_items().is_none());
+        assert!(matches!(
+            spec.as_chain_information(),
+            Err(FromGenesisStorageError::UnsupportedGenesisKind)
+        ));
+        assert_eq!(
+            spec.validate(),
+            Err(vec![SpecProblem::UnsupportedGenesisKind])
+        );
+
+        // `patch` and `config` are mutually exclusive, and one of them must be present.
+        for genesis in [
+            r#"{ "code": "0x00", "patch": {}, "config": {} }"#,
+            r#"{ "code": "0x00" }"#,
+        ] {
+            let error = ChainSpec::from_json_bytes(format!(
+                r#"{{
+                    "name": "Test",
+                    "id": "test",
+                    "bootNodes": [],
+                    "genesis": {{ "runtimeGenesis": {} }}
+                }}"#,
+                genesis
+            ))
+            .err()
+            .unwrap();
+            assert!(error.is_runtime_genesis_config_conflict());
+        }
+    }
+
+    #[test]
+    fn empty_genesis_storage_trie_root() {
+        let spec = ChainSpec::from_json_bytes(
+            r#"{
+                "name": "Test",
+                "id": "test",
+                "bootNodes": [],
+                "genesis": { "raw": { "top": {}, "childrenDefault": {} } }
+            }"#,
+        )
+        .unwrap();
+
+        let items = spec.genesis_storage().into_genesis_items().unwrap();
+        assert_eq!(items.iter().count(), 0);
+
+        for state_version in [trie::TrieEntryVersion::V0, trie::TrieEntryVersion::V1] {
+            assert_eq!(
+                items.trie_root_hash(state_version),
+                [
+                    0x03, 0x17, 0x0a, 0x2e, 0x75, 0x97, 0xb7, 0xb7, 0xe3, 0xd8, 0x4c, 0x05, 0x39,
+                    0x1d, 0x13, 0x9a, 0x62, 0xb1, 0x57, 0xe7, 0x87, 0x86, 0xd8, 0xc0, 0x82, 0xf2,
+                    0x9d, 0xcf, 0x4c, 0x11, 0x13, 0x14
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn genesis_storage_selective_reads() {
+        let spec = &include_bytes!("chain_spec/example.json")[..];
+        let specs = ChainSpec::from_json_bytes(&spec).unwrap();
+        let items = specs.genesis_storage().into_genesis_items().unwrap();
+
+        assert_eq!(specs.genesis_storage_value(b":code"), items.value(b":code"));
+        assert!(specs.genesis_storage_value(b":code").is_some());
+        assert!(specs.genesis_storage_value(b"foo").is_none());
+
+        for prefix in [
+            &b""[..],
+            &b":"[..],
+            &b":code"[..],
+            &[0x1a, 0x73][..],
+            &[0xff; 3][..],
+        ] {
+            assert_eq!(
+                specs.genesis_storage_prefix(prefix).collect::<Vec<_>>(),
+                items
+                    .iter()
+                    .filter(|(k, _)| k.starts_with(prefix))
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn validate_reports_invalid_bootnode() {
+        let spec = &include_bytes!("chain_spec/example.json")[..];
+        let specs = ChainSpec::from_json_bytes(&spec).unwrap();
+        assert_eq!(
+            specs.validate(),
+            Err(vec![SpecProblem::InvalidBootnode(
+                "/some/wrong/multiaddress".into()
+            )])
+        );
+    }
+
+    #[test]
+    fn runtime_genesis_patch_without_genesis_builder() {
+        // The runtime doesn't implement the `GenesisBuilder` runtime API.
+        let spec = ChainSpec::from_json_bytes(format!(
+            r#"{{
+                "name": "Test",
+                "id": "test",
+                "bootNodes": [],
+                "genesis": {{
+                    "runtimeGenesis": {{
+                        "code": "0x{}",
+                        "patch": {{ "balances": {{ "balances": [] }} }}
+                    }}
+                }}
+            }}"#,
+            hex::encode(&include_bytes!("executor/vm/test-polkadot-runtime-v9160.wasm")[..])
+        ))
+        .unwrap();
+
+        assert_eq!(spec.genesis_kind(), GenesisKind::RuntimeGenesis);
+        assert!(matches!(
+            spec.build_runtime_genesis_storage(),
+            Err(BuildGenesisStorageError::VmStart(
+                host::StartErr::VirtualMachine(vm::StartErr::FunctionNotFound)
+            ))
+        ));
+    }
 }
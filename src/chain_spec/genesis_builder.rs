@@ -0,0 +1,194 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Building the genesis storage of a chain spec whose genesis is in the `runtimeGenesis` form.
+//!
+//! In this form, the chain spec contains the Wasm runtime code of the genesis block and a JSON
+//! document describing the genesis configuration. This document is either the complete
+//! configuration, or a patch to apply on top of the default configuration of the runtime. The
+//! genesis storage is then built by calling the `GenesisBuilder` runtime API, starting from an
+//! empty storage.
+
+use super::{BuildGenesisStorageError, GenesisStorageEntries};
+use crate::{
+    executor::{self, host, runtime_host, storage_diff},
+    util,
+};
+
+use alloc::{borrow::ToOwned as _, string::String, vec::Vec};
+use core::iter;
+
+/// See [`build_genesis_storage`].
+pub(super) enum GenesisConfig<'a> {
+    /// Complete configuration, passed as-is to the runtime.
+    Full(&'a str),
+    /// Patch to apply on top of the configuration returned by
+    /// `GenesisBuilder_create_default_config`.
+    Patch(&'a str),
+}
+
+/// Builds the storage of the genesis block by executing the given runtime.
+///
+/// Returns the storage items ordered by key, including `:code`.
+pub(super) fn build_genesis_storage(
+    code: &[u8],
+    config: GenesisConfig,
+) -> Result<GenesisStorageEntries, BuildGenesisStorageError> {
+    let vm_prototype = host::HostVmPrototype::new(host::Config {
+        module: code,
+        heap_pages: executor::DEFAULT_HEAP_PAGES,
+        exec_hint: executor::vm::ExecHint::Oneshot,
+        allow_unresolved_imports: false,
+    })
+    .map_err(BuildGenesisStorageError::VmInitialization)?;
+
+    let (json_config, vm_prototype) = match config {
+        GenesisConfig::Full(config) => (config.as_bytes().to_vec(), vm_prototype),
+        GenesisConfig::Patch(patch) => {
+            let (output, _, vm_prototype) =
+                run(vm_prototype, "GenesisBuilder_create_default_config", &[])?;
+
+            // The output is a SCALE-encoded `Vec<u8>` containing the JSON configuration.
+            let mut default_config = util::nom_bytes_decode::<nom::error::Error<&[u8]>>(&output)
+                .ok()
+                .filter(|(rest, _)| rest.is_empty())
+                .and_then(|(_, json)| serde_json::from_slice::<serde_json::Value>(json).ok())
+                .ok_or(BuildGenesisStorageError::InvalidDefaultConfig)?;
+
+            // The patch has already been verified to be valid JSON when parsing the chain spec.
+            let patch = serde_json::from_str::<serde_json::Value>(patch).unwrap();
+            merge_json(&mut default_config, patch);
+
+            (serde_json::to_vec(&default_config).unwrap(), vm_prototype)
+        }
+    };
+
+    let mut parameter = util::encode_scale_compact_usize(json_config.len())
+        .as_ref()
+        .to_vec();
+    parameter.extend_from_slice(&json_config);
+
+    let (output, storage_changes, _) =
+        run(vm_prototype, "GenesisBuilder_build_config", &parameter)?;
+
+    // The output is a SCALE-encoded `Result<(), String>`.
+    match output.split_first() {
+        Some((0, [])) => {}
+        Some((1, error)) => {
+            let error = util::nom_bytes_decode::<nom::error::Error<&[u8]>>(error)
+                .map_err(|_| BuildGenesisStorageError::InvalidBuildConfigOutput)?
+                .1;
+            return Err(BuildGenesisStorageError::BuildConfig(
+                String::from_utf8_lossy(error).into_owned(),
+            ));
+        }
+        _ => return Err(BuildGenesisStorageError::InvalidBuildConfigOutput),
+    }
+
+    // The runtime code isn't part of the configuration and is added separately.
+    let mut storage = storage_changes
+        .diff_iter_unordered()
+        .filter(|(key, _)| *key != b":code")
+        .filter_map(|(key, value)| Some((key.to_owned(), value?.to_owned())))
+        .collect::<Vec<_>>();
+    storage.push((b":code".to_vec(), code.to_owned()));
+    storage.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(storage)
+}
+
+/// Calls the given runtime function against an empty storage. Returns the output of the
+/// function and the storage changes it has performed.
+fn run(
+    vm_prototype: host::HostVmPrototype,
+    function_to_call: &str,
+    parameter: &[u8],
+) -> Result<(Vec<u8>, storage_diff::StorageDiff, host::HostVmPrototype), BuildGenesisStorageError> {
+    let mut vm = runtime_host::run(runtime_host::Config {
+        virtual_machine: vm_prototype,
+        function_to_call,
+        parameter: iter::once(parameter),
+        top_trie_root_calculation_cache: None,
+        storage_top_trie_changes: storage_diff::StorageDiff::empty(),
+        offchain_storage_changes: storage_diff::StorageDiff::empty(),
+    })
+    .map_err(|(err, _)| BuildGenesisStorageError::VmStart(err))?;
+
+    loop {
+        vm = match vm {
+            runtime_host::RuntimeHostVm::Finished(Ok(success)) => {
+                let output = success.virtual_machine.value().as_ref().to_vec();
+                return Ok((
+                    output,
+                    success.storage_top_trie_changes,
+                    success.virtual_machine.into_prototype(),
+                ));
+            }
+            runtime_host::RuntimeHostVm::Finished(Err(err)) => {
+                return Err(BuildGenesisStorageError::Execution(err.detail))
+            }
+            // The storage is empty, apart from the changes that the runtime has performed and
+            // that are tracked by `runtime_host`.
+            runtime_host::RuntimeHostVm::StorageGet(get) => {
+                get.inject_value(None::<iter::Empty<&[u8]>>)
+            }
+            runtime_host::RuntimeHostVm::PrefixKeys(prefix) => {
+                prefix.inject_keys_ordered(iter::empty::<&[u8]>())
+            }
+            runtime_host::RuntimeHostVm::NextKey(next) => next.inject_key(None::<&[u8]>),
+        }
+    }
+}
+
+/// Applies `patch` on top of `base`. Objects are merged recursively, while any other value of
+/// the patch overwrites the corresponding value of `base`.
+fn merge_json(base: &mut serde_json::Value, patch: serde_json::Value) {
+    match (base, patch) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(patch)) => {
+            for (key, value) in patch {
+                merge_json(base.entry(key).or_insert(serde_json::Value::Null), value);
+            }
+        }
+        (base, patch) => *base = patch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn merge_json() {
+        let mut base = serde_json::json!({
+            "balances": { "balances": [["alice", 1]], "devAccounts": null },
+            "sudo": { "key": "alice" }
+        });
+        super::merge_json(
+            &mut base,
+            serde_json::json!({
+                "balances": { "balances": [["bob", 2]] },
+                "staking": { "validatorCount": 2 }
+            }),
+        );
+
+        assert_eq!(
+            base,
+            serde_json::json!({
+                "balances": { "balances": [["bob", 2]], "devAccounts": null },
+                "sudo": { "key": "alice" },
+                "staking": { "validatorCount": 2 }
+            })
+        );
+    }
+}
@@ -106,6 +106,26 @@ impl Default for ChainType {
 pub(super) enum Genesis {
     Raw(RawGenesis),
     StateRootHash(HashHexString),
+    /// Non-raw form of the genesis, as generated by Substrate when `--raw` isn't passed. Its
+    /// content is runtime-specific and isn't interpreted.
+    Runtime(Box<serde_json::value::RawValue>),
+    /// Genesis described by the Wasm runtime code and a JSON configuration, from which the
+    /// genesis storage is built by executing the runtime.
+    RuntimeGenesis(RuntimeGenesis),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub(super) struct RuntimeGenesis {
+    pub(super) code: HexString,
+    /// Patch to apply on top of the default genesis configuration of the runtime. Mutually
+    /// exclusive with [`RuntimeGenesis::config`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) patch: Option<Box<serde_json::value::RawValue>>,
+    /// Full genesis configuration. Mutually exclusive with [`RuntimeGenesis::patch`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) config: Option<Box<serde_json::value::RawValue>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
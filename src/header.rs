@@ -636,7 +636,8 @@ impl<'a> DigestRef<'a> {
                     debug_assert!(babe_seal_index.is_none());
                 }
                 DigestItem::UnknownSeal { .. } => return Err(Error::SealIsntLastItem),
-                DigestItem::UnknownConsensus { .. }
+                DigestItem::BeefyConsensus(_)
+                | DigestItem::UnknownConsensus { .. }
                 | DigestItem::UnknownPreRuntime { .. }
                 | DigestItem::Other(..) => {}
             }
@@ -733,7 +734,8 @@ impl<'a> DigestRef<'a> {
                     debug_assert!(babe_seal_index.is_none());
                 }
                 DigestItemRef::UnknownSeal { .. } => return Err(Error::SealIsntLastItem),
-                DigestItemRef::UnknownConsensus { .. }
+                DigestItemRef::BeefyConsensus(_)
+                | DigestItemRef::UnknownConsensus { .. }
                 | DigestItemRef::UnknownPreRuntime { .. }
                 | DigestItemRef::Other { .. } => {}
             }
@@ -966,6 +968,11 @@ pub enum DigestItemRef<'a> {
 
     GrandpaConsensus(GrandpaConsensusLogRef<'a>),
 
+    /// Consensus item of the BEEFY protocol. Despite being a consensus item, BEEFY isn't a
+    /// consensus engine and this item has no influence on the validity of the block. Smoldot
+    /// doesn't interpret the content of the log item.
+    BeefyConsensus(&'a [u8]),
+
     /// Consensus item with an engine that hasn't been recognized.
     UnknownConsensus {
         /// Name of the consensus engine.
@@ -1113,6 +1120,13 @@ impl<'a> DigestItemRef<'a> {
                 ret.extend_from_slice(seal);
                 iter::once(ret)
             }
+            DigestItemRef::BeefyConsensus(opaque) => {
+                let mut ret = vec![4];
+                ret.extend_from_slice(b"BEEF");
+                ret.extend_from_slice(util::encode_scale_compact_usize(opaque.len()).as_ref());
+                ret.extend_from_slice(opaque);
+                iter::once(ret)
+            }
             DigestItemRef::UnknownConsensus { engine, opaque } => {
                 let mut ret = vec![4];
                 ret.extend_from_slice(&engine);
@@ -1155,6 +1169,7 @@ impl<'a> From<&'a DigestItem> for DigestItemRef<'a> {
             DigestItem::BabeConsensus(v) => DigestItemRef::BabeConsensus(v.into()),
             DigestItem::BabeSeal(v) => DigestItemRef::BabeSeal(v),
             DigestItem::GrandpaConsensus(v) => DigestItemRef::GrandpaConsensus(v.into()),
+            DigestItem::BeefyConsensus(v) => DigestItemRef::BeefyConsensus(v),
             DigestItem::UnknownConsensus { engine, opaque } => DigestItemRef::UnknownConsensus {
                 engine: *engine,
                 opaque: &*opaque,
@@ -1188,6 +1203,9 @@ pub enum DigestItem {
 
     GrandpaConsensus(GrandpaConsensusLog),
 
+    /// See [`DigestItemRef::BeefyConsensus`].
+    BeefyConsensus(Vec<u8>),
+
     /// See [`DigestItemRef::UnknownConsensus`].
     UnknownConsensus {
         /// Name of the consensus engine.
@@ -1236,6 +1254,7 @@ impl<'a> From<DigestItemRef<'a>> for DigestItem {
                 DigestItem::BabeSeal(seal)
             }
             DigestItemRef::GrandpaConsensus(v) => DigestItem::GrandpaConsensus(v.into()),
+            DigestItemRef::BeefyConsensus(v) => DigestItem::BeefyConsensus(v.to_vec()),
             DigestItemRef::UnknownConsensus { engine, opaque } => DigestItem::UnknownConsensus {
                 opaque: opaque.to_vec(),
                 engine,
@@ -1318,6 +1337,7 @@ fn decode_item_from_parts<'a>(
         (4, b"FRNK") => {
             DigestItemRef::GrandpaConsensus(GrandpaConsensusLogRef::from_slice(content)?)
         }
+        (4, b"BEEF") => DigestItemRef::BeefyConsensus(content),
         (4, engine) => DigestItemRef::UnknownConsensus {
             engine: *engine,
             opaque: content,
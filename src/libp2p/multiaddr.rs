@@ -547,6 +547,7 @@ mod tests {
         check_valid(
             "/ip4/127.0.0.1/tcp/30333/p2p/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN",
         );
+        check_valid("/ip4/127.0.0.1/tcp/30333/p2p/QmSoLnSGccFuZQJzRadHn95W2CrSFmZuTdDWP8HXaHca9z");
         check_valid("/ip6/::/udp/30333");
         check_valid("/ip6/::1/udp/30333/tls");
         check_valid("/ip6/::1/udp/30333/tls/ws");
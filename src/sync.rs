@@ -28,6 +28,10 @@
 //!
 //! The [`all`] module represents a good combination of all syncing strategies and should be the
 //! default choice for most clients.
+//! It wraps the other strategies behind a single API, and in particular exposes a single
+//! [`all::ProcessOne`] enum regardless of the strategy being used under the hood. Code that
+//! wants to be able to switch between strategies should rely on it rather than handle the
+//! outcomes of each sub-module separately.
 //!
 //! # About safety
 //!
@@ -53,3 +57,9 @@ pub mod all_forks;
 pub mod optimistic;
 pub mod para;
 pub mod warp_sync;
+
+mod finalized_checkpoint;
+mod health;
+#[cfg(test)]
+mod test_utils;
+mod verification_rate;
@@ -45,6 +45,7 @@ use core::{
     ops,
     time::Duration,
 };
+use rand::{Rng as _, SeedableRng as _};
 
 pub use warp_sync::WarpSyncFragment;
 
@@ -87,6 +88,54 @@ pub struct Config {
     /// See [`all_forks::Config::max_requests_per_block`] for more information.
     pub max_requests_per_block: NonZeroU32,
 
+    /// Maximum number of simultaneous pending requests meant to resolve forks.
+    ///
+    /// See [`all_forks::Config::max_fork_ancestry_searches`] for more information.
+    pub max_fork_ancestry_searches: NonZeroU32,
+
+    /// Number of times in a row a source can announce an old block as its best block before it
+    /// is considered as misbehaving.
+    ///
+    /// See [`all_forks::Config::max_consecutive_too_old_best_announces`] for more information.
+    pub max_consecutive_too_old_best_announces: Option<NonZeroU32>,
+
+    /// Maximum number of GrandPa commits that can't be verified yet and that are stored for
+    /// later.
+    ///
+    /// See [`all_forks::Config::max_pending_grandpa_commits`] for more information.
+    pub max_pending_grandpa_commits: usize,
+
+    /// Maximum size, in bytes, of a SCALE-encoded block header received in a response to a
+    /// request.
+    ///
+    /// See [`all_forks::Config::max_header_size`] for more information.
+    pub max_header_size: usize,
+
+    /// If `Some`, blocks more than this number of blocks above the finalized block aren't
+    /// verified until the finalized block catches up.
+    ///
+    /// See [`all_forks::Config::max_blocks_ahead_of_finalized`] and
+    /// [`optimistic::Config::max_blocks_ahead_of_finalized`] for more information.
+    pub max_blocks_ahead_of_finalized: Option<NonZeroU64>,
+
+    /// Number of consecutive failed requests after which a source is banned.
+    ///
+    /// See [`optimistic::Config::request_failures_before_ban`] for more information.
+    pub request_failures_before_ban: NonZeroU32,
+
+    /// Maximum duration by which the slot of a block is allowed to be in the future compared to
+    /// the current time, in order to tolerate clock drifts.
+    ///
+    /// See [`all_forks::Config::aura_max_future_drift`] and
+    /// [`optimistic::Config::aura_max_future_drift`] for more information.
+    pub aura_max_future_drift: Duration,
+
+    /// Number of ongoing requests from which a source is considered as saturated, in which case
+    /// its announces of unknown blocks are discarded.
+    ///
+    /// See [`all_forks::Config::source_saturation_threshold`] for more information.
+    pub source_saturation_threshold: Option<NonZeroU32>,
+
     /// Number of blocks to download ahead of the best verified block.
     ///
     /// Whenever the latest best block is updated, the state machine will start block
@@ -98,6 +147,19 @@ pub struct Config {
     /// block requests.
     pub download_ahead_blocks: NonZeroU32,
 
+    /// Pre-allocated capacity for the queue of blocks being downloaded or waiting to be
+    /// verified.
+    ///
+    /// See [`optimistic::Config::verification_queue_capacity`] for more information.
+    pub verification_queue_capacity: usize,
+
+    /// Seed used to determine the order in which the sources are considered when deciding
+    /// which requests to start.
+    ///
+    /// See [`all_forks::Config::randomness_seed`] and [`optimistic::Config::randomness_seed`]
+    /// for more information.
+    pub randomness_seed: [u8; 32],
+
     /// If `Some`, the block bodies and storage are also synchronized. Contains the extra
     /// configuration.
     pub full: Option<ConfigFull>,
@@ -108,6 +170,13 @@ pub struct Config {
 pub struct ConfigFull {
     /// Compiled runtime code of the finalized block.
     pub finalized_runtime: host::HostVmPrototype,
+
+    /// See [`optimistic::ConfigFull::extra_runtime_environment_keys`].
+    ///
+    /// Whenever a block modifies one of these keys, [`AllSync::process_one`] returns
+    /// [`ProcessOne::BestRuntimeOutdated`] until a rebuilt runtime is passed to
+    /// [`BestRuntimeOutdated::set_best_runtime`].
+    pub extra_runtime_environment_keys: Vec<Vec<u8>>,
 }
 
 /// Identifier for a source in the [`AllSync`].
@@ -131,6 +200,7 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
     /// Initializes a new state machine.
     pub fn new(config: Config) -> Self {
         let is_full = config.full.is_some();
+        let mut randomness = rand_chacha::ChaCha20Rng::from_seed(config.randomness_seed);
 
         AllSync {
             inner: if let Some(config_full) = config.full {
@@ -141,8 +211,15 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                         sources_capacity: config.sources_capacity,
                         blocks_capacity: config.blocks_capacity,
                         download_ahead_blocks: config.download_ahead_blocks,
+                        verification_queue_capacity: config.verification_queue_capacity,
+                        max_blocks_ahead_of_finalized: config.max_blocks_ahead_of_finalized,
+                        request_failures_before_ban: config.request_failures_before_ban,
+                        aura_max_future_drift: config.aura_max_future_drift,
+                        randomness_seed: randomness.sample(rand::distributions::Standard),
                         full: Some(optimistic::ConfigFull {
-                            finalized_runtime: config_full.finalized_runtime,
+                            finalized_runtime: Some(config_full.finalized_runtime),
+                            extra_runtime_environment_keys: config_full
+                                .extra_runtime_environment_keys,
                         }),
                     }),
                 }
@@ -163,6 +240,11 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                                 sources_capacity: config.sources_capacity,
                                 blocks_capacity: config.blocks_capacity,
                                 download_ahead_blocks: config.download_ahead_blocks,
+                                verification_queue_capacity: config.verification_queue_capacity,
+                                max_blocks_ahead_of_finalized: config.max_blocks_ahead_of_finalized,
+                                request_failures_before_ban: config.request_failures_before_ban,
+                                aura_max_future_drift: config.aura_max_future_drift,
+                                randomness_seed: randomness.sample(rand::distributions::Standard),
                                 full: None,
                             }),
                         }
@@ -177,8 +259,17 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                 blocks_capacity: config.blocks_capacity,
                 max_disjoint_headers: config.max_disjoint_headers,
                 max_requests_per_block: config.max_requests_per_block,
+                max_fork_ancestry_searches: config.max_fork_ancestry_searches,
+                max_consecutive_too_old_best_announces: config
+                    .max_consecutive_too_old_best_announces,
+                max_pending_grandpa_commits: config.max_pending_grandpa_commits,
+                max_header_size: config.max_header_size,
+                max_blocks_ahead_of_finalized: config.max_blocks_ahead_of_finalized,
+                source_saturation_threshold: config.source_saturation_threshold,
                 block_number_bytes: config.block_number_bytes,
                 allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+                aura_max_future_drift: config.aura_max_future_drift,
+                randomness,
             },
         }
     }
@@ -425,6 +516,7 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                         best_block_hash,
                     },
                     best_block_number,
+                    false,
                 );
                 outer_source_id_entry.insert(SourceMapping::Optimistic(source_id));
 
@@ -1010,29 +1102,43 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                 inner: warp_sync::InProgressWarpSync::Verifier(_),
             } => ProcessOne::VerifyWarpSyncFragment(WarpSyncFragmentVerify { inner: self }),
             AllSyncInner::GrandpaWarpSync { .. } => ProcessOne::AllSync(self),
-            AllSyncInner::AllForks(sync) => match sync.process_one() {
-                all_forks::ProcessOne::AllSync { sync } => {
-                    self.inner = AllSyncInner::AllForks(sync);
-                    ProcessOne::AllSync(self)
-                }
-                all_forks::ProcessOne::HeaderVerify(verify) => {
-                    ProcessOne::VerifyHeader(HeaderVerify {
-                        inner: HeaderVerifyInner::AllForks(verify),
-                        shared: self.shared,
-                    })
-                }
-                all_forks::ProcessOne::FinalityProofVerify(verify) => {
-                    ProcessOne::VerifyFinalityProof(FinalityProofVerify {
-                        inner: FinalityProofVerifyInner::AllForks(verify),
-                        shared: self.shared,
-                    })
+            AllSyncInner::AllForks(mut sync) => loop {
+                match sync.process_one() {
+                    // Processing queued block announces doesn't require any action from the API
+                    // user. Continue processing until something else is ready.
+                    all_forks::ProcessOne::BlockAnnouncesProcessed { sync: s } => sync = s,
+                    all_forks::ProcessOne::AllSync { sync }
+                    | all_forks::ProcessOne::WaitingForFinality { sync } => {
+                        self.inner = AllSyncInner::AllForks(sync);
+                        break ProcessOne::AllSync(self);
+                    }
+                    all_forks::ProcessOne::HeaderVerify(verify) => {
+                        break ProcessOne::VerifyHeader(HeaderVerify {
+                            inner: HeaderVerifyInner::AllForks(verify),
+                            shared: self.shared,
+                        })
+                    }
+                    all_forks::ProcessOne::FinalityProofVerify(verify) => {
+                        break ProcessOne::VerifyFinalityProof(FinalityProofVerify {
+                            inner: FinalityProofVerifyInner::AllForks(verify),
+                            shared: self.shared,
+                        })
+                    }
                 }
             },
             AllSyncInner::Optimistic { inner } => match inner.process_one() {
-                optimistic::ProcessOne::Idle { sync } => {
+                optimistic::ProcessOne::Idle { sync }
+                | optimistic::ProcessOne::WaitingForFinality { sync } => {
                     self.inner = AllSyncInner::Optimistic { inner: sync };
                     ProcessOne::AllSync(self)
                 }
+                // The runtime of the finalized block is always passed at initialization, and the
+                // optimistic sync never discards it afterwards.
+                optimistic::ProcessOne::FinalizedRuntimeMissing { .. } => unreachable!(),
+                optimistic::ProcessOne::BestRuntimeOutdated { sync } => {
+                    self.inner = AllSyncInner::Optimistic { inner: sync };
+                    ProcessOne::BestRuntimeOutdated(BestRuntimeOutdated { inner: self })
+                }
                 optimistic::ProcessOne::VerifyBlock(inner) => {
                     ProcessOne::VerifyBodyHeader(HeaderBodyVerify {
                         inner: HeaderBodyVerifyInner::Optimistic(inner),
@@ -1051,11 +1157,15 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
     }
 
     /// Injects a block announcement made by a source into the state machine.
+    ///
+    /// `now_from_unix_epoch` must be the current time. It is used in order to track how long
+    /// ago the best block of each source has last been updated.
     pub fn block_announce(
         &mut self,
         source_id: SourceId,
         announced_scale_encoded_header: Vec<u8>,
         is_best: bool,
+        now_from_unix_epoch: Duration,
     ) -> BlockAnnounceOutcome {
         let source_id = self.shared.sources.get(source_id.0).unwrap();
 
@@ -1065,9 +1175,11 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                     all_forks::BlockAnnounceOutcome::TooOld {
                         announce_block_height,
                         finalized_block_height,
+                        persistently_too_old,
                     } => BlockAnnounceOutcome::TooOld {
                         announce_block_height,
                         finalized_block_height,
+                        persistently_too_old,
                     },
                     all_forks::BlockAnnounceOutcome::Unknown(source_update) => {
                         source_update.insert_and_update_source(None);
@@ -1081,13 +1193,21 @@ impl<TRq, TSrc, TBl> AllSync<TRq, TSrc, TBl> {
                     all_forks::BlockAnnounceOutcome::InvalidHeader(error) => {
                         BlockAnnounceOutcome::InvalidHeader(error)
                     }
+                    all_forks::BlockAnnounceOutcome::SelfParent => BlockAnnounceOutcome::SelfParent,
+                    all_forks::BlockAnnounceOutcome::SourceSaturated => {
+                        BlockAnnounceOutcome::Discarded
+                    }
                 }
             }
             (AllSyncInner::Optimistic { inner }, &SourceMapping::Optimistic(source_id)) => {
                 match header::decode(&announced_scale_encoded_header) {
                     Ok(header) => {
                         if is_best {
-                            inner.raise_source_best_block(source_id, header.number);
+                            inner.raise_source_best_block(
+                                source_id,
+                                header.number,
+                                now_from_unix_epoch,
+                            );
                             inner[source_id].best_block_hash =
                                 header::hash_from_scale_encoded_header(
                                     &announced_scale_encoded_header,
@@ -1655,6 +1775,9 @@ pub enum BlockAnnounceOutcome {
         announce_block_height: u64,
         /// Height of the currently finalized block.
         finalized_block_height: u64,
+        /// `true` if the source has announced too old best blocks too many times in a row. See
+        /// [`Config::max_consecutive_too_old_best_announces`].
+        persistently_too_old: bool,
     },
     /// Announced block has already been successfully verified and is part of the non-finalized
     /// chain.
@@ -1666,6 +1789,8 @@ pub enum BlockAnnounceOutcome {
     StoredForLater,
     /// Failed to decode announce header.
     InvalidHeader(header::Error),
+    /// Announced header indicates itself as its own parent. The source is misbehaving.
+    SelfParent,
 
     /// Header cannot be verified now and has been silently discarded.
     Discarded,
@@ -1728,6 +1853,10 @@ impl<'a, TRq, TSrc, TBl> BlockStorage<'a, TRq, TSrc, TBl> {
 /// Outcome of calling [`AllSync::process_one`].
 pub enum ProcessOne<TRq, TSrc, TBl> {
     /// No block ready to be processed.
+    ///
+    /// This is also the case if blocks are ready but too far ahead of the finalized block. See
+    /// [`Config::max_blocks_ahead_of_finalized`]. Processing resumes once a finality proof has
+    /// been verified.
     AllSync(AllSync<TRq, TSrc, TBl>),
 
     /// Ready to start verifying a header.
@@ -1741,6 +1870,11 @@ pub enum ProcessOne<TRq, TSrc, TBl> {
 
     /// Ready to start verifying a warp sync fragment.
     VerifyWarpSyncFragment(WarpSyncFragmentVerify<TRq, TSrc, TBl>),
+
+    /// The best block has modified one of the keys of
+    /// [`ConfigFull::extra_runtime_environment_keys`]. No block can be verified until the runtime
+    /// is rebuilt against the storage of the best block.
+    BestRuntimeOutdated(BestRuntimeOutdated<TRq, TSrc, TBl>),
 }
 
 /// Outcome of injecting a response in the [`AllSync`].
@@ -1888,6 +2022,10 @@ impl<TRq, TSrc, TBl> HeaderVerify<TRq, TSrc, TBl> {
                                 all_forks::HeaderVerifyError::ConsensusMismatch => {
                                     HeaderVerifyError::ConsensusMismatch
                                 }
+                                all_forks::HeaderVerifyError::TooFarInFuture {
+                                    slot,
+                                    max_allowed,
+                                } => HeaderVerifyError::TooFarInFuture { slot, max_allowed },
                             },
                             user_data,
                         }
@@ -1929,6 +2067,19 @@ pub enum HeaderVerifyError {
     /// The block verification has failed. The block is invalid and should be thrown away.
     #[display(fmt = "{}", _0)]
     VerificationFailed(verify::header_only::Error),
+    /// The slot of the block is further in the future than allowed by
+    /// [`Config::aura_max_future_drift`].
+    #[display(
+        fmt = "Slot number {} is too far in the future. Maximum allowed: {}",
+        slot,
+        max_allowed
+    )]
+    TooFarInFuture {
+        /// Slot number of the block.
+        slot: u64,
+        /// Highest slot number that is currently accepted.
+        max_allowed: u64,
+    },
 }
 
 // TODO: should be used by the optimistic syncing as well
@@ -1965,6 +2116,7 @@ impl<TRq, TSrc, TBl> FinalityProofVerify<TRq, TSrc, TBl> {
                         all_forks::FinalityProofVerifyOutcome::NewFinalized {
                             finalized_blocks,
                             updates_best_block,
+                            updates_finalized_runtime,
                         },
                     ) => (
                         sync,
@@ -1979,6 +2131,7 @@ impl<TRq, TSrc, TBl> FinalityProofVerify<TRq, TSrc, TBl> {
                                 })
                                 .collect(),
                             updates_best_block,
+                            updates_finalized_runtime,
                         },
                     ),
                     (sync, all_forks::FinalityProofVerifyOutcome::AlreadyFinalized) => {
@@ -1987,6 +2140,9 @@ impl<TRq, TSrc, TBl> FinalityProofVerify<TRq, TSrc, TBl> {
                     (sync, all_forks::FinalityProofVerifyOutcome::GrandpaCommitPending) => {
                         (sync, FinalityProofVerifyOutcome::GrandpaCommitPending)
                     }
+                    (sync, all_forks::FinalityProofVerifyOutcome::JustificationPending) => {
+                        (sync, FinalityProofVerifyOutcome::JustificationPending)
+                    }
                     (sync, all_forks::FinalityProofVerifyOutcome::JustificationError(error)) => {
                         (sync, FinalityProofVerifyOutcome::JustificationError(error))
                     }
@@ -2004,7 +2160,13 @@ impl<TRq, TSrc, TBl> FinalityProofVerify<TRq, TSrc, TBl> {
                 )
             }
             FinalityProofVerifyInner::Optimistic(verify) => match verify.perform() {
-                (inner, optimistic::JustificationVerification::Finalized { finalized_blocks }) => (
+                (
+                    inner,
+                    optimistic::JustificationVerification::Finalized {
+                        finalized_blocks,
+                        updates_finalized_runtime,
+                    },
+                ) => (
                     // TODO: transition to all_forks
                     AllSync {
                         inner: AllSyncInner::Optimistic { inner },
@@ -2025,6 +2187,7 @@ impl<TRq, TSrc, TBl> FinalityProofVerify<TRq, TSrc, TBl> {
                             })
                             .collect(),
                         updates_best_block: false,
+                        updates_finalized_runtime,
                     },
                 ),
                 (inner, optimistic::JustificationVerification::Reset { error, .. }) => (
@@ -2051,11 +2214,17 @@ pub enum FinalityProofVerifyOutcome<TBl> {
         /// This can happen if the previous best block isn't a descendant of the now finalized
         /// block.
         updates_best_block: bool,
+        /// If `true`, at least one of the finalized blocks has modified the runtime. The runtime
+        /// of the finalized block is thus different from the runtime of the previously-finalized
+        /// block.
+        updates_finalized_runtime: bool,
     },
     /// Finality proof concerns block that was already finalized.
     AlreadyFinalized,
     /// GrandPa commit cannot be verified yet and has been stored for later.
     GrandpaCommitPending,
+    /// Justification cannot be verified yet and has been stored for later.
+    JustificationPending,
     /// Problem while verifying justification.
     JustificationError(blocks_tree::JustificationVerifyError),
     /// Problem while verifying GrandPa commit.
@@ -2103,6 +2272,35 @@ impl<TRq, TSrc, TBl> WarpSyncFragmentVerify<TRq, TSrc, TBl> {
     }
 }
 
+/// See [`ProcessOne::BestRuntimeOutdated`].
+pub struct BestRuntimeOutdated<TRq, TSrc, TBl> {
+    inner: AllSync<TRq, TSrc, TBl>,
+}
+
+impl<TRq, TSrc, TBl> BestRuntimeOutdated<TRq, TSrc, TBl> {
+    /// Returns the height of the best block, whose runtime must be rebuilt.
+    pub fn best_block_number(&self) -> u64 {
+        self.inner.best_block_number()
+    }
+
+    /// Returns the hash of the best block, whose runtime must be rebuilt.
+    pub fn best_block_hash(&self) -> [u8; 32] {
+        self.inner.best_block_hash()
+    }
+
+    /// Sets the compiled runtime code of the best block, and yields back the [`AllSync`].
+    ///
+    /// See [`optimistic::OptimisticSync::set_best_runtime`].
+    pub fn set_best_runtime(mut self, runtime: host::HostVmPrototype) -> AllSync<TRq, TSrc, TBl> {
+        match &mut self.inner.inner {
+            AllSyncInner::Optimistic { inner } => inner.set_best_runtime(runtime),
+            _ => unreachable!(),
+        }
+
+        self.inner
+    }
+}
+
 pub struct HeaderBodyVerify<TRq, TSrc, TBl> {
     inner: HeaderBodyVerifyInner<TRq, TSrc, TBl>,
     shared: Shared<TRq>,
@@ -2196,6 +2394,19 @@ pub enum BlockVerificationError {
     /// Error while verifying a header and body.
     #[display(fmt = "{}", _0)]
     HeaderBodyError(blocks_tree::BodyVerifyError),
+    /// The slot of the block is further in the future than allowed by
+    /// [`Config::aura_max_future_drift`].
+    #[display(
+        fmt = "Slot number {} is too far in the future. Maximum allowed: {}",
+        slot,
+        max_allowed
+    )]
+    TooFarInFuture {
+        /// Slot number of the block.
+        slot: u64,
+        /// Highest slot number that is currently accepted.
+        max_allowed: u64,
+    },
 }
 
 impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
@@ -2234,6 +2445,9 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                     optimistic::ResetCause::HeaderBodyError(err) => {
                         BlockVerificationError::HeaderBodyError(err)
                     }
+                    optimistic::ResetCause::TooFarInFuture { slot, max_allowed } => {
+                        BlockVerificationError::TooFarInFuture { slot, max_allowed }
+                    }
                     optimistic::ResetCause::NonCanonical => BlockVerificationError::HeaderError(
                         // TODO: completely wrong error; unclear how to handle this
                         blocks_tree::HeaderVerifyError::VerificationFailed(
@@ -2411,10 +2625,27 @@ struct Shared<TRq> {
     max_disjoint_headers: usize,
     /// Value passed through [`Config::max_requests_per_block`].
     max_requests_per_block: NonZeroU32,
+    /// Value passed through [`Config::max_fork_ancestry_searches`].
+    max_fork_ancestry_searches: NonZeroU32,
+    /// Value passed through [`Config::max_consecutive_too_old_best_announces`].
+    max_consecutive_too_old_best_announces: Option<NonZeroU32>,
+    /// Value passed through [`Config::max_pending_grandpa_commits`].
+    max_pending_grandpa_commits: usize,
+    /// Value passed through [`Config::max_header_size`].
+    max_header_size: usize,
+    /// Value passed through [`Config::max_blocks_ahead_of_finalized`].
+    max_blocks_ahead_of_finalized: Option<NonZeroU64>,
+    /// Value passed through [`Config::source_saturation_threshold`].
+    source_saturation_threshold: Option<NonZeroU32>,
     /// Value passed through [`Config::block_number_bytes`].
     block_number_bytes: usize,
     /// Value passed through [`Config::allow_unknown_consensus_engines`].
     allow_unknown_consensus_engines: bool,
+    /// Value passed through [`Config::aura_max_future_drift`].
+    aura_max_future_drift: Duration,
+    /// Generator for the seeds passed to the inner state machines. Seeded with
+    /// [`Config::randomness_seed`].
+    randomness: rand_chacha::ChaCha20Rng,
 }
 
 impl<TRq> Shared<TRq> {
@@ -2436,9 +2667,20 @@ impl<TRq> Shared<TRq> {
             blocks_capacity: self.blocks_capacity,
             max_disjoint_headers: self.max_disjoint_headers,
             max_requests_per_block: self.max_requests_per_block,
+            max_fork_ancestry_searches: self.max_fork_ancestry_searches,
+            max_consecutive_too_old_best_announces: self.max_consecutive_too_old_best_announces,
+            max_pending_grandpa_commits: self.max_pending_grandpa_commits,
+            max_header_size: self.max_header_size,
+            max_blocks_ahead_of_finalized: self.max_blocks_ahead_of_finalized,
+            // `AllSync` passes block announces directly to `block_announce`.
+            max_queued_block_announces: 0,
+            source_saturation_threshold: self.source_saturation_threshold,
             allow_unknown_consensus_engines: self.allow_unknown_consensus_engines,
+            aura_max_future_drift: self.aura_max_future_drift,
             full: false,
             banned_blocks: iter::empty(), // TODO: not implemented, should be passed by config after the optimistic sync supports banned blocks too
+            banned_blocks_hasher: all_forks::BannedBlocksHasher::Fnv,
+            randomness_seed: self.randomness.sample(rand::distributions::Standard),
         });
 
         debug_assert!(self
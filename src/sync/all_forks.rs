@@ -84,18 +84,39 @@
 
 use crate::{
     chain::{blocks_tree, chain_information},
-    header, verify,
+    header,
+    util::SipHasherBuild,
+    verify,
 };
 
-use alloc::{borrow::ToOwned as _, vec::Vec};
-use core::{mem, num::NonZeroU32, ops, time::Duration};
+use alloc::{
+    borrow::ToOwned as _,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    vec,
+    vec::Vec,
+};
+use core::{
+    cmp, iter, mem,
+    num::{NonZeroU32, NonZeroU64},
+    ops,
+    time::Duration,
+};
+use rand::{Rng as _, SeedableRng as _};
 
 mod disjoint;
 mod pending_blocks;
 
 pub mod sources;
 
-pub use pending_blocks::{RequestId, RequestParams, SourceId};
+pub use super::finalized_checkpoint::CheckpointError;
+pub use super::health::{SyncHealth, SYNCED_THRESHOLD};
+pub use pending_blocks::{
+    RequestId, RequestParams, SourceId, UnverifiedBlockSnapshot, UnverifiedBlockState,
+};
+
+/// Maximum number of announces queued with [`AllForksSync::queue_block_announce`] that a single
+/// call to [`AllForksSync::process_one`] processes.
+const QUEUED_BLOCK_ANNOUNCES_BATCH: usize = 16;
 
 /// Configuration for the [`AllForksSync`].
 #[derive(Debug)]
@@ -116,6 +137,14 @@ pub struct Config<TBannedBlocksIter> {
     /// the network is bounded.
     pub allow_unknown_consensus_engines: bool,
 
+    /// Maximum duration by which the slot of a block is allowed to be in the future compared to
+    /// the current time, in order to tolerate clock drifts. Blocks further in the future fail to
+    /// verify with [`HeaderVerifyError::TooFarInFuture`].
+    ///
+    /// Only applies to chains that use the Aura consensus engine. The slot of blocks using the
+    /// Babe consensus engine isn't compared with the current time.
+    pub aura_max_future_drift: Duration,
+
     /// Pre-allocated capacity for the number of block sources.
     pub sources_capacity: usize,
 
@@ -158,6 +187,66 @@ pub struct Config<TBannedBlocksIter> {
     /// The higher the value, the more bandwidth is potentially wasted.
     pub max_requests_per_block: NonZeroU32,
 
+    /// Maximum number of simultaneous pending requests whose target is a block at a height
+    /// inferior or equal to the current best block.
+    ///
+    /// Such requests are typically ancestry searches meant to resolve forks, as opposed to
+    /// requests that follow the head of the chain. Since malicious sources can announce a large
+    /// number of blocks belonging to forks, this limit ensures that the requests targeting forks
+    /// can't crowd out the requests necessary to catch up with the head of the chain.
+    pub max_fork_ancestry_searches: NonZeroU32,
+
+    /// Number of times in a row a source can announce as its best block a block whose height is
+    /// inferior or equal to the finalized block before
+    /// [`BlockAnnounceOutcome::TooOld::persistently_too_old`] is set to `true`.
+    ///
+    /// A source occasionally announcing old blocks is most likely simply lagging behind, while a
+    /// source that persistently does so is suspicious. If `None`, announcing old blocks is
+    /// always silently accepted.
+    pub max_consecutive_too_old_best_announces: Option<NonZeroU32>,
+
+    /// Maximum number of GrandPa commits, across all sources, that can't be verified yet and
+    /// that are stored for later. See [`AllForksSync::grandpa_commit_message`].
+    ///
+    /// Pending commits are discarded once the finalized block reaches their target block. This
+    /// limit additionally guarantees that commits targeting blocks that are never received can't
+    /// accumulate.
+    pub max_pending_grandpa_commits: usize,
+
+    /// Maximum size, in bytes, of a SCALE-encoded block header provided through
+    /// [`FinishAncestrySearch::add_block`]. Larger headers are rejected without being decoded,
+    /// and the source that has provided them is flagged as misbehaving.
+    pub max_header_size: usize,
+
+    /// If `Some`, blocks whose height is more than this number of blocks above the height of
+    /// the finalized block aren't verified. Verifying headers is then paused until the finalized
+    /// block catches up, and [`AllForksSync::process_one`] returns
+    /// [`ProcessOne::WaitingForFinality`].
+    ///
+    /// This bounds the number of non-finalized blocks kept in memory on chains whose finality
+    /// is slow or stalled.
+    ///
+    /// > **Note**: Finality proofs are still accepted while verification is paused, but only
+    /// >           for blocks that have been verified. This value should be larger than the
+    /// >           typical distance between two finality proofs, otherwise syncing might stall.
+    pub max_blocks_ahead_of_finalized: Option<NonZeroU64>,
+
+    /// Maximum number of block announces that can be queued through
+    /// [`AllForksSync::queue_block_announce`] and that haven't been processed yet.
+    ///
+    /// When the queue is full, the least valuable announce is discarded. If `0`, all the
+    /// announces passed to [`AllForksSync::queue_block_announce`] are discarded.
+    pub max_queued_block_announces: usize,
+
+    /// If `Some`, a source with at least this number of ongoing requests is considered as
+    /// saturated. When a saturated source announces a block that is unknown to the state
+    /// machine, the state machine only records that the source knows this block, and
+    /// [`AllForksSync::block_announce`] returns [`BlockAnnounceOutcome::SourceSaturated`].
+    ///
+    /// The block can later be inserted if it is announced again, by this source once it has
+    /// fewer ongoing requests or by another source.
+    pub source_saturation_threshold: Option<NonZeroU32>,
+
     /// If true, the block bodies and storage are also synchronized.
     pub full: bool,
 
@@ -167,6 +256,19 @@ pub struct Config<TBannedBlocksIter> {
     /// >           specification. It is part of the "trusted setup" of the node, in other words
     /// >           the information that is passed by the user and blindly assumed to be true.
     pub banned_blocks: TBannedBlocksIter,
+
+    /// Hashing algorithm used for the set of banned blocks. See [`BannedBlocksHasher`].
+    ///
+    /// [`BannedBlocksHasher::Fnv`] is a good default.
+    pub banned_blocks_hasher: BannedBlocksHasher,
+
+    /// Seed used to determine the order in which the sources are returned by
+    /// [`AllForksSync::desired_requests`]. For each block, the sources are ordered differently,
+    /// so that requests are spread between sources rather than always targeting the same source.
+    ///
+    /// Passing the same seed and performing the same operations always leads to the same
+    /// requests being returned, which can be useful for example for testing purposes.
+    pub randomness_seed: [u8; 32],
 }
 
 pub struct AllForksSync<TBl, TRq, TSrc> {
@@ -183,8 +285,173 @@ pub struct AllForksSync<TBl, TRq, TSrc> {
 struct Inner<TBl, TRq, TSrc> {
     blocks: pending_blocks::PendingBlocks<PendingBlock<TBl>, TRq, Source<TSrc>>,
 
+    /// See [`Config::randomness_seed`].
+    randomness_seed: [u8; 32],
+
     /// Same value as [`Config::banned_blocks`].
-    banned_blocks: hashbrown::HashSet<[u8; 32], fnv::FnvBuildHasher>,
+    banned_blocks: BannedBlocks,
+
+    /// Public keys of the authorities found to equivocate. See
+    /// [`AllForksSync::equivocating_authorities`].
+    equivocating_authorities: hashbrown::HashSet<[u8; 32], fnv::FnvBuildHasher>,
+
+    /// See [`Config::max_fork_ancestry_searches`].
+    /// Since it is always compared with `usize`s, converted to `usize` ahead of time.
+    max_fork_ancestry_searches: usize,
+
+    /// If `true`, [`AllForksSync::desired_requests`] doesn't return anything. See
+    /// [`AllForksSync::set_paused`].
+    paused: bool,
+
+    /// See [`Config::max_consecutive_too_old_best_announces`].
+    max_consecutive_too_old_best_announces: Option<NonZeroU32>,
+
+    /// See [`Config::max_pending_grandpa_commits`].
+    max_pending_grandpa_commits: usize,
+
+    /// See [`Config::max_header_size`].
+    max_header_size: usize,
+
+    /// See [`Config::max_blocks_ahead_of_finalized`].
+    max_blocks_ahead_of_finalized: Option<NonZeroU64>,
+
+    /// Block announces queued with [`AllForksSync::queue_block_announce`] and that haven't been
+    /// processed yet, in the order in which they have been queued. Contains at most
+    /// [`Config::max_queued_block_announces`] entries.
+    queued_block_announces: VecDeque<QueuedBlockAnnounce<TBl>>,
+
+    /// See [`Config::max_queued_block_announces`].
+    max_queued_block_announces: usize,
+
+    /// Hashes of the blocks that have been announced together as siblings through
+    /// [`AllForksSync::queue_sibling_block_announces`], indexed by height. Only contains heights
+    /// strictly superior to the height of the finalized block, and sets of at least two blocks.
+    announced_siblings: BTreeMap<u64, BTreeSet<[u8; 32]>>,
+
+    /// See [`Config::source_saturation_threshold`].
+    source_saturation_threshold: Option<NonZeroU32>,
+
+    /// Time, as provided by the API user, when the most recent headers have been successfully
+    /// verified.
+    recent_verifications: super::verification_rate::VerificationRate,
+
+    /// Same values as in [`Config`]. Used in order to rebuild the chain in
+    /// [`AllForksSync::reset_to_finalized_checkpoint`].
+    block_number_bytes: usize,
+    blocks_capacity: usize,
+    allow_unknown_consensus_engines: bool,
+    aura_max_future_drift: Duration,
+
+    /// Justifications, in the form `(consensus_engine_id, justification)`, that have finalized
+    /// the current finalized block. Empty if the current finalized block hasn't been finalized
+    /// through a justification verified by this state machine.
+    finalized_block_justifications: Vec<([u8; 4], Vec<u8>)>,
+}
+
+impl<TBl, TRq, TSrc> Inner<TBl, TRq, TSrc> {
+    /// Discards the finality proofs that can't be verified yet and whose target is inferior or
+    /// equal to the given finalized block height, as they are now useless.
+    fn remove_obsolete_pending_finality_proofs(&mut self, finalized_height: u64) {
+        for source in self.blocks.sources_user_data_iter_mut() {
+            source
+                .pending_finality_proofs
+                .remove_up_to_height(finalized_height);
+        }
+    }
+
+    /// Updates the state after the finalized block has been updated following the verification
+    /// of a finality proof, and builds the corresponding outcome.
+    ///
+    /// `finalized_blocks` must not be empty.
+    fn on_finalized(
+        &mut self,
+        finalized_height: u64,
+        finalized_blocks: Vec<(header::Header, TBl)>,
+        updates_best_block: bool,
+        finalized_block_justifications: Vec<([u8; 4], Vec<u8>)>,
+    ) -> FinalityProofVerifyOutcome<TBl> {
+        let updates_finalized_runtime = finalized_blocks
+            .iter()
+            .any(|(h, _)| h.digest.has_runtime_environment_updated());
+        self.blocks
+            .set_finalized_block_height(finalized_blocks.last().unwrap().0.number);
+        self.finalized_block_justifications = finalized_block_justifications;
+        self.remove_obsolete_pending_finality_proofs(finalized_height);
+        self.remove_obsolete_announced_siblings(finalized_height);
+        FinalityProofVerifyOutcome::NewFinalized {
+            finalized_blocks,
+            updates_best_block,
+            updates_finalized_runtime,
+        }
+    }
+
+    /// Discards the siblings information concerning heights inferior or equal to the given
+    /// finalized block height.
+    fn remove_obsolete_announced_siblings(&mut self, finalized_height: u64) {
+        self.announced_siblings = self
+            .announced_siblings
+            .split_off(&finalized_height.saturating_add(1));
+    }
+}
+
+/// Hashing algorithm of the set of banned blocks. See [`Config::banned_blocks_hasher`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BannedBlocksHasher {
+    /// FNV hashing algorithm. Fast, but its output is predictable.
+    Fnv,
+    /// SipHash with a key derived from [`Config::randomness_seed`]. Slower than
+    /// [`BannedBlocksHasher::Fnv`], but resistant to an attacker trying to cause collisions.
+    Randomized,
+}
+
+/// See [`Inner::banned_blocks`].
+enum BannedBlocks {
+    Fnv(hashbrown::HashSet<[u8; 32], fnv::FnvBuildHasher>),
+    Randomized(hashbrown::HashSet<[u8; 32], SipHasherBuild>),
+}
+
+impl BannedBlocks {
+    fn contains(&self, hash: &[u8; 32]) -> bool {
+        match self {
+            BannedBlocks::Fnv(set) => set.contains(hash),
+            BannedBlocks::Randomized(set) => set.contains(hash),
+        }
+    }
+
+    fn insert(&mut self, hash: [u8; 32]) {
+        match self {
+            BannedBlocks::Fnv(set) => set.insert(hash),
+            BannedBlocks::Randomized(set) => set.insert(hash),
+        };
+    }
+
+    fn remove(&mut self, hash: &[u8; 32]) -> bool {
+        match self {
+            BannedBlocks::Fnv(set) => set.remove(hash),
+            BannedBlocks::Randomized(set) => set.remove(hash),
+        }
+    }
+
+    fn iter(&'_ self) -> impl Iterator<Item = &'_ [u8; 32]> + '_ {
+        match self {
+            BannedBlocks::Fnv(set) => either::Left(set.iter()),
+            BannedBlocks::Randomized(set) => either::Right(set.iter()),
+        }
+    }
+}
+
+/// See [`Inner::queued_block_announces`].
+struct QueuedBlockAnnounce<TBl> {
+    source_id: SourceId,
+    scale_encoded_header: Vec<u8>,
+    /// Height of the block, decoded from the header.
+    number: u64,
+    /// Hash of the parent of the block, decoded from the header.
+    parent_hash: [u8; 32],
+    is_best: bool,
+    /// Value passed to [`AnnouncedBlockUnknown::insert_and_update_source`] if the block turns
+    /// out to be unknown.
+    user_data: TBl,
 }
 
 struct PendingBlock<TBl> {
@@ -210,6 +477,17 @@ struct Source<TSrc> {
     /// and have been determined to not be verifiable right now.
     pending_finality_proofs: SourcePendingJustificationProofs,
 
+    /// `true` if this source has provided a block that is provably invalid. See
+    /// [`AllForksSync::source_misbehaved`].
+    misbehaved: bool,
+
+    /// Number of times in a row this source has announced as its best block a block whose
+    /// height is inferior or equal to the finalized block.
+    num_consecutive_too_old_best_announces: u32,
+
+    /// See [`AllForksSync::source_stats`].
+    stats: SourceStats,
+
     /// Opaque data chosen by the API user.
     user_data: TSrc,
 }
@@ -233,6 +511,19 @@ impl SourcePendingJustificationProofs {
         matches!(self, SourcePendingJustificationProofs::None)
     }
 
+    /// Returns `true` if calling [`SourcePendingJustificationProofs::insert`] with the given
+    /// target height would increase the number of proofs stored in this container, rather than
+    /// replace an existing proof or do nothing.
+    fn insert_adds_entry(&self, new_target_height: u64) -> bool {
+        match self {
+            SourcePendingJustificationProofs::None => true,
+            SourcePendingJustificationProofs::One { target_height, .. } => {
+                *target_height != new_target_height
+            }
+            SourcePendingJustificationProofs::Two { .. } => false,
+        }
+    }
+
     fn insert(&mut self, new_target_height: u64, new_proof: FinalityProofs) {
         // An empty list of justifications is an invalid state.
         debug_assert!(match &new_proof {
@@ -307,6 +598,75 @@ impl SourcePendingJustificationProofs {
         }
     }
 
+    /// Returns the number of finality proofs stored in this container.
+    fn num_proofs(&self) -> usize {
+        let num = |proof: &FinalityProofs| match proof {
+            FinalityProofs::GrandpaCommit(_) => 1,
+            FinalityProofs::Justifications(justifications) => justifications.len(),
+        };
+        match self {
+            SourcePendingJustificationProofs::None => 0,
+            SourcePendingJustificationProofs::One { proof, .. } => num(proof),
+            SourcePendingJustificationProofs::Two {
+                low_proof,
+                high_proof,
+                ..
+            } => num(low_proof) + num(high_proof),
+        }
+    }
+
+    /// Returns the number of GrandPa commits stored in this container.
+    fn num_grandpa_commits(&self) -> usize {
+        let is_commit = |proof: &FinalityProofs| matches!(proof, FinalityProofs::GrandpaCommit(_));
+        match self {
+            SourcePendingJustificationProofs::None => 0,
+            SourcePendingJustificationProofs::One { proof, .. } => usize::from(is_commit(proof)),
+            SourcePendingJustificationProofs::Two {
+                low_proof,
+                high_proof,
+                ..
+            } => usize::from(is_commit(low_proof)) + usize::from(is_commit(high_proof)),
+        }
+    }
+
+    /// Removes the proofs whose target is inferior or equal to the given height.
+    fn remove_up_to_height(&mut self, finalized_height: u64) {
+        match mem::replace(self, SourcePendingJustificationProofs::None) {
+            SourcePendingJustificationProofs::None => {}
+            SourcePendingJustificationProofs::One {
+                target_height,
+                proof,
+            } => {
+                if target_height > finalized_height {
+                    *self = SourcePendingJustificationProofs::One {
+                        target_height,
+                        proof,
+                    };
+                }
+            }
+            SourcePendingJustificationProofs::Two {
+                low_target_height,
+                low_proof,
+                high_target_height,
+                high_proof,
+            } => {
+                if low_target_height > finalized_height {
+                    *self = SourcePendingJustificationProofs::Two {
+                        low_target_height,
+                        low_proof,
+                        high_target_height,
+                        high_proof,
+                    };
+                } else if high_target_height > finalized_height {
+                    *self = SourcePendingJustificationProofs::One {
+                        target_height: high_target_height,
+                        proof: high_proof,
+                    };
+                }
+            }
+        }
+    }
+
     fn take_one(&mut self) -> Option<FinalityProof> {
         match mem::replace(self, SourcePendingJustificationProofs::None) {
             SourcePendingJustificationProofs::None => {
@@ -431,6 +791,7 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
             block_number_bytes: config.block_number_bytes,
             blocks_capacity: config.blocks_capacity,
             allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+            aura_max_future_drift: config.aura_max_future_drift,
         });
 
         Self {
@@ -442,8 +803,45 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
                     max_requests_per_block: config.max_requests_per_block,
                     sources_capacity: config.sources_capacity,
                     verify_bodies: config.full,
+                    randomness_seed: config.randomness_seed,
                 }),
-                banned_blocks: config.banned_blocks.collect(),
+                banned_blocks: match config.banned_blocks_hasher {
+                    BannedBlocksHasher::Fnv => BannedBlocks::Fnv(config.banned_blocks.collect()),
+                    BannedBlocksHasher::Randomized => {
+                        // A different stream than the one used by `pending_blocks` is used in
+                        // order for the keys to be independent.
+                        let mut randomness =
+                            rand_chacha::ChaCha20Rng::from_seed(config.randomness_seed);
+                        randomness.set_stream(1);
+                        let mut set = hashbrown::HashSet::with_hasher(SipHasherBuild::new(
+                            randomness.sample(rand::distributions::Standard),
+                        ));
+                        set.extend(config.banned_blocks);
+                        BannedBlocks::Randomized(set)
+                    }
+                },
+                equivocating_authorities: Default::default(),
+                randomness_seed: config.randomness_seed,
+                max_fork_ancestry_searches: usize::try_from(
+                    config.max_fork_ancestry_searches.get(),
+                )
+                .unwrap_or(usize::max_value()),
+                paused: false,
+                max_consecutive_too_old_best_announces: config
+                    .max_consecutive_too_old_best_announces,
+                max_pending_grandpa_commits: config.max_pending_grandpa_commits,
+                max_header_size: config.max_header_size,
+                max_blocks_ahead_of_finalized: config.max_blocks_ahead_of_finalized,
+                queued_block_announces: VecDeque::with_capacity(config.max_queued_block_announces),
+                max_queued_block_announces: config.max_queued_block_announces,
+                announced_siblings: BTreeMap::new(),
+                source_saturation_threshold: config.source_saturation_threshold,
+                recent_verifications: super::verification_rate::VerificationRate::new(),
+                block_number_bytes: config.block_number_bytes,
+                blocks_capacity: config.blocks_capacity,
+                allow_unknown_consensus_engines: config.allow_unknown_consensus_engines,
+                aura_max_future_drift: config.aura_max_future_drift,
+                finalized_block_justifications: Vec::new(),
             },
         }
     }
@@ -454,6 +852,137 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         self.chain.as_chain_information()
     }
 
+    /// Similar to [`AllForksSync::as_chain_information`], but returns an owned value.
+    ///
+    /// This is typically useful in order to initialize a new state machine.
+    pub fn finalized_chain_information_owned(&self) -> chain_information::ValidChainInformation {
+        self.as_chain_information().into()
+    }
+
+    /// Resets the state machine so that its finalized block is the one described by
+    /// `chain_information`, which must be the current finalized block or one of its ancestors.
+    ///
+    /// `ancestry` must yield the SCALE-encoded headers of the blocks between the finalized block
+    /// of `chain_information` (exclusive) and the current finalized block (exclusive), in
+    /// decreasing block number order. It is used to verify that the finalized block of
+    /// `chain_information` is indeed an ancestor of the current finalized block. On error, the
+    /// state machine is left untouched.
+    ///
+    /// All the verified non-finalized blocks are discarded and returned, in an order in which
+    /// parents are found before their children. Blocks that haven't been verified yet, sources,
+    /// and ongoing requests are kept. Pending finality proofs, announced siblings, and the
+    /// verification speed estimation are discarded.
+    ///
+    /// > **Warning**: This operation goes against the very definition of finality, and should
+    /// >              only be used in order to recover from a situation where blocks have been
+    /// >              wrongly finalized, for example because of a bug or of a compromised
+    /// >              trusted setup. Apart from its ancestry, the validity of
+    /// >              `chain_information` isn't verified.
+    pub fn reset_to_finalized_checkpoint(
+        &mut self,
+        chain_information: chain_information::ValidChainInformation,
+        ancestry: impl Iterator<Item = impl AsRef<[u8]>>,
+    ) -> Result<Vec<(header::Header, TBl)>, CheckpointError> {
+        super::finalized_checkpoint::verify_ancestry(
+            chain_information.as_ref().finalized_block_header,
+            self.chain.finalized_block_header(),
+            ancestry,
+        )?;
+
+        let new_finalized_height = chain_information.as_ref().finalized_block_header.number;
+
+        let former_chain = mem::replace(
+            &mut self.chain,
+            blocks_tree::NonFinalizedTree::new(blocks_tree::Config {
+                chain_information,
+                block_number_bytes: self.inner.block_number_bytes,
+                blocks_capacity: self.inner.blocks_capacity,
+                allow_unknown_consensus_engines: self.inner.allow_unknown_consensus_engines,
+                aura_max_future_drift: self.inner.aura_max_future_drift,
+            }),
+        );
+
+        self.inner
+            .blocks
+            .lower_finalized_block_height(new_finalized_height);
+        self.inner.finalized_block_justifications.clear();
+        self.inner.announced_siblings.clear();
+        self.inner.recent_verifications.clear();
+        for source in self.inner.blocks.sources_user_data_iter_mut() {
+            source.pending_finality_proofs = SourcePendingJustificationProofs::None;
+        }
+
+        Ok(former_chain
+            .into_iter_ancestry_order()
+            .map(|(_, block)| (block.header, block.user_data))
+            .collect())
+    }
+
+    /// Returns the height and hash of the lowest common ancestor of the two given blocks, passed
+    /// as `(height, hash)`.
+    ///
+    /// Each block must be either the finalized block or a verified non-finalized block. If one
+    /// block is an ancestor of the other, it is returned.
+    ///
+    /// Returns `None` if either block isn't tracked by the state machine, or if its height
+    /// doesn't match its hash.
+    pub fn common_ancestor(
+        &self,
+        a: (u64, [u8; 32]),
+        b: (u64, [u8; 32]),
+    ) -> Option<(u64, [u8; 32])> {
+        for (height, hash) in [a, b] {
+            let known_height = if hash == self.chain.finalized_block_hash() {
+                self.chain.finalized_block_header().number
+            } else {
+                self.chain
+                    .non_finalized_block_user_data(&hash)?
+                    .header
+                    .number
+            };
+            if known_height != height {
+                return None;
+            }
+        }
+
+        self.chain.common_ancestor(&a.1, &b.1)
+    }
+
+    /// Returns the justifications, in the form `(consensus_engine_id, justification)`, that
+    /// prove the finality of the current finalized block.
+    ///
+    /// The returned list is empty if the current finalized block hasn't been finalized by
+    /// verifying a justification. This is notably the case if it has been finalized through a
+    /// GrandPa commit message, or if it is the block the state machine has been initialized with.
+    pub fn finalized_block_justifications(&self) -> &[([u8; 4], Vec<u8>)] {
+        &self.inner.finalized_block_justifications
+    }
+
+    /// Returns the list of GrandPa authorities that must finalize the children of the latest
+    /// finalized block, or `None` if the chain doesn't use GrandPa for its finality.
+    pub fn current_grandpa_authorities(&self) -> Option<&[header::GrandpaAuthority]> {
+        match self.chain.as_chain_information().as_ref().finality {
+            chain_information::ChainInformationFinalityRef::Grandpa {
+                finalized_triggered_authorities,
+                ..
+            } => Some(finalized_triggered_authorities),
+            chain_information::ChainInformationFinalityRef::Outsourced => None,
+        }
+    }
+
+    /// Returns the identifier of the GrandPa authorities set returned by
+    /// [`AllForksSync::current_grandpa_authorities`], or `None` if the chain doesn't use
+    /// GrandPa for its finality.
+    pub fn current_authority_set_id(&self) -> Option<u64> {
+        match self.chain.as_chain_information().as_ref().finality {
+            chain_information::ChainInformationFinalityRef::Grandpa {
+                after_finalized_block_authorities_set_id,
+                ..
+            } => Some(after_finalized_block_authorities_set_id),
+            chain_information::ChainInformationFinalityRef::Outsourced => None,
+        }
+    }
+
     /// Returns the header of the finalized block.
     pub fn finalized_block_header(&self) -> header::HeaderRef {
         self.chain
@@ -486,6 +1015,178 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         self.chain.best_block_hash()
     }
 
+    /// Returns `true` if the block with the given height and hash is the current best block.
+    ///
+    /// Returns `false` if the hash matches the best block but the height doesn't.
+    pub fn is_best_block(&self, height: u64, hash: &[u8; 32]) -> bool {
+        self.chain.best_block_header().number == height && self.chain.best_block_hash() == *hash
+    }
+
+    /// Returns the number of blocks that have been verified but not finalized yet.
+    ///
+    /// This set of blocks isn't bounded by this state machine, but by the consensus and
+    /// finalization algorithms of the chain. See the module-level documentation.
+    pub fn num_verified_non_finalized_blocks(&self) -> usize {
+        self.chain.len()
+    }
+
+    /// Returns the number of blocks in the longest chain of verified non-finalized blocks, in
+    /// other words the difference between the height of the highest verified non-finalized
+    /// block and the height of the finalized block.
+    ///
+    /// This can be different from the distance between the best block and the finalized block,
+    /// as the highest block isn't necessarily the best block. Returns 0 if there isn't any
+    /// verified non-finalized block.
+    pub fn longest_non_finalized_chain_len(&self) -> u64 {
+        let finalized_height = self.chain.finalized_block_header().number;
+        self.chain
+            .iter_unordered()
+            .map(|header| header.number - finalized_height)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of blocks that can't be verified yet.
+    ///
+    /// This set of blocks is bounded by this state machine. See the module-level documentation.
+    pub fn num_unverified_blocks(&self) -> usize {
+        self.inner.blocks.num_unverified_blocks()
+    }
+
+    /// Returns the number of finality proofs (justifications and GrandPa commits) that have been
+    /// received from sources and are waiting to be verified, including the ones that have
+    /// already been checked and couldn't be verified yet because their target block isn't known.
+    ///
+    /// A number that keeps growing indicates that the blocks targeted by these finality proofs
+    /// aren't being downloaded.
+    pub fn num_pending_finality_proofs(&self) -> usize {
+        self.inner
+            .blocks
+            .sources()
+            .map(|source_id| {
+                let source = &self.inner.blocks[source_id];
+                source.unverified_finality_proofs.num_proofs()
+                    + source.pending_finality_proofs.num_proofs()
+            })
+            .sum()
+    }
+
+    /// Returns a snapshot of all the blocks that can't be verified yet, ordered by increasing
+    /// height.
+    ///
+    /// This is meant to be used for diagnostic purposes, for example in order to figure out why
+    /// a specific block never gets verified.
+    pub fn unverified_blocks_snapshot(
+        &'_ self,
+    ) -> impl Iterator<Item = UnverifiedBlockSnapshot> + '_ {
+        self.inner.blocks.unverified_blocks_snapshot()
+    }
+
+    /// Returns the list of hashes of the blocks that are banned, in no specific order.
+    ///
+    /// This includes the blocks passed through [`Config::banned_blocks`] and the blocks that
+    /// have been banned afterwards, for example because of an equivocation.
+    pub fn banned_blocks(&'_ self) -> impl Iterator<Item = &'_ [u8; 32]> + '_ {
+        self.inner.banned_blocks.iter()
+    }
+
+    /// Removes the given block from the list of banned blocks.
+    ///
+    /// If the block is in the list of unverified blocks, it and its descendants are no longer
+    /// considered as bad and can be downloaded and verified again. Note that descendants that
+    /// were considered as bad for a different reason (for example a verification failure) will
+    /// be verified again as well.
+    ///
+    /// Returns `false` if the block wasn't banned, in which case this function has no effect.
+    pub fn unban_block(&mut self, hash: &[u8; 32]) -> bool {
+        if !self.inner.banned_blocks.remove(hash) {
+            return false;
+        }
+
+        // The "bad" flag can't be cleared from unverified blocks. Instead, the block and its bad
+        // descendants are removed then inserted back, ordered by increasing height so that
+        // parents are always inserted before their children.
+        let mut revived = hashbrown::HashSet::<_, fnv::FnvBuildHasher>::default();
+        let to_revive = self
+            .inner
+            .blocks
+            .unverified_blocks_snapshot()
+            .filter(|block| {
+                if !block.is_bad {
+                    return false;
+                }
+
+                let revive = block.hash == *hash
+                    || block
+                        .state
+                        .parent_hash()
+                        .map_or(false, |parent_hash| revived.contains(parent_hash));
+                if revive {
+                    revived.insert(block.hash);
+                }
+                revive
+            })
+            .collect::<Vec<_>>();
+
+        for block in to_revive {
+            let user_data = self
+                .inner
+                .blocks
+                .remove_unverified_block(block.height, &block.hash);
+            self.inner.blocks.insert_unverified_block(
+                block.height,
+                block.hash,
+                block.state,
+                user_data,
+            );
+
+            // Blocks that are still banned must be marked as bad again.
+            if self.inner.banned_blocks.contains(&block.hash) {
+                self.inner
+                    .blocks
+                    .mark_unverified_block_as_bad(block.height, &block.hash);
+            }
+        }
+
+        true
+    }
+
+    /// Returns what is known about the parent of the given unverified block.
+    ///
+    /// This can be used in order to determine whether an ancestry search is necessary in order
+    /// to be able to verify the block.
+    ///
+    /// Returns `None` if the block isn't in the list of unverified blocks.
+    pub fn unverified_block_parent(
+        &self,
+        height: u64,
+        hash: &[u8; 32],
+    ) -> Option<UnverifiedBlockParent> {
+        let state = self.inner.blocks.unverified_block_state(height, hash)?;
+        let parent_hash = match state.parent_hash() {
+            Some(h) => *h,
+            None => return Some(UnverifiedBlockParent::HashUnknown),
+        };
+
+        // Unverified blocks are always strictly above the finalized block, so `height - 1`
+        // can't underflow.
+        Some(
+            if parent_hash == self.chain.finalized_block_hash()
+                || self.chain.contains_non_finalized_block(&parent_hash)
+            {
+                UnverifiedBlockParent::Verified(parent_hash)
+            } else if self
+                .inner
+                .blocks
+                .contains_unverified_block(height - 1, &parent_hash)
+            {
+                UnverifiedBlockParent::Unverified(parent_hash)
+            } else {
+                UnverifiedBlockParent::Unknown(parent_hash)
+            },
+        )
+    }
+
     /// Returns the header of all known non-finalized blocks in the chain without any specific
     /// order.
     pub fn non_finalized_blocks_unordered(
@@ -494,6 +1195,22 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         self.chain.iter_unordered()
     }
 
+    /// Returns the SCALE encoding of the header of the given verified non-finalized block.
+    ///
+    /// Returns `None` if the block isn't a verified non-finalized block, or if its height
+    /// doesn't match its hash.
+    pub fn non_finalized_block_scale_encoded_header(
+        &self,
+        height: u64,
+        hash: &[u8; 32],
+    ) -> Option<Vec<u8>> {
+        let block = self.chain.non_finalized_block_user_data(hash)?;
+        if block.header.number != height {
+            return None;
+        }
+        Some(block.header.scale_encoding_vec())
+    }
+
     /// Returns the header of all known non-finalized blocks in the chain.
     ///
     /// The returned items are guaranteed to be in an order in which the parents are found before
@@ -587,6 +1304,24 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         }
     }
 
+    /// Inserts a new source in the [`AllForksSync`] whose best block is temporarily assumed to
+    /// be the current finalized block.
+    ///
+    /// This is useful when the best block of the source isn't known yet at the time when the
+    /// source is added. Once it is known, it should be reported by calling
+    /// [`AllForksSync::block_announce`] with `is_best` equal to `true`.
+    ///
+    /// Equivalent to calling [`AllForksSync::prepare_add_source`] with the finalized block, then
+    /// [`AddSourceOldBlock::add_source`].
+    pub fn reserve_source(&mut self, source_user_data: TSrc) -> SourceId {
+        let finalized_block_number = self.chain.finalized_block_header().number;
+        let finalized_block_hash = self.chain.finalized_block_hash();
+        match self.prepare_add_source(finalized_block_number, finalized_block_hash) {
+            AddSource::OldBestBlock(add) => add.add_source(source_user_data),
+            _ => unreachable!(),
+        }
+    }
+
     /// Removes the source from the [`AllForksSync`].
     ///
     /// Removing the source implicitly cancels the request that is associated to it (if any).
@@ -608,6 +1343,9 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         source_id: SourceId,
     ) -> (TSrc, impl Iterator<Item = (RequestId, RequestParams, TRq)>) {
         let (user_data, iter) = self.inner.blocks.remove_source(source_id);
+        self.inner
+            .queued_block_announces
+            .retain(|announce| announce.source_id != source_id);
         (user_data.user_data, iter)
     }
 
@@ -616,6 +1354,17 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         self.inner.blocks.sources()
     }
 
+    /// Returns the list of sources whose best block, as tracked by this state machine, has a
+    /// height superior or equal to the given height.
+    ///
+    /// See also [`AllForksSync::source_best_block`].
+    pub fn sources_at_or_above(&'_ self, height: u64) -> impl Iterator<Item = SourceId> + '_ {
+        self.inner
+            .blocks
+            .sources()
+            .filter(move |source_id| self.inner.blocks.source_best_block(*source_id).0 >= height)
+    }
+
     /// Returns true if the source has earlier announced the block passed as parameter or one of
     /// its descendants.
     ///
@@ -678,10 +1427,31 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
             .add_known_block_to_source(source_id, height, hash);
     }
 
+    /// Registers a list of new blocks that the source is aware of.
+    ///
+    /// Equivalent to calling [`AllForksSync::add_known_block_to_source`] for each block, but
+    /// more efficient. Blocks whose height is inferior or equal to the finalized block height
+    /// are ignored.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SourceId`] is out of range.
+    ///
+    pub fn add_known_blocks_to_source(
+        &mut self,
+        source_id: SourceId,
+        blocks: impl Iterator<Item = (u64, [u8; 32])>,
+    ) {
+        self.inner
+            .blocks
+            .add_known_blocks_to_source(source_id, blocks);
+    }
+
     /// Returns the current best block of the given source.
     ///
     /// This corresponds either the latest call to [`AllForksSync::block_announce`] where
-    /// `is_best` was `true`, or to the parameter passed to [`AllForksSync::prepare_add_source`].
+    /// `is_best` was `true`, or to the parameter passed to [`AllForksSync::prepare_add_source`],
+    /// or to the finalized block at the time of the call to [`AllForksSync::reserve_source`].
     ///
     /// # Panic
     ///
@@ -701,23 +1471,115 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         self.inner.blocks.source_num_ongoing_requests(source_id)
     }
 
-    /// Returns the details of a request to start towards a source.
+    /// Returns `true` if this source has provided a header indicating itself as its own parent
+    /// or a header larger than [`Config::max_header_size`].
     ///
-    /// This method doesn't modify the state machine in any way. [`AllForksSync::add_request`]
-    /// must be called in order for the request to actually be marked as started.
-    pub fn desired_requests(
-        &'_ self,
-    ) -> impl Iterator<Item = (SourceId, &'_ TSrc, RequestParams)> + '_ {
-        // TODO: need to periodically query for justifications of non-finalized blocks that change GrandPa authorities
-
-        self.inner
-            .blocks
-            .desired_requests()
+    /// # Panic
+    ///
+    /// Panics if the [`SourceId`] is invalid.
+    ///
+    pub fn source_misbehaved(&self, source_id: SourceId) -> bool {
+        self.inner.blocks[source_id].misbehaved
+    }
+
+    /// Returns the counters of the requests that have been finished for this source.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SourceId`] is invalid.
+    ///
+    pub fn source_stats(&self, source_id: SourceId) -> SourceStats {
+        self.inner.blocks[source_id].stats.clone()
+    }
+
+    /// Returns an estimation of the number of headers successfully verified per second, based on
+    /// the time passed to [`HeaderVerify::perform`] for the most recently verified headers.
+    ///
+    /// Returns `None` if not enough headers have been verified yet to give an estimate.
+    pub fn blocks_per_second(&self) -> Option<f64> {
+        self.inner.recent_verifications.blocks_per_second()
+    }
+
+    /// Returns a summary of the state of the state machine.
+    pub fn health(&self) -> SyncHealth {
+        SyncHealth::new(
+            (
+                self.chain.best_block_header().number,
+                self.chain.best_block_hash(),
+            ),
+            (
+                self.chain.finalized_block_header().number,
+                self.chain.finalized_block_hash(),
+            ),
+            self.inner.blocks.sources().len(),
+            self.inner.blocks.requests().count(),
+            self.inner
+                .blocks
+                .sources()
+                .map(|source_id| self.inner.blocks.source_best_block(source_id).0),
+        )
+    }
+
+    /// Pauses or resumes the generation of new requests.
+    ///
+    /// While paused, [`AllForksSync::desired_requests`] doesn't return any request. Requests
+    /// that are already in progress aren't affected, and their responses can still be processed
+    /// normally.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.inner.paused = paused;
+    }
+
+    /// Returns the value that was passed as [`Config::randomness_seed`].
+    ///
+    /// This seed determines the order in which the sources of each block are picked, and the key
+    /// of the set of banned blocks if [`BannedBlocksHasher::Randomized`] is used. It can be
+    /// logged in order to later reproduce a problem offline by building a new [`AllForksSync`]
+    /// with the same seed and replaying the same sequence of calls.
+    pub fn randomness_seed(&self) -> [u8; 32] {
+        self.inner.randomness_seed
+    }
+
+    /// Returns `true` if [`AllForksSync::set_paused`] has last been called with `true`.
+    pub fn is_paused(&self) -> bool {
+        self.inner.paused
+    }
+
+    /// Returns the details of a request to start towards a source.
+    ///
+    /// This method doesn't modify the state machine in any way. [`AllForksSync::add_request`]
+    /// must be called in order for the request to actually be marked as started.
+    ///
+    /// Requests targeting a block whose height is inferior or equal to the current best block
+    /// are no longer returned if [`Config::max_fork_ancestry_searches`] such requests are already
+    /// in progress.
+    ///
+    /// Always empty if the state machine is paused. See [`AllForksSync::set_paused`].
+    pub fn desired_requests(
+        &'_ self,
+    ) -> impl Iterator<Item = (SourceId, &'_ TSrc, RequestParams)> + '_ {
+        // TODO: need to periodically query for justifications of non-finalized blocks that change GrandPa authorities
+
+        let best_block_number = self.chain.best_block_header().number;
+        let forks_limit_reached = self
+            .inner
+            .blocks
+            .requests()
+            .filter(|(_, _, rq)| rq.first_block_height <= best_block_number)
+            .count()
+            >= self.inner.max_fork_ancestry_searches;
+
+        self.inner
+            .blocks
+            .desired_requests()
             .filter(move |rq| {
                 !self
                     .chain
                     .contains_non_finalized_block(&rq.request_params.first_block_hash)
             })
+            .filter(move |rq| {
+                !forks_limit_reached || rq.request_params.first_block_height > best_block_number
+            })
+            .filter(move |_| !self.inner.paused)
             .map(move |rq| {
                 (
                     rq.source_id,
@@ -745,6 +1607,31 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         self.inner.blocks.add_request(source_id, detail, user_data)
     }
 
+    /// Returns the source and parameters of the given request, as passed to
+    /// [`AllForksSync::add_request`].
+    ///
+    /// Returns `None` if the [`RequestId`] is invalid, for example because the request has
+    /// already been finished.
+    ///
+    /// > **Note**: [`RequestId`]s of finished requests can later be re-used for new requests.
+    pub fn request_detail(&self, request_id: RequestId) -> Option<(SourceId, RequestParams)> {
+        self.inner.blocks.request_detail(request_id)
+    }
+
+    /// Returns the list of blocks, as `(height, hash)`, whose header or body is currently being
+    /// downloaded, ordered by increasing height.
+    ///
+    /// A block is considered as being downloaded if it is the first block of an ongoing request
+    /// added with [`AllForksSync::add_request`], or one of its ancestors known at the time when
+    /// the request has been added. Each block is yielded only once, even if it is targeted by
+    /// multiple requests.
+    pub fn blocks_being_downloaded(&'_ self) -> impl Iterator<Item = (u64, [u8; 32])> + '_ {
+        self.inner
+            .blocks
+            .requested_blocks()
+            .map(|(height, hash)| (height, *hash))
+    }
+
     /// Returns a list of requests that are considered obsolete and can be removed using
     /// [`AllForksSync::finish_ancestry_search`] or similar.
     ///
@@ -773,6 +1660,165 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
     pub fn finish_ancestry_search(
         mut self,
         request_id: RequestId,
+    ) -> (TRq, FinishAncestrySearch<TBl, TRq, TSrc>) {
+        self.record_request_finished(request_id, true);
+        self.finish_ancestry_search_inner(request_id)
+    }
+
+    /// Checks whether all the blocks of a response to the given request would be successfully
+    /// added by [`FinishAncestrySearch::add_block`], without modifying the state machine.
+    ///
+    /// This makes it possible to only call [`AllForksSync::finish_ancestry_search`] if the
+    /// entire response is valid, rather than inserting a valid prefix of the response followed
+    /// by an error. The headers must be passed in the same order as they would be passed to
+    /// [`FinishAncestrySearch::add_block`].
+    ///
+    /// On error, returns the index within the response of the first problematic header and the
+    /// error that [`FinishAncestrySearch::add_block`] would return. Contrary to
+    /// [`FinishAncestrySearch::add_block`], the source isn't flagged as misbehaving.
+    ///
+    /// > **Note**: Responses that contain blocks whose height is inferior or equal to the
+    /// >           finalized block lead to [`AncestrySearchResponseError::TooOld`]. The API user
+    /// >           might want to truncate the response before these blocks.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`RequestId`] is invalid.
+    ///
+    pub fn validate_ancestry_search_response<'h>(
+        &self,
+        request_id: RequestId,
+        scale_encoded_headers: impl Iterator<Item = &'h [u8]>,
+    ) -> Result<(), (usize, AncestrySearchResponseError)> {
+        let params = self.inner.blocks.request_params(request_id);
+        let mut expected_next_hash = params.first_block_hash;
+        let mut expected_next_height = params.first_block_height;
+
+        for (index, scale_encoded_header) in scale_encoded_headers.enumerate() {
+            let decoded_header = self
+                .check_ancestry_search_header(
+                    scale_encoded_header,
+                    index,
+                    &expected_next_hash,
+                    expected_next_height,
+                )
+                .map_err(|err| (index, err))?;
+
+            expected_next_hash = *decoded_header.parent_hash;
+            expected_next_height -= 1;
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether a header of a response to an ancestry search is acceptable.
+    ///
+    /// `index_in_response` is the position of the header within the response, and
+    /// `expected_hash` and `expected_height` the hash and height the header must have.
+    ///
+    /// On success, returns the decoded header. The block might already be part of the chain, in
+    /// which case the check for [`AncestrySearchResponseError::NotFinalizedChain`] is skipped.
+    fn check_ancestry_search_header<'h>(
+        &self,
+        scale_encoded_header: &'h [u8],
+        index_in_response: usize,
+        expected_hash: &[u8; 32],
+        expected_height: u64,
+    ) -> Result<header::HeaderRef<'h>, AncestrySearchResponseError> {
+        let unexpected_block = || {
+            if index_in_response == 0 {
+                AncestrySearchResponseError::UnexpectedFirstBlock
+            } else {
+                AncestrySearchResponseError::UnexpectedNextBlock
+            }
+        };
+
+        // Oversized headers are erroneous. This is checked before doing anything else with the
+        // header in order to bound the amount of work and memory that a source can cause.
+        if scale_encoded_header.len() > self.inner.max_header_size {
+            return Err(AncestrySearchResponseError::InvalidHeader(
+                header::Error::TooLong,
+            ));
+        }
+
+        // Compare expected with actual hash.
+        // This ensure that each header being processed is the parent of the previous one.
+        if *expected_hash != header::hash_from_scale_encoded_header(scale_encoded_header) {
+            return Err(unexpected_block());
+        }
+
+        // Invalid headers are erroneous.
+        let decoded_header = header::decode(scale_encoded_header)
+            .map_err(AncestrySearchResponseError::InvalidHeader)?;
+
+        // Also compare the block numbers.
+        // The utility of checking the height (even though we've already checked the hash) is
+        // questionable, but considering that blocks are identified with their combination of
+        // hash and number, checking both the hash and number might prevent malicious sources
+        // from introducing state inconsistenties, even though it's unclear how that could happen.
+        if expected_height != decoded_header.number {
+            return Err(unexpected_block());
+        }
+
+        // A block can't be its own parent. Such a header is necessarily malformed.
+        if *decoded_header.parent_hash == *expected_hash {
+            return Err(AncestrySearchResponseError::SelfParent);
+        }
+
+        // It is assumed that all sources will eventually agree on the same finalized chain. If
+        // the block number is lower or equal than the locally-finalized block number, it is
+        // assumed that this source is simply late compared to the local node, and that the block
+        // that has been received is either part of the finalized chain or belongs to a fork that
+        // will get discarded by this source in the future.
+        if decoded_header.number <= self.chain.finalized_block_header().number {
+            return Err(AncestrySearchResponseError::TooOld);
+        }
+
+        // Block is not part of the finalized chain.
+        if !self.chain.contains_non_finalized_block(expected_hash)
+            && decoded_header.number == self.chain.finalized_block_header().number + 1
+            && *decoded_header.parent_hash != self.chain.finalized_block_hash()
+        {
+            // This doesn't necessarily mean that the source and the local node disagree
+            // on the finalized chain. It is possible that the finalized block has been
+            // updated between the moment the request was emitted and the moment the
+            // response is received.
+            return Err(AncestrySearchResponseError::NotFinalizedChain {
+                discarded_unverified_block_headers: Vec::new(), // TODO: not properly implemented /!\
+            });
+        }
+
+        Ok(decoded_header)
+    }
+
+    /// Updates the [`SourceStats`] of the source of the given request, which is about to be
+    /// finished.
+    ///
+    /// A request is considered as obsolete, no matter whether it has succeeded, under the same
+    /// conditions as [`AllForksSync::obsolete_requests`].
+    fn record_request_finished(&mut self, request_id: RequestId, success: bool) {
+        let source_id = self.inner.blocks.request_source(request_id);
+        let is_obsolete = self
+            .inner
+            .blocks
+            .request_params(request_id)
+            .first_block_height
+            <= self.chain.finalized_block_header().number;
+
+        let stats = &mut self.inner.blocks[source_id].stats;
+        if is_obsolete {
+            stats.num_requests_obsolete += 1;
+        } else if success {
+            stats.num_requests_succeeded += 1;
+        } else {
+            stats.num_requests_failed += 1;
+        }
+    }
+
+    /// Same as [`AllForksSync::finish_ancestry_search`], but doesn't update the [`SourceStats`].
+    fn finish_ancestry_search_inner(
+        mut self,
+        request_id: RequestId,
     ) -> (TRq, FinishAncestrySearch<TBl, TRq, TSrc>) {
         // Sets the `occupation` of `source_id` back to `AllSync`.
         let (
@@ -810,13 +1856,58 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
     ///
     // TODO: taking a `&mut self` instead of a `self` would be more correct, however this doesn't give any benefit and complicates the implementation at the moment, so it might not be worth doing
     pub fn ancestry_search_failed(
-        self,
+        mut self,
         request_id: RequestId,
     ) -> (TRq, AllForksSync<TBl, TRq, TSrc>) {
-        let (user_data, inner) = self.finish_ancestry_search(request_id);
+        self.record_request_finished(request_id, false);
+        let (user_data, inner) = self.finish_ancestry_search_inner(request_id);
         (user_data, inner.finish())
     }
 
+    /// Returns a request that can be started in order to replace the given request, in case it
+    /// has failed.
+    ///
+    /// This method must be called before [`AllForksSync::ancestry_search_failed`], as the
+    /// [`RequestId`] is no longer valid afterwards.
+    ///
+    /// The returned request has the same parameters as the given request, but targets a
+    /// different source that is known to have the requested block. Sources with the fewest
+    /// number of ongoing requests are preferred. Returns `None` if no other source is known to
+    /// have the requested block, or if the requested block doesn't need to be requested anymore.
+    ///
+    /// This method doesn't modify the state machine in any way. [`AllForksSync::add_request`]
+    /// must be called in order for the request to actually be marked as started.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`RequestId`] is invalid.
+    ///
+    pub fn reissue_failed_ancestry_search(
+        &self,
+        failed_request: RequestId,
+    ) -> Option<(SourceId, RequestParams)> {
+        let failed_source = self.inner.blocks.request_source(failed_request);
+        let params = *self.inner.blocks.request_params(failed_request);
+
+        // Blocks that are finalized or already in the chain don't need to be requested.
+        if params.first_block_height <= self.chain.finalized_block_header().number
+            || self
+                .chain
+                .contains_non_finalized_block(&params.first_block_hash)
+        {
+            return None;
+        }
+
+        let new_source = self
+            .inner
+            .blocks
+            .knows_non_finalized_block(params.first_block_height, &params.first_block_hash)
+            .filter(|source_id| *source_id != failed_source)
+            .min_by_key(|source_id| self.inner.blocks.source_num_ongoing_requests(*source_id))?;
+
+        Some((new_source, params))
+    }
+
     /// Update the source with a newly-announced block.
     ///
     /// > **Note**: This information is normally reported by the source itself. In the case of a
@@ -841,6 +1932,13 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         let announced_header_parent_hash = *announced_header.parent_hash;
         let announced_header_hash = announced_header.hash();
 
+        // A block can't be its own parent. Such a header is necessarily malformed, and the
+        // source is flagged as misbehaving.
+        if announced_header_parent_hash == announced_header_hash {
+            self.inner.blocks[source_id].misbehaved = true;
+            return BlockAnnounceOutcome::SelfParent;
+        }
+
         // It is assumed that all sources will eventually agree on the same finalized chain. If
         // the block number is lower or equal than the locally-finalized block number, it is
         // assumed that this source is simply late compared to the local node, and that the block
@@ -855,14 +1953,33 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
                     announced_header_number,
                     announced_header_hash,
                 );
+
+                let source = &mut self.inner.blocks[source_id];
+                source.num_consecutive_too_old_best_announces = source
+                    .num_consecutive_too_old_best_announces
+                    .saturating_add(1);
             }
 
+            let persistently_too_old = is_best
+                && self
+                    .inner
+                    .max_consecutive_too_old_best_announces
+                    .map_or(false, |max| {
+                        self.inner.blocks[source_id].num_consecutive_too_old_best_announces
+                            >= max.get()
+                    });
+
             return BlockAnnounceOutcome::TooOld {
                 announce_block_height: announced_header_number,
                 finalized_block_height: self.chain.finalized_block_header().number,
+                persistently_too_old,
             };
         }
 
+        if is_best {
+            self.inner.blocks[source_id].num_consecutive_too_old_best_announces = 0;
+        }
+
         // If the block is already part of the local tree of blocks, nothing more to do.
         if self
             .chain
@@ -881,6 +1998,34 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         }
 
         // At this point, we have excluded blocks that are already part of the chain or too old.
+        // If the block is unknown and the source is saturated, only remember that the source
+        // knows this block.
+        if let Some(threshold) = self.inner.source_saturation_threshold {
+            if self.inner.blocks.source_num_ongoing_requests(source_id)
+                >= usize::try_from(threshold.get()).unwrap_or(usize::max_value())
+                && !self
+                    .inner
+                    .blocks
+                    .contains_unverified_block(announced_header_number, &announced_header_hash)
+            {
+                if is_best {
+                    self.inner.blocks.add_known_block_to_source_and_set_best(
+                        source_id,
+                        announced_header_number,
+                        announced_header_hash,
+                    );
+                } else {
+                    self.inner.blocks.add_known_block_to_source(
+                        source_id,
+                        announced_header_number,
+                        announced_header_hash,
+                    );
+                }
+
+                return BlockAnnounceOutcome::SourceSaturated;
+            }
+        }
+
         // We insert the block in the list of unverified blocks so as to treat all blocks the
         // same.
         if !self
@@ -911,10 +2056,197 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         }
     }
 
+    /// Similar to [`AllForksSync::block_announce`], but the announce is stored in a queue and is
+    /// only processed later by [`AllForksSync::process_one`], which then returns
+    /// [`ProcessOne::BlockAnnouncesProcessed`]. Each call to [`AllForksSync::process_one`]
+    /// processes a bounded number of queued announces.
+    ///
+    /// If the announced block turns out to be unknown when the announce is processed, it is
+    /// inserted with the given `user_data`, as if
+    /// [`AnnouncedBlockUnknown::insert_and_update_source`] was called. Otherwise, `user_data` is
+    /// discarded.
+    ///
+    /// If [`Config::max_queued_block_announces`] announces are already queued, the least
+    /// valuable announce, which can be the one passed as parameter, is discarded. Announces
+    /// whose parent is known are considered more valuable than the ones whose parent isn't, and
+    /// announces of lower blocks more valuable than announces of higher blocks, as they are
+    /// closer to being verifiable.
+    ///
+    /// Because the announce is processed later, the misbehaviors that
+    /// [`AllForksSync::block_announce`] would report are only reflected through
+    /// [`AllForksSync::source_misbehaved`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if `source_id` is invalid.
+    ///
+    pub fn queue_block_announce(
+        &mut self,
+        source_id: SourceId,
+        announced_scale_encoded_header: Vec<u8>,
+        is_best: bool,
+        user_data: TBl,
+    ) -> QueueBlockAnnounceOutcome {
+        // Panics if `source_id` is invalid.
+        let _ = &self.inner.blocks[source_id];
+
+        let (number, parent_hash) = match header::decode(&announced_scale_encoded_header) {
+            Ok(h) => (h.number, *h.parent_hash),
+            Err(error) => return QueueBlockAnnounceOutcome::InvalidHeader(error),
+        };
+
+        let mut outcome = QueueBlockAnnounceOutcome::Queued;
+
+        if self.inner.queued_block_announces.len() >= self.inner.max_queued_block_announces {
+            let value = |number: u64, parent_hash: &[u8; 32]| {
+                let parent_known = *parent_hash == self.chain.finalized_block_hash()
+                    || self.chain.contains_non_finalized_block(parent_hash)
+                    || number.checked_sub(1).map_or(false, |parent_number| {
+                        self.inner
+                            .blocks
+                            .contains_unverified_block(parent_number, parent_hash)
+                    });
+                (parent_known, cmp::Reverse(number))
+            };
+
+            let new_announce_value = value(number, &parent_hash);
+            let least_valuable = self
+                .inner
+                .queued_block_announces
+                .iter()
+                .map(|announce| value(announce.number, &announce.parent_hash))
+                .enumerate()
+                .min_by_key(|(_, value)| *value);
+
+            match least_valuable {
+                Some((index, least_value)) if least_value < new_announce_value => {
+                    self.inner.queued_block_announces.remove(index);
+                    outcome = QueueBlockAnnounceOutcome::QueuedWithEviction;
+                }
+                _ => return QueueBlockAnnounceOutcome::Discarded,
+            }
+        }
+
+        self.inner
+            .queued_block_announces
+            .push_back(QueuedBlockAnnounce {
+                source_id,
+                scale_encoded_header: announced_scale_encoded_header,
+                number,
+                parent_hash,
+                is_best,
+                user_data,
+            });
+
+        outcome
+    }
+
+    /// Similar to calling [`AllForksSync::queue_block_announce`] once for each announce, but all
+    /// the announced blocks must have the same height. They are recorded as siblings competing
+    /// for this height, which can later be retrieved with [`AllForksSync::block_siblings`] and
+    /// [`AllForksSync::contested_heights`].
+    ///
+    /// `announces` contains tuples of `(scale_encoded_header, is_best, user_data)`.
+    ///
+    /// On success, returns the outcome of queuing each announce, in the same order as
+    /// `announces`. Announces that have been discarded because the queue is full aren't
+    /// recorded as siblings. On error, nothing has been queued or recorded.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `source_id` is invalid.
+    ///
+    pub fn queue_sibling_block_announces(
+        &mut self,
+        source_id: SourceId,
+        announces: impl IntoIterator<Item = (Vec<u8>, bool, TBl)>,
+    ) -> Result<Vec<QueueBlockAnnounceOutcome>, SiblingBlockAnnouncesError> {
+        let announces = announces.into_iter().collect::<Vec<_>>();
+
+        let mut height = None;
+        let mut hashes = Vec::with_capacity(announces.len());
+        for (index, (scale_encoded_header, _, _)) in announces.iter().enumerate() {
+            let header = header::decode(scale_encoded_header)
+                .map_err(|error| SiblingBlockAnnouncesError::InvalidHeader { index, error })?;
+            match height {
+                None => height = Some(header.number),
+                Some(h) if h == header.number => {}
+                Some(_) => return Err(SiblingBlockAnnouncesError::HeightMismatch { index }),
+            }
+            hashes.push(header.hash());
+        }
+
+        // Only the announces that have effectively been queued are recorded as siblings.
+        let mut queued_hashes = BTreeSet::new();
+        let outcomes = announces
+            .into_iter()
+            .zip(hashes)
+            .map(|((scale_encoded_header, is_best, user_data), hash)| {
+                let outcome =
+                    self.queue_block_announce(source_id, scale_encoded_header, is_best, user_data);
+                if matches!(
+                    outcome,
+                    QueueBlockAnnounceOutcome::Queued
+                        | QueueBlockAnnounceOutcome::QueuedWithEviction
+                ) {
+                    queued_hashes.insert(hash);
+                }
+                outcome
+            })
+            .collect();
+
+        if let Some(height) = height {
+            if queued_hashes.len() >= 2 && height > self.chain.finalized_block_header().number {
+                self.inner
+                    .announced_siblings
+                    .entry(height)
+                    .or_default()
+                    .extend(queued_hashes);
+            }
+        }
+
+        Ok(outcomes)
+    }
+
+    /// Returns the hashes of the blocks that have been announced as siblings of the given block
+    /// through [`AllForksSync::queue_sibling_block_announces`]. The given block itself isn't
+    /// included.
+    ///
+    /// Siblings at heights inferior or equal to the finalized block are forgotten.
+    pub fn block_siblings(
+        &'_ self,
+        height: u64,
+        hash: &[u8; 32],
+    ) -> impl Iterator<Item = &'_ [u8; 32]> + '_ {
+        let hash = *hash;
+        self.inner
+            .announced_siblings
+            .get(&height)
+            .filter(|siblings| siblings.contains(&hash))
+            .into_iter()
+            .flat_map(|siblings| siblings.iter())
+            .filter(move |sibling| **sibling != hash)
+    }
+
+    /// Returns the list of heights above the finalized block for which multiple competing blocks
+    /// have been announced through [`AllForksSync::queue_sibling_block_announces`], in increasing
+    /// order, alongside with the number of competing blocks.
+    pub fn contested_heights(&'_ self) -> impl Iterator<Item = (u64, usize)> + '_ {
+        self.inner
+            .announced_siblings
+            .iter()
+            .map(|(height, siblings)| (*height, siblings.len()))
+    }
+
     /// Update the state machine with a Grandpa commit message received from the network.
     ///
     /// On success, the finalized block has been updated.
     ///
+    /// If the commit can't be verified yet, for example because its target block isn't known,
+    /// it is stored for later and `Ok` is returned. The commit is silently discarded if
+    /// [`Config::max_pending_grandpa_commits`] commits are already stored, unless it replaces a
+    /// commit or justification of the same source.
+    ///
     /// # Panic
     ///
     /// Panics if `source_id` is invalid.
@@ -927,7 +2259,7 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         scale_encoded_commit: &[u8],
     ) -> Result<(), blocks_tree::CommitVerifyError> {
         // Grabbing the source is done early on in order to panic if the `source_id` is invalid.
-        let source = &mut self.inner.blocks[source_id];
+        let _ = &self.inner.blocks[source_id];
 
         let block_number = match self
             .chain
@@ -935,6 +2267,10 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         {
             Ok(apply) => {
                 apply.apply();
+                self.inner.finalized_block_justifications.clear();
+                let finalized_height = self.chain.finalized_block_header().number;
+                self.inner
+                    .remove_obsolete_pending_finality_proofs(finalized_height);
                 return Ok(());
             }
             // In case where the commit message concerns a block older or equal to the finalized
@@ -960,8 +2296,24 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
             Err(err) => return Err(err),
         };
 
-        // If we reach here, the commit can't be verified yet. The commit is stored for later.
-        source.pending_finality_proofs.insert(
+        // If we reach here, the commit can't be verified yet. The commit is stored for later,
+        // unless it would add a new entry while too many commits are already waiting.
+        if self.inner.blocks[source_id]
+            .pending_finality_proofs
+            .insert_adds_entry(block_number)
+        {
+            let num_pending_commits = self
+                .inner
+                .blocks
+                .sources_user_data_iter()
+                .map(|s| s.pending_finality_proofs.num_grandpa_commits())
+                .sum::<usize>();
+            if num_pending_commits >= self.inner.max_pending_grandpa_commits {
+                return Ok(());
+            }
+        }
+
+        self.inner.blocks[source_id].pending_finality_proofs.insert(
             block_number,
             FinalityProofs::GrandpaCommit(scale_encoded_commit.to_vec()),
         );
@@ -969,19 +2321,175 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
         Ok(())
     }
 
-    /// Process the next block in the queue of verification.
+    /// Reports that the given source has provided two different blocks that have been authored
+    /// by the same authority during the same slot.
     ///
-    /// This method takes ownership of the [`AllForksSync`] and starts a verification
-    /// process. The [`AllForksSync`] is yielded back at the end of this process.
-    pub fn process_one(mut self) -> ProcessOne<TBl, TRq, TSrc> {
-        // TODO: O(n)
-        let source_id_with_finality_proof = self
-            .inner
-            .blocks
-            .sources()
-            .find(|id| !self.inner.blocks[*id].unverified_finality_proofs.is_none());
+    /// Both blocks must be in the list of unverified blocks, must be known to the given source,
+    /// and their headers must be known. Their parents must have been verified, so that the seals
+    /// of the two blocks can be checked against the authorities of the chain. Blocks that have
+    /// already been verified can't be removed from the chain and are refused.
+    ///
+    /// On success, both blocks are added to the list of banned blocks, which prevents them and
+    /// their descendants from being verified, and the public key of the authority that has
+    /// authored them is added to the list of equivocating authorities. See
+    /// [`AllForksSync::equivocating_authorities`]. This public key is also returned.
+    ///
+    /// Must be passed the current UNIX time in order to verify that the blocks don't pretend to
+    /// come from the future.
+    ///
+    /// # Panic
+    ///
+    /// Panics if `source_id` is invalid.
+    ///
+    pub fn report_equivocation(
+        &mut self,
+        source_id: SourceId,
+        first_block: (u64, &[u8; 32]),
+        second_block: (u64, &[u8; 32]),
+        now_from_unix_epoch: Duration,
+    ) -> Result<[u8; 32], EquivocationProofError> {
+        // Grabbing the source is done early on in order to panic if the `source_id` is invalid.
+        let _ = &self.inner.blocks[source_id];
 
-        if let Some(source_id_with_finality_proof) = source_id_with_finality_proof {
+        if first_block.1 == second_block.1 {
+            return Err(EquivocationProofError::SameBlock);
+        }
+
+        let mut headers = Vec::with_capacity(2);
+        for (height, hash) in [first_block, second_block] {
+            if self.chain.contains_non_finalized_block(hash)
+                || *hash == self.chain.finalized_block_hash()
+            {
+                return Err(EquivocationProofError::AlreadyVerified);
+            }
+
+            if !self.inner.blocks.contains_unverified_block(height, hash)
+                || !self
+                    .inner
+                    .blocks
+                    .source_knows_non_finalized_block(source_id, height, hash)
+            {
+                return Err(EquivocationProofError::UnknownBlock);
+            }
+
+            match &self
+                .inner
+                .blocks
+                .unverified_block_user_data(height, hash)
+                .header
+            {
+                Some(header) => headers.push(header.clone()),
+                None => return Err(EquivocationProofError::HeaderUnknown),
+            }
+        }
+
+        let first_digest = header::DigestRef::from(&headers[0].digest);
+        let second_digest = header::DigestRef::from(&headers[1].digest);
+        match (
+            first_digest.babe_pre_runtime(),
+            second_digest.babe_pre_runtime(),
+            first_digest.aura_pre_runtime(),
+            second_digest.aura_pre_runtime(),
+        ) {
+            (Some(first), Some(second), _, _) => {
+                if first.slot_number() != second.slot_number() {
+                    return Err(EquivocationProofError::SlotMismatch);
+                }
+            }
+            (None, None, Some(first), Some(second)) => {
+                if first.slot_number != second.slot_number {
+                    return Err(EquivocationProofError::SlotMismatch);
+                }
+            }
+            _ => return Err(EquivocationProofError::UnsupportedConsensus),
+        }
+
+        // Verify the seals of both blocks, without inserting them in the chain, in order to
+        // find out which authority has signed them.
+        let mut authors = Vec::with_capacity(2);
+        for header in headers {
+            match self
+                .chain
+                .verify_header(header.scale_encoding_vec(), now_from_unix_epoch)
+            {
+                Ok(blocks_tree::HeaderVerifySuccess::Insert { insert, .. }) => {
+                    authors.push(insert.author_public_key());
+                }
+                Ok(blocks_tree::HeaderVerifySuccess::Duplicate) => {
+                    return Err(EquivocationProofError::AlreadyVerified)
+                }
+                Err(blocks_tree::HeaderVerifyError::BadParent { .. }) => {
+                    return Err(EquivocationProofError::ParentNotVerified)
+                }
+                Err(err) => return Err(EquivocationProofError::InvalidHeader(err)),
+            }
+        }
+
+        if authors[0] != authors[1] {
+            return Err(EquivocationProofError::AuthorityMismatch);
+        }
+
+        for (height, hash) in [first_block, second_block] {
+            self.inner.banned_blocks.insert(*hash);
+            self.inner.blocks.mark_unverified_block_as_bad(height, hash);
+        }
+        self.inner.equivocating_authorities.insert(authors[0]);
+        Ok(authors[0])
+    }
+
+    /// Returns the list of public keys of the authorities that have been found to equivocate
+    /// through [`AllForksSync::report_equivocation`], in no specific order.
+    pub fn equivocating_authorities(&'_ self) -> impl Iterator<Item = &'_ [u8; 32]> + '_ {
+        self.inner.equivocating_authorities.iter()
+    }
+
+    /// Process the next block in the queue of verification.
+    ///
+    /// This method takes ownership of the [`AllForksSync`] and starts a verification
+    /// process. The [`AllForksSync`] is yielded back at the end of this process.
+    pub fn process_one(mut self) -> ProcessOne<TBl, TRq, TSrc> {
+        // Start by processing the announces queued with `queue_block_announce`. Only a limited
+        // number of them are processed at once in order to bound the duration of this call.
+        if !self.inner.queued_block_announces.is_empty() {
+            for _ in 0..QUEUED_BLOCK_ANNOUNCES_BATCH {
+                let announce = match self.inner.queued_block_announces.pop_front() {
+                    Some(a) => a,
+                    None => break,
+                };
+                let QueuedBlockAnnounce {
+                    source_id,
+                    scale_encoded_header,
+                    is_best,
+                    user_data,
+                    ..
+                } = announce;
+
+                match self.block_announce(source_id, scale_encoded_header, is_best) {
+                    BlockAnnounceOutcome::Unknown(unknown) => {
+                        unknown.insert_and_update_source(user_data)
+                    }
+                    BlockAnnounceOutcome::Known(known)
+                    | BlockAnnounceOutcome::AlreadyInChain(known) => {
+                        known.update_source_and_block()
+                    }
+                    BlockAnnounceOutcome::TooOld { .. }
+                    | BlockAnnounceOutcome::InvalidHeader(_)
+                    | BlockAnnounceOutcome::SelfParent
+                    | BlockAnnounceOutcome::SourceSaturated => {}
+                }
+            }
+
+            return ProcessOne::BlockAnnouncesProcessed { sync: self };
+        }
+
+        // TODO: O(n)
+        let source_id_with_finality_proof = self
+            .inner
+            .blocks
+            .sources()
+            .find(|id| !self.inner.blocks[*id].unverified_finality_proofs.is_none());
+
+        if let Some(source_id_with_finality_proof) = source_id_with_finality_proof {
             let finality_proof_to_verify = self.inner.blocks[source_id_with_finality_proof]
                 .unverified_finality_proofs
                 .take_one()
@@ -990,14 +2498,33 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
                 parent: self,
                 source_id: source_id_with_finality_proof,
                 finality_proof_to_verify,
+                verified_block_hash: None,
             });
         }
 
+        // Blocks above this height aren't verified until the finalized block catches up.
+        // See `Config::max_blocks_ahead_of_finalized`.
+        let max_height = self
+            .inner
+            .max_blocks_ahead_of_finalized
+            .map_or(u64::max_value(), |max| {
+                self.chain
+                    .finalized_block_header()
+                    .number
+                    .saturating_add(max.get())
+            });
+
+        let mut waiting_for_finality = false;
         let block = self.inner.blocks.unverified_leaves().find(|block| {
-            block.parent_block_hash == self.chain.finalized_block_hash()
+            let parent_known = block.parent_block_hash == self.chain.finalized_block_hash()
                 || self
                     .chain
-                    .contains_non_finalized_block(&block.parent_block_hash)
+                    .contains_non_finalized_block(&block.parent_block_hash);
+            if parent_known && block.block_number > max_height {
+                waiting_for_finality = true;
+                return false;
+            }
+            parent_known
         });
 
         if let Some(block) = block {
@@ -1005,11 +2532,82 @@ impl<TBl, TRq, TSrc> AllForksSync<TBl, TRq, TSrc> {
                 parent: self,
                 block_to_verify: block,
             })
+        } else if waiting_for_finality {
+            ProcessOne::WaitingForFinality { sync: self }
         } else {
             ProcessOne::AllSync { sync: self }
         }
     }
 
+    /// Calls [`AllForksSync::process_one`] and performs the verification that it yields, up to
+    /// `max_steps` times or until there is nothing more to process.
+    ///
+    /// This is a convenience method for API users that don't need to act on the individual
+    /// outcome of each verification.
+    ///
+    /// Must be passed the time elapsed since the Unix Epoch. See [`HeaderVerify::perform`].
+    pub fn process_some(
+        mut self,
+        max_steps: usize,
+        now_from_unix_epoch: Duration,
+    ) -> (Self, ProcessSome<TBl>) {
+        let mut summary = ProcessSome {
+            num_steps: 0,
+            is_idle: false,
+            num_verified_headers: 0,
+            num_invalid_headers: 0,
+            new_best: false,
+            finalized_blocks: Vec::new(),
+            num_invalid_finality_proofs: 0,
+        };
+
+        while summary.num_steps < max_steps {
+            self = match self.process_one() {
+                ProcessOne::AllSync { sync } | ProcessOne::WaitingForFinality { sync } => {
+                    summary.is_idle = true;
+                    return (sync, summary);
+                }
+                ProcessOne::BlockAnnouncesProcessed { sync } => sync,
+                ProcessOne::HeaderVerify(verify) => match verify.perform(now_from_unix_epoch) {
+                    HeaderVerifyOutcome::Success { is_new_best, sync } => {
+                        summary.num_verified_headers += 1;
+                        summary.new_best |= is_new_best;
+                        sync
+                    }
+                    HeaderVerifyOutcome::Error { sync, .. } => {
+                        summary.num_invalid_headers += 1;
+                        sync
+                    }
+                },
+                ProcessOne::FinalityProofVerify(verify) => {
+                    let (sync, outcome) = verify.perform();
+                    match outcome {
+                        FinalityProofVerifyOutcome::NewFinalized {
+                            finalized_blocks,
+                            updates_best_block,
+                            ..
+                        } => {
+                            summary.finalized_blocks.extend(finalized_blocks);
+                            summary.new_best |= updates_best_block;
+                        }
+                        FinalityProofVerifyOutcome::AlreadyFinalized
+                        | FinalityProofVerifyOutcome::GrandpaCommitPending
+                        | FinalityProofVerifyOutcome::JustificationPending => {}
+                        FinalityProofVerifyOutcome::JustificationError(_)
+                        | FinalityProofVerifyOutcome::GrandpaCommitError(_) => {
+                            summary.num_invalid_finality_proofs += 1;
+                        }
+                    }
+                    sync
+                }
+            };
+
+            summary.num_steps += 1;
+        }
+
+        (self, summary)
+    }
+
     /*/// Call in response to a [`BlockAnnounceOutcome::BlockBodyDownloadStart`].
     ///
     /// # Panic
@@ -1118,45 +2716,40 @@ impl<TBl, TRq, TSrc> FinishAncestrySearch<TBl, TRq, TSrc> {
         scale_encoded_justifications: impl Iterator<Item = ([u8; 4], impl AsRef<[u8]>)>,
     ) -> Result<AddBlock<TBl, TRq, TSrc>, (AncestrySearchResponseError, AllForksSync<TBl, TRq, TSrc>)>
     {
-        // Compare expected with actual hash.
-        // This ensure that each header being processed is the parent of the previous one.
-        if self.expected_next_hash != header::hash_from_scale_encoded_header(scale_encoded_header) {
-            return Err((AncestrySearchResponseError::UnexpectedBlock, self.finish()));
-        }
-
-        // Invalid headers are erroneous.
-        let decoded_header = match header::decode(scale_encoded_header) {
+        let decoded_header = match self.inner.check_ancestry_search_header(
+            scale_encoded_header,
+            self.index_in_response,
+            &self.expected_next_hash,
+            self.expected_next_height,
+        ) {
             Ok(h) => h,
-            Err(err) => {
-                return Err((
-                    AncestrySearchResponseError::InvalidHeader(err),
-                    self.finish(),
-                ))
+            Err(error) => {
+                // Oversized and self-parent headers are necessarily malformed, and the source
+                // is flagged as misbehaving.
+                if scale_encoded_header.len() > self.inner.inner.max_header_size
+                    || matches!(error, AncestrySearchResponseError::SelfParent)
+                {
+                    self.inner.inner.blocks[self.source_id].misbehaved = true;
+                }
+
+                // Blocks that are too old or not part of the finalized chain are nonetheless
+                // correct, and the response as a whole is considered as useful.
+                if matches!(
+                    error,
+                    AncestrySearchResponseError::TooOld
+                        | AncestrySearchResponseError::NotFinalizedChain { .. }
+                ) {
+                    self.any_progress = true;
+                }
+
+                return Err((error, self.finish()));
             }
         };
 
-        // Also compare the block numbers.
-        // The utility of checking the height (even though we've already checked the hash) is
-        // questionable, but considering that blocks are identified with their combination of
-        // hash and number, checking both the hash and number might prevent malicious sources
-        // from introducing state inconsistenties, even though it's unclear how that could happen.
-        if self.expected_next_height != decoded_header.number {
-            return Err((AncestrySearchResponseError::UnexpectedBlock, self.finish()));
-        }
-
         // At this point, the source has given us correct blocks, and we consider the response
         // as a whole to be useful.
         self.any_progress = true;
 
-        // It is assumed that all sources will eventually agree on the same finalized chain. If
-        // the block number is lower or equal than the locally-finalized block number, it is
-        // assumed that this source is simply late compared to the local node, and that the block
-        // that has been received is either part of the finalized chain or belongs to a fork that
-        // will get discarded by this source in the future.
-        if decoded_header.number <= self.inner.chain.finalized_block_header().number {
-            return Err((AncestrySearchResponseError::TooOld, self.finish()));
-        }
-
         // If the block is already part of the local tree of blocks, nothing more to do.
         if self
             .inner
@@ -1170,23 +2763,6 @@ impl<TBl, TRq, TSrc> FinishAncestrySearch<TBl, TRq, TSrc> {
             }));
         }
 
-        // Block is not part of the finalized chain.
-        // TODO: also give possibility to update user data
-        if decoded_header.number == self.inner.chain.finalized_block_header().number + 1
-            && *decoded_header.parent_hash != self.inner.chain.finalized_block_hash()
-        {
-            // TODO: remove_verify_failed
-            // Block isn't part of the finalized chain.
-            // This doesn't necessarily mean that the source and the local node disagree
-            // on the finalized chain. It is possible that the finalized block has been
-            // updated between the moment the request was emitted and the moment the
-            // response is received.
-            let error = AncestrySearchResponseError::NotFinalizedChain {
-                discarded_unverified_block_headers: Vec::new(), // TODO: not properly implemented /!\
-            };
-            return Err((error, self.finish()));
-        }
-
         // At this point, we have excluded blocks that are already part of the chain or too old.
         // We insert the block in the list of unverified blocks so as to treat all blocks the
         // same.
@@ -1299,12 +2875,25 @@ impl<TBl, TRq, TSrc> AddBlockOccupied<TBl, TRq, TSrc> {
             self.inner.expected_next_hash,
         );
 
-        // Source also knows the parent of the announced block.
-        // TODO: do this for the entire chain of blocks if it is known locally?
-        self.inner.inner.inner.blocks.add_known_block_to_source(
+        // Source also knows the parent of the announced block, and all of its ancestors that
+        // are known locally.
+        let known_ancestry = self
+            .inner
+            .inner
+            .inner
+            .blocks
+            .unverified_block_ancestry(
+                self.decoded_header.number - 1,
+                &self.decoded_header.parent_hash,
+            )
+            .collect::<Vec<_>>();
+        self.inner.inner.inner.blocks.add_known_blocks_to_source(
             self.inner.source_id,
-            self.decoded_header.number - 1,
-            self.decoded_header.parent_hash,
+            iter::once((
+                self.decoded_header.number - 1,
+                self.decoded_header.parent_hash,
+            ))
+            .chain(known_ancestry),
         );
 
         let former_user_data = if self.is_verified {
@@ -1384,12 +2973,25 @@ impl<TBl, TRq, TSrc> AddBlockVacant<TBl, TRq, TSrc> {
             self.inner.expected_next_hash,
         );
 
-        // Source also knows the parent of the announced block.
-        // TODO: do this for the entire chain of blocks if it is known locally?
-        self.inner.inner.inner.blocks.add_known_block_to_source(
+        // Source also knows the parent of the announced block, and all of its ancestors that
+        // are known locally.
+        let known_ancestry = self
+            .inner
+            .inner
+            .inner
+            .blocks
+            .unverified_block_ancestry(
+                self.decoded_header.number - 1,
+                &self.decoded_header.parent_hash,
+            )
+            .collect::<Vec<_>>();
+        self.inner.inner.inner.blocks.add_known_blocks_to_source(
             self.inner.source_id,
-            self.decoded_header.number - 1,
-            self.decoded_header.parent_hash,
+            iter::once((
+                self.decoded_header.number - 1,
+                self.decoded_header.parent_hash,
+            ))
+            .chain(known_ancestry),
         );
 
         self.inner.inner.inner.blocks.insert_unverified_block(
@@ -1471,6 +3073,43 @@ impl<TBl, TRq, TSrc> AddBlockVacant<TBl, TRq, TSrc> {
     }
 }
 
+/// Outcome of calling [`AllForksSync::queue_block_announce`].
+#[derive(Debug)]
+pub enum QueueBlockAnnounceOutcome {
+    /// The announce has been queued.
+    Queued,
+    /// The announce has been queued, and a less valuable announce has been discarded in order
+    /// to make space for it.
+    QueuedWithEviction,
+    /// The queue is full and all the queued announces are more valuable than this one. The
+    /// announce has been discarded.
+    Discarded,
+    /// Failed to decode the announced header. The announce has been discarded.
+    InvalidHeader(header::Error),
+}
+
+/// Error potentially returned by [`AllForksSync::queue_sibling_block_announces`].
+#[derive(Debug, derive_more::Display)]
+pub enum SiblingBlockAnnouncesError {
+    /// Failed to decode one of the announced headers.
+    #[display(fmt = "Failed to decode header #{}: {}", index, error)]
+    InvalidHeader {
+        /// Index within the list of announces of the invalid header.
+        index: usize,
+        /// Decoding error.
+        error: header::Error,
+    },
+    /// One of the announced headers doesn't have the same height as the first one.
+    #[display(
+        fmt = "Header #{} doesn't have the same height as the first header",
+        index
+    )]
+    HeightMismatch {
+        /// Index within the list of announces of the faulty header.
+        index: usize,
+    },
+}
+
 /// Outcome of calling [`AllForksSync::block_announce`].
 pub enum BlockAnnounceOutcome<'a, TBl, TRq, TSrc> {
     /// Announced block is too old to be part of the finalized chain.
@@ -1487,6 +3126,10 @@ pub enum BlockAnnounceOutcome<'a, TBl, TRq, TSrc> {
         announce_block_height: u64,
         /// Height of the currently finalized block.
         finalized_block_height: u64,
+        /// `true` if the source has announced too old best blocks at least
+        /// [`Config::max_consecutive_too_old_best_announces`] times in a row. The API user is
+        /// encouraged to consider the source as misbehaving, and for example disconnect from it.
+        persistently_too_old: bool,
     },
 
     /// Announced block has already been successfully verified and is part of the non-finalized
@@ -1501,6 +3144,15 @@ pub enum BlockAnnounceOutcome<'a, TBl, TRq, TSrc> {
 
     /// Failed to decode announce header.
     InvalidHeader(header::Error),
+
+    /// Announced header indicates itself as its own parent. The source has been flagged as
+    /// misbehaving. See [`AllForksSync::source_misbehaved`].
+    SelfParent,
+
+    /// Announced block is unknown, and the source is saturated. The state machine has recorded
+    /// that the source knows this block, but the block hasn't been inserted. See
+    /// [`Config::source_saturation_threshold`].
+    SourceSaturated,
 }
 
 /// See [`BlockAnnounceOutcome`] and [`AllForksSync::block_announce`].
@@ -1706,12 +3358,20 @@ pub enum AncestrySearchResponseError {
     /// Failed to decode block header.
     InvalidHeader(header::Error),
 
-    /// Provided block isn't a block that we expect to be added.
+    /// Block header indicates itself as its own parent. The source has been flagged as
+    /// misbehaving. See [`AllForksSync::source_misbehaved`].
+    SelfParent,
+
+    /// First block of the response doesn't correspond to the block that has been requested.
     ///
-    /// If this is the first block, then it doesn't correspond to the block that has been
-    /// requested. If this is not the first block, then it doesn't correspond to the parent of
-    /// the previous block that has been added.
-    UnexpectedBlock,
+    /// The source has answered a different request than the one that was sent. Since a request
+    /// is only ever sent to a source that is known to know the requested block, this is a
+    /// stronger sign of misbehavior than [`AncestrySearchResponseError::UnexpectedNextBlock`].
+    UnexpectedFirstBlock,
+
+    /// Block of the response other than the first one doesn't correspond to the parent of the
+    /// previous block that has been added.
+    UnexpectedNextBlock,
 
     /// The block height is equal to the locally-known finalized block height, but its hash isn't
     /// the same.
@@ -1736,6 +3396,30 @@ pub enum AncestrySearchResponseError {
     TooOld,
 }
 
+/// Error when calling [`AllForksSync::report_equivocation`].
+#[derive(Debug, derive_more::Display)]
+pub enum EquivocationProofError {
+    /// Both hashes designate the same block.
+    SameBlock,
+    /// One of the blocks has already been verified and can't be removed from the chain.
+    AlreadyVerified,
+    /// One of the blocks isn't in the list of unverified blocks, or isn't known to the source.
+    UnknownBlock,
+    /// The header of one of the blocks isn't known yet.
+    HeaderUnknown,
+    /// The parent of one of the blocks hasn't been verified yet.
+    ParentNotVerified,
+    /// Failed to verify the header of one of the blocks.
+    #[display(fmt = "{}", _0)]
+    InvalidHeader(blocks_tree::HeaderVerifyError),
+    /// The two blocks don't belong to the same slot.
+    SlotMismatch,
+    /// The two blocks haven't been authored by the same authority.
+    AuthorityMismatch,
+    /// The blocks don't use a consensus algorithm whose equivocations can be detected.
+    UnsupportedConsensus,
+}
+
 /// Outcome of calling [`AllForksSync::prepare_add_source`].
 #[must_use]
 pub enum AddSource<'a, TBl, TRq, TSrc> {
@@ -1775,6 +3459,9 @@ impl<'a, TBl, TRq, TSrc> AddSourceOldBlock<'a, TBl, TRq, TSrc> {
                 user_data: source_user_data,
                 unverified_finality_proofs: SourcePendingJustificationProofs::None,
                 pending_finality_proofs: SourcePendingJustificationProofs::None,
+                misbehaved: false,
+                num_consecutive_too_old_best_announces: 0,
+                stats: SourceStats::default(),
             },
             self.best_block_number,
             self.best_block_hash,
@@ -1821,6 +3508,9 @@ impl<'a, TBl, TRq, TSrc> AddSourceKnown<'a, TBl, TRq, TSrc> {
                 user_data: source_user_data,
                 unverified_finality_proofs: SourcePendingJustificationProofs::None,
                 pending_finality_proofs: SourcePendingJustificationProofs::None,
+                misbehaved: false,
+                num_consecutive_too_old_best_announces: 0,
+                stats: SourceStats::default(),
             },
             self.best_block_number,
             self.best_block_hash,
@@ -1856,6 +3546,9 @@ impl<'a, TBl, TRq, TSrc> AddSourceUnknown<'a, TBl, TRq, TSrc> {
                 user_data: source_user_data,
                 unverified_finality_proofs: SourcePendingJustificationProofs::None,
                 pending_finality_proofs: SourcePendingJustificationProofs::None,
+                misbehaved: false,
+                num_consecutive_too_old_best_announces: 0,
+                stats: SourceStats::default(),
             },
             self.best_block_number,
             self.best_block_hash,
@@ -1948,6 +3641,11 @@ impl<TBl, TRq, TSrc> HeaderVerify<TBl, TRq, TSrc> {
                 };
                 insert.insert(block);
 
+                self.parent
+                    .inner
+                    .recent_verifications
+                    .record(now_from_unix_epoch);
+
                 // Because a new block is now in the chain, all the previously-unverifiable
                 // finality proofs might have now become verifiable.
                 // TODO: this way of doing it is correct but quite inefficient
@@ -1969,7 +3667,12 @@ impl<TBl, TRq, TSrc> HeaderVerify<TBl, TRq, TSrc> {
                     &self.block_to_verify.block_hash,
                 );
 
-                Err(HeaderVerifyError::VerificationFailed(error))
+                match error {
+                    verify::header_only::Error::TooFarInFuture { slot, max_allowed } => {
+                        Err(HeaderVerifyError::TooFarInFuture { slot, max_allowed })
+                    }
+                    error => Err(HeaderVerifyError::VerificationFailed(error)),
+                }
             }
             Err(blocks_tree::HeaderVerifyError::ConsensusMismatch) => {
                 // Remove the block from `pending_blocks`.
@@ -2023,16 +3726,124 @@ pub struct FinalityProofVerify<TBl, TRq, TSrc> {
     source_id: SourceId,
     /// Justification and its consensus engine id, or commit that can be verified.
     finality_proof_to_verify: FinalityProof,
+    /// Hash of the block finalized by the finality proof, if it has already been successfully
+    /// verified by [`FinalityProofVerify::verify_only`].
+    verified_block_hash: Option<[u8; 32]>,
 }
 
 impl<TBl, TRq, TSrc> FinalityProofVerify<TBl, TRq, TSrc> {
-    /// Perform the verification.
+    /// Verifies the finality proof without applying it.
+    ///
+    /// The state machine is left untouched. In particular, the finalized block isn't updated,
+    /// and finality proofs that can't be verified yet aren't stored for later.
+    ///
+    /// If the finality proof is valid, calling [`FinalityProofVerify::apply`] afterwards
+    /// finalizes the block without verifying the proof a second time.
+    pub fn verify_only(&mut self) -> FinalityProofCheckOutcome {
+        let outcome = match &self.finality_proof_to_verify {
+            FinalityProof::GrandpaCommit(scale_encoded_commit) => {
+                match self
+                    .parent
+                    .chain
+                    .verify_grandpa_commit_message(scale_encoded_commit)
+                {
+                    Ok(success) => FinalityProofCheckOutcome::Valid {
+                        finalized_block_height: success.block_height(),
+                        finalized_block_hash: *success.block_hash(),
+                    },
+                    Err(blocks_tree::CommitVerifyError::FinalityVerify(
+                        blocks_tree::FinalityVerifyError::EqualToFinalized
+                        | blocks_tree::FinalityVerifyError::BelowFinalized,
+                    )) => FinalityProofCheckOutcome::AlreadyFinalized,
+                    Err(
+                        blocks_tree::CommitVerifyError::FinalityVerify(
+                            blocks_tree::FinalityVerifyError::UnknownTargetBlock { .. }
+                            | blocks_tree::FinalityVerifyError::TooFarAhead { .. },
+                        )
+                        | blocks_tree::CommitVerifyError::NotEnoughKnownBlocks { .. },
+                    ) => FinalityProofCheckOutcome::Pending,
+                    Err(err) => FinalityProofCheckOutcome::GrandpaCommitError(err),
+                }
+            }
+            FinalityProof::Justification((consensus_engine_id, scale_encoded_justification)) => {
+                match self
+                    .parent
+                    .chain
+                    .verify_justification(*consensus_engine_id, scale_encoded_justification)
+                {
+                    Ok(success) => FinalityProofCheckOutcome::Valid {
+                        finalized_block_height: success.block_height(),
+                        finalized_block_hash: *success.block_hash(),
+                    },
+                    Err(blocks_tree::JustificationVerifyError::FinalityVerify(
+                        blocks_tree::FinalityVerifyError::EqualToFinalized
+                        | blocks_tree::FinalityVerifyError::BelowFinalized,
+                    )) => FinalityProofCheckOutcome::AlreadyFinalized,
+                    Err(blocks_tree::JustificationVerifyError::FinalityVerify(
+                        blocks_tree::FinalityVerifyError::UnknownTargetBlock { .. }
+                        | blocks_tree::FinalityVerifyError::TooFarAhead { .. },
+                    )) => FinalityProofCheckOutcome::Pending,
+                    Err(err) => FinalityProofCheckOutcome::JustificationError(err),
+                }
+            }
+        };
+
+        self.verified_block_hash = match &outcome {
+            FinalityProofCheckOutcome::Valid {
+                finalized_block_hash,
+                ..
+            } => Some(*finalized_block_hash),
+            _ => None,
+        };
+
+        outcome
+    }
+
+    /// Applies the finality proof.
+    ///
+    /// If [`FinalityProofVerify::verify_only`] has previously returned
+    /// [`FinalityProofCheckOutcome::Valid`], the block is finalized without verifying the proof
+    /// again. Otherwise, this is equivalent to [`FinalityProofVerify::perform`].
+    pub fn apply(
+        self,
+    ) -> (
+        AllForksSync<TBl, TRq, TSrc>,
+        FinalityProofVerifyOutcome<TBl>,
+    ) {
+        self.perform()
+    }
+
+    /// Perform the verification, then apply the finality proof.
+    ///
+    /// The verification is skipped if [`FinalityProofVerify::verify_only`] has previously
+    /// returned [`FinalityProofCheckOutcome::Valid`].
     pub fn perform(
         mut self,
     ) -> (
         AllForksSync<TBl, TRq, TSrc>,
         FinalityProofVerifyOutcome<TBl>,
     ) {
+        if let Some(block_hash) = self.verified_block_hash {
+            // The block has been verified to be in the chain by `verify_only`.
+            let finalized_blocks_iter = self.parent.chain.set_finalized_block(&block_hash).unwrap();
+            let updates_best_block = finalized_blocks_iter.updates_best_block();
+            let finalized_blocks = finalized_blocks_iter
+                .map(|b| (b.header, b.user_data))
+                .collect::<Vec<_>>();
+            let finalized_block_justifications = match self.finality_proof_to_verify {
+                FinalityProof::GrandpaCommit(_) => Vec::new(),
+                FinalityProof::Justification(justification) => vec![justification],
+            };
+            let finalized_height = self.parent.chain.finalized_block_header().number;
+            let outcome = self.parent.inner.on_finalized(
+                finalized_height,
+                finalized_blocks,
+                updates_best_block,
+                finalized_block_justifications,
+            );
+            return (self.parent, outcome);
+        }
+
         let outcome = match self.finality_proof_to_verify {
             FinalityProof::GrandpaCommit(scale_encoded_commit) => {
                 match self
@@ -2041,20 +3852,18 @@ impl<TBl, TRq, TSrc> FinalityProofVerify<TBl, TRq, TSrc> {
                     .verify_grandpa_commit_message(&scale_encoded_commit)
                 {
                     Ok(success) => {
-                        // TODO: DRY
                         let finalized_blocks_iter = success.apply();
                         let updates_best_block = finalized_blocks_iter.updates_best_block();
                         let finalized_blocks = finalized_blocks_iter
                             .map(|b| (b.header, b.user_data))
                             .collect::<Vec<_>>();
-                        self.parent
-                            .inner
-                            .blocks
-                            .set_finalized_block_height(finalized_blocks.last().unwrap().0.number);
-                        FinalityProofVerifyOutcome::NewFinalized {
+                        let finalized_height = self.parent.chain.finalized_block_header().number;
+                        self.parent.inner.on_finalized(
+                            finalized_height,
                             finalized_blocks,
                             updates_best_block,
-                        }
+                            Vec::new(),
+                        )
                     }
                     // In case where the commit message concerns a block older or equal to the
                     // finalized block, the operation is silently considered successful.
@@ -2102,14 +3911,13 @@ impl<TBl, TRq, TSrc> FinalityProofVerify<TBl, TRq, TSrc> {
                         let finalized_blocks = finalized_blocks_iter
                             .map(|b| (b.header, b.user_data))
                             .collect::<Vec<_>>();
-                        self.parent
-                            .inner
-                            .blocks
-                            .set_finalized_block_height(finalized_blocks.last().unwrap().0.number);
-                        FinalityProofVerifyOutcome::NewFinalized {
+                        let finalized_height = self.parent.chain.finalized_block_header().number;
+                        self.parent.inner.on_finalized(
+                            finalized_height,
                             finalized_blocks,
                             updates_best_block,
-                        }
+                            vec![(consensus_engine_id, scale_encoded_justification)],
+                        )
                     }
                     // In case where the commit message concerns a block older or equal to the
                     // finalized block, the operation is silently considered successful.
@@ -2118,8 +3926,30 @@ impl<TBl, TRq, TSrc> FinalityProofVerify<TBl, TRq, TSrc> {
                         | blocks_tree::FinalityVerifyError::BelowFinalized,
                     )) => FinalityProofVerifyOutcome::AlreadyFinalized,
 
-                    // Note that, contrary to commits, there's no such thing as a justification
-                    // that can't be verified yet.
+                    // The justification targets a block that isn't known yet, or a block that
+                    // can't be finalized before an earlier block that changes the authorities
+                    // set. This can legitimately happen if the justification was received before
+                    // the block that makes it verifiable. The justification is stored for later.
+                    Err(blocks_tree::JustificationVerifyError::FinalityVerify(
+                        blocks_tree::FinalityVerifyError::UnknownTargetBlock {
+                            block_number, ..
+                        }
+                        | blocks_tree::FinalityVerifyError::TooFarAhead {
+                            justification_block_number: block_number,
+                            ..
+                        },
+                    )) => {
+                        self.parent.inner.blocks[self.source_id]
+                            .pending_finality_proofs
+                            .insert(
+                                block_number,
+                                FinalityProofs::Justifications(vec![(
+                                    consensus_engine_id,
+                                    scale_encoded_justification,
+                                )]),
+                            );
+                        FinalityProofVerifyOutcome::JustificationPending
+                    }
                     Err(err) => FinalityProofVerifyOutcome::JustificationError(err),
                 }
             }
@@ -2146,6 +3976,29 @@ pub enum ProcessOne<TBl, TRq, TSrc> {
         sync: AllForksSync<TBl, TRq, TSrc>,
     },
 
+    /// Some of the announces queued with [`AllForksSync::queue_block_announce`] have been
+    /// processed.
+    ///
+    /// [`AllForksSync::process_one`] should be called again.
+    BlockAnnouncesProcessed {
+        /// The state machine.
+        /// The [`AllForksSync::process_one`] method takes ownership of the [`AllForksSync`]. This
+        /// field yields it back.
+        sync: AllForksSync<TBl, TRq, TSrc>,
+    },
+
+    /// Headers are ready for verification, but they are too far ahead of the finalized block.
+    /// See [`Config::max_blocks_ahead_of_finalized`].
+    ///
+    /// Calling [`AllForksSync::process_one`] again is unnecessary until a new block has been
+    /// finalized.
+    WaitingForFinality {
+        /// The state machine.
+        /// The [`AllForksSync::process_one`] method takes ownership of the [`AllForksSync`]. This
+        /// field yields it back.
+        sync: AllForksSync<TBl, TRq, TSrc>,
+    },
+
     /// A header is ready for verification.
     HeaderVerify(HeaderVerify<TBl, TRq, TSrc>),
 
@@ -2153,6 +4006,47 @@ pub enum ProcessOne<TBl, TRq, TSrc> {
     FinalityProofVerify(FinalityProofVerify<TBl, TRq, TSrc>),
 }
 
+/// Counters of the requests of a source. See [`AllForksSync::source_stats`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SourceStats {
+    /// Number of requests passed to [`AllForksSync::finish_ancestry_search`] that weren't
+    /// obsolete. Requests are counted even if the blocks of the response turn out to be invalid.
+    pub num_requests_succeeded: u64,
+    /// Number of requests passed to [`AllForksSync::ancestry_search_failed`] that weren't
+    /// obsolete.
+    pub num_requests_failed: u64,
+    /// Number of requests that were obsolete when they have been finished, no matter whether
+    /// they succeeded or failed. See [`AllForksSync::obsolete_requests`].
+    pub num_requests_obsolete: u64,
+}
+
+/// Summary of the work performed by [`AllForksSync::process_some`].
+#[derive(Debug)]
+pub struct ProcessSome<TBl> {
+    /// Number of verifications that have been performed.
+    pub num_steps: usize,
+
+    /// If `true`, there was nothing more to process. Calling [`AllForksSync::process_some`] or
+    /// [`AllForksSync::process_one`] again is unnecessary.
+    pub is_idle: bool,
+
+    /// Number of headers that have been successfully verified.
+    pub num_verified_headers: usize,
+
+    /// Number of headers whose verification has failed.
+    pub num_invalid_headers: usize,
+
+    /// `true` if the best block has been modified.
+    pub new_best: bool,
+
+    /// List of blocks that have been finalized, in decreasing block number within each
+    /// finality proof.
+    pub finalized_blocks: Vec<(header::Header, TBl)>,
+
+    /// Number of finality proofs whose verification has failed.
+    pub num_invalid_finality_proofs: usize,
+}
+
 /// Outcome of calling [`HeaderVerify::perform`].
 pub enum HeaderVerifyOutcome<TBl, TRq, TSrc> {
     /// Header has been successfully verified.
@@ -2182,6 +4076,19 @@ pub enum HeaderVerifyError {
     /// The block verification has failed. The block is invalid and should be thrown away.
     #[display(fmt = "{}", _0)]
     VerificationFailed(verify::header_only::Error),
+    /// The slot of the block is further in the future than allowed by
+    /// [`Config::aura_max_future_drift`].
+    #[display(
+        fmt = "Slot number {} is too far in the future. Maximum allowed: {}",
+        slot,
+        max_allowed
+    )]
+    TooFarInFuture {
+        /// Slot number of the block.
+        slot: u64,
+        /// Highest slot number that is currently accepted.
+        max_allowed: u64,
+    },
 }
 
 /// Information about the outcome of verifying a finality proof.
@@ -2197,11 +4104,53 @@ pub enum FinalityProofVerifyOutcome<TBl> {
         /// This can happen if the previous best block isn't a descendant of the now finalized
         /// block.
         updates_best_block: bool,
+        /// If `true`, at least one of the finalized blocks indicates in its header that it
+        /// modifies the runtime. The runtime of the finalized block is thus different from the
+        /// runtime of the previously-finalized block.
+        updates_finalized_runtime: bool,
     },
     /// Finality proof concerns block that was already finalized.
     AlreadyFinalized,
     /// GrandPa commit cannot be verified yet and has been stored for later.
     GrandpaCommitPending,
+    /// Justification cannot be verified yet and has been stored for later.
+    JustificationPending,
+    /// Problem while verifying justification.
+    JustificationError(blocks_tree::JustificationVerifyError),
+    /// Problem while verifying GrandPa commit.
+    GrandpaCommitError(blocks_tree::CommitVerifyError),
+}
+
+/// See [`AllForksSync::unverified_block_parent`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UnverifiedBlockParent {
+    /// The header of the block isn't known yet, and thus neither is the hash of its parent.
+    HashUnknown,
+    /// The parent is the finalized block or a verified non-finalized block. The block can be
+    /// verified once its header (and body, in full mode) is known.
+    Verified([u8; 32]),
+    /// The parent is itself in the list of unverified blocks.
+    Unverified([u8; 32]),
+    /// The hash of the parent is known, but the parent isn't known by the state machine. An
+    /// ancestry search is necessary in order to download it.
+    Unknown([u8; 32]),
+}
+
+/// Outcome of calling [`FinalityProofVerify::verify_only`].
+#[derive(Debug)]
+pub enum FinalityProofCheckOutcome {
+    /// The finality proof is valid. Applying it would finalize the given block and all its
+    /// ancestors.
+    Valid {
+        /// Height of the block that would be finalized.
+        finalized_block_height: u64,
+        /// Hash of the block that would be finalized.
+        finalized_block_hash: [u8; 32],
+    },
+    /// Finality proof concerns block that is already finalized.
+    AlreadyFinalized,
+    /// Finality proof can't be verified yet, for example because its target block isn't known.
+    Pending,
     /// Problem while verifying justification.
     JustificationError(blocks_tree::JustificationVerifyError),
     /// Problem while verifying GrandPa commit.
@@ -2250,3 +4199,1088 @@ pub enum BlockBodyVerify<TBl, TRq, TSrc> {
     /// order to continue.
     FinalizedStorageNextKey(StorageNextKey<TBl, TRq, TSrc>),*/
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AddBlock, AddSource, AllForksSync, AncestrySearchResponseError, BlockAnnounceOutcome,
+        Config, EquivocationProofError, FinalityProofVerifyOutcome, FinalityProofs,
+        HeaderVerifyError, HeaderVerifyOutcome, ProcessOne, QueueBlockAnnounceOutcome,
+        RequestParams, SiblingBlockAnnouncesError, SourcePendingJustificationProofs, SourceStats,
+        UnverifiedBlockParent,
+    };
+    use crate::{
+        chain::chain_information,
+        header,
+        sync::test_utils::{
+            all_forks_config, aura_authority, aura_consensus, aura_header, process_until_idle,
+            test_header, Event,
+        },
+    };
+    use core::{
+        iter,
+        num::{NonZeroU32, NonZeroU64},
+        time::Duration,
+    };
+
+    /// Builds an [`AllForksSync`] from the given configuration.
+    ///
+    /// Returns the state machine and its finalized block.
+    fn test_sync(
+        config: Config<iter::Empty<[u8; 32]>>,
+    ) -> (AllForksSync<(), (), ()>, header::Header) {
+        let genesis = config
+            .chain_information
+            .as_ref()
+            .finalized_block_header
+            .into();
+        (AllForksSync::new(config), genesis)
+    }
+
+    #[test]
+    fn justification_before_target_block_is_pending() {
+        let (mut sync, genesis) = test_sync(all_forks_config(
+            chain_information::ChainInformationConsensus::Unknown,
+        ));
+
+        let source_id = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+
+        // Block #1, which will never successfully verify, but is enough to be targeted by a
+        // justification.
+        let block1 = test_header(genesis.hash(), 1, [0; 32]);
+        let block1_hash = block1.hash();
+
+        // Justification targeting block #1, without any precommit or vote ancestry.
+        let justification = {
+            let mut j = 1u64.to_le_bytes().to_vec();
+            j.extend_from_slice(&block1_hash);
+            j.extend_from_slice(&1u32.to_le_bytes());
+            j.extend_from_slice(&[0, 0]);
+            j
+        };
+
+        let request_id = sync.add_request(
+            source_id,
+            RequestParams {
+                first_block_height: 1,
+                first_block_hash: block1_hash,
+                num_blocks: NonZeroU64::new(1).unwrap(),
+            },
+            (),
+        );
+        let (_, response) = sync.finish_ancestry_search(request_id);
+        let sync = match response.add_block(
+            &block1.scale_encoding_vec(),
+            iter::once((*b"FRNK", &justification)),
+        ) {
+            Ok(AddBlock::UnknownBlock(vacant)) => vacant.insert(()).finish(),
+            _ => panic!(),
+        };
+
+        // The justification is verified before the header, and must be considered as pending
+        // rather than erroneous.
+        let sync = match sync.process_one() {
+            ProcessOne::FinalityProofVerify(verify) => match verify.perform() {
+                (sync, FinalityProofVerifyOutcome::JustificationPending) => sync,
+                _ => panic!(),
+            },
+            _ => panic!(),
+        };
+
+        // The justification is no longer proposed for verification until a new block is added.
+        match sync.process_one() {
+            ProcessOne::HeaderVerify(verify) => {
+                assert_eq!(*verify.hash(), block1_hash);
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn queued_announces_evict_least_valuable() {
+        let (mut sync, genesis) = test_sync(Config {
+            max_queued_block_announces: 1,
+            ..all_forks_config(chain_information::ChainInformationConsensus::Unknown)
+        });
+
+        let source_id = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+
+        let block1 = test_header(genesis.hash(), 1, [0; 32]);
+        let block1_hash = block1.hash();
+
+        // Block whose parent is unknown.
+        let block5 = test_header([1; 32], 5, [0; 32]);
+
+        assert!(matches!(
+            sync.queue_block_announce(source_id, block5.scale_encoding_vec(), true, ()),
+            QueueBlockAnnounceOutcome::Queued
+        ));
+        assert!(matches!(
+            sync.queue_block_announce(source_id, block1.scale_encoding_vec(), true, ()),
+            QueueBlockAnnounceOutcome::QueuedWithEviction
+        ));
+        assert!(matches!(
+            sync.queue_block_announce(source_id, block5.scale_encoding_vec(), true, ()),
+            QueueBlockAnnounceOutcome::Discarded
+        ));
+
+        let sync = match sync.process_one() {
+            ProcessOne::BlockAnnouncesProcessed { sync } => sync,
+            _ => panic!(),
+        };
+        match sync.process_one() {
+            ProcessOne::HeaderVerify(verify) => {
+                assert_eq!(*verify.hash(), block1_hash);
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn queued_announces_processed_in_batches() {
+        let num_announces = super::QUEUED_BLOCK_ANNOUNCES_BATCH + 3;
+        let (mut sync, genesis) = test_sync(Config {
+            max_queued_block_announces: num_announces,
+            ..all_forks_config(chain_information::ChainInformationConsensus::Unknown)
+        });
+
+        let source_id = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+
+        // Blocks whose parent is unknown, in order to not have anything to verify.
+        for number in 0..num_announces {
+            let header = test_header([1; 32], 10 + u64::try_from(number).unwrap(), [0; 32]);
+            assert!(matches!(
+                sync.queue_block_announce(source_id, header.scale_encoding_vec(), false, ()),
+                QueueBlockAnnounceOutcome::Queued
+            ));
+        }
+
+        let sync = match sync.process_one() {
+            ProcessOne::BlockAnnouncesProcessed { sync } => sync,
+            _ => panic!(),
+        };
+        assert_eq!(sync.inner.queued_block_announces.len(), 3);
+
+        let sync = match sync.process_one() {
+            ProcessOne::BlockAnnouncesProcessed { sync } => sync,
+            _ => panic!(),
+        };
+        assert!(sync.inner.queued_block_announces.is_empty());
+
+        assert!(matches!(sync.process_one(), ProcessOne::AllSync { .. }));
+    }
+
+    #[test]
+    fn sibling_announces_recorded() {
+        let (mut sync, genesis) = test_sync(Config {
+            max_queued_block_announces: 4,
+            ..all_forks_config(chain_information::ChainInformationConsensus::Unknown)
+        });
+
+        let source_id = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+
+        let block1a = test_header(genesis.hash(), 1, [0; 32]);
+        let block1b = test_header(genesis.hash(), 1, [1; 32]);
+        let block2 = test_header(block1a.hash(), 2, [0; 32]);
+
+        assert!(matches!(
+            sync.queue_sibling_block_announces(
+                source_id,
+                [
+                    (block1a.scale_encoding_vec(), false, ()),
+                    (block2.scale_encoding_vec(), true, ()),
+                ]
+            ),
+            Err(SiblingBlockAnnouncesError::HeightMismatch { index: 1 })
+        ));
+        assert_eq!(sync.contested_heights().count(), 0);
+
+        let outcomes = sync
+            .queue_sibling_block_announces(
+                source_id,
+                [
+                    (block1a.scale_encoding_vec(), true, ()),
+                    (block1b.scale_encoding_vec(), false, ()),
+                ],
+            )
+            .unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes
+            .iter()
+            .all(|outcome| matches!(outcome, QueueBlockAnnounceOutcome::Queued)));
+
+        assert_eq!(sync.contested_heights().collect::<Vec<_>>(), vec![(1, 2)]);
+        assert_eq!(
+            sync.block_siblings(1, &block1a.hash()).collect::<Vec<_>>(),
+            vec![&block1b.hash()]
+        );
+        assert_eq!(sync.block_siblings(1, &[0xff; 32]).count(), 0);
+    }
+
+    #[test]
+    fn discarded_sibling_announces_not_recorded() {
+        let (mut sync, genesis) = test_sync(Config {
+            max_queued_block_announces: 2,
+            ..all_forks_config(chain_information::ChainInformationConsensus::Unknown)
+        });
+
+        let source_id = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+
+        let block1a = test_header(genesis.hash(), 1, [0; 32]);
+        let block1b = test_header(genesis.hash(), 1, [1; 32]);
+        let block1c = test_header(genesis.hash(), 1, [2; 32]);
+
+        // The queue is full after the first two announces.
+        let outcomes = sync
+            .queue_sibling_block_announces(
+                source_id,
+                [
+                    (block1a.scale_encoding_vec(), true, ()),
+                    (block1b.scale_encoding_vec(), false, ()),
+                    (block1c.scale_encoding_vec(), false, ()),
+                ],
+            )
+            .unwrap();
+        assert!(matches!(
+            &outcomes[..],
+            [
+                QueueBlockAnnounceOutcome::Queued,
+                QueueBlockAnnounceOutcome::Queued,
+                QueueBlockAnnounceOutcome::Discarded
+            ]
+        ));
+        assert_eq!(sync.contested_heights().collect::<Vec<_>>(), vec![(1, 2)]);
+        assert_eq!(
+            sync.block_siblings(1, &block1a.hash()).collect::<Vec<_>>(),
+            vec![&block1b.hash()]
+        );
+        assert_eq!(sync.block_siblings(1, &block1c.hash()).count(), 0);
+
+        // If fewer than two announces are queued, nothing is recorded.
+        let block2a = test_header(block1a.hash(), 2, [0; 32]);
+        let block2b = test_header(block1a.hash(), 2, [1; 32]);
+        let outcomes = sync
+            .queue_sibling_block_announces(
+                source_id,
+                [
+                    (block2a.scale_encoding_vec(), true, ()),
+                    (block2b.scale_encoding_vec(), false, ()),
+                ],
+            )
+            .unwrap();
+        assert!(outcomes
+            .iter()
+            .all(|outcome| matches!(outcome, QueueBlockAnnounceOutcome::Discarded)));
+        assert_eq!(sync.contested_heights().collect::<Vec<_>>(), vec![(1, 2)]);
+    }
+
+    #[test]
+    fn reissue_failed_ancestry_search_prefers_other_source() {
+        let (mut sync, _) = test_sync(all_forks_config(
+            chain_information::ChainInformationConsensus::Unknown,
+        ));
+        let block1_hash = [1; 32];
+
+        let source1 = match sync.prepare_add_source(1, block1_hash) {
+            AddSource::UnknownBestBlock(add) => add.add_source_and_insert_block((), ()),
+            _ => unreachable!(),
+        };
+        let source2 = match sync.prepare_add_source(1, block1_hash) {
+            AddSource::BestBlockPendingVerification(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+
+        let params = RequestParams {
+            first_block_height: 1,
+            first_block_hash: block1_hash,
+            num_blocks: NonZeroU64::new(1).unwrap(),
+        };
+        let request_id = sync.add_request(source1, params, ());
+
+        assert_eq!(
+            sync.reissue_failed_ancestry_search(request_id),
+            Some((source2, params))
+        );
+
+        // If the failed source is the only one to know the block, there is no replacement.
+        let _ = sync.remove_source(source2);
+        assert_eq!(sync.reissue_failed_ancestry_search(request_id), None);
+    }
+
+    #[test]
+    fn report_equivocation_bans_double_signed_blocks() {
+        let authority = aura_authority();
+
+        let (mut sync, genesis) = test_sync(all_forks_config(aura_consensus()));
+
+        // Blocks `1a` and `1b` are signed by the same authority during the same slot.
+        let block1a = aura_header(&genesis, 1, [1; 32], &authority);
+        let block1b = aura_header(&genesis, 1, [2; 32], &authority);
+        let block1c = aura_header(&genesis, 2, [3; 32], &authority);
+
+        let source_id = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+        let other_source_id = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+
+        for block in [&block1a, &block1b, &block1c] {
+            match sync.block_announce(source_id, block.scale_encoding_vec(), false) {
+                BlockAnnounceOutcome::Unknown(unknown) => unknown.insert_and_update_source(()),
+                _ => unreachable!(),
+            }
+        }
+
+        let now = Duration::from_secs(12);
+
+        // The proof must come from a source that knows the blocks.
+        assert!(matches!(
+            sync.report_equivocation(
+                other_source_id,
+                (1, &block1a.hash()),
+                (1, &block1b.hash()),
+                now
+            ),
+            Err(EquivocationProofError::UnknownBlock)
+        ));
+        assert!(matches!(
+            sync.report_equivocation(source_id, (1, &block1a.hash()), (1, &block1c.hash()), now),
+            Err(EquivocationProofError::SlotMismatch)
+        ));
+
+        assert_eq!(
+            sync.report_equivocation(source_id, (1, &block1a.hash()), (1, &block1b.hash()), now)
+                .unwrap(),
+            authority.public.to_bytes()
+        );
+        assert_eq!(
+            sync.equivocating_authorities().collect::<Vec<_>>(),
+            vec![&authority.public.to_bytes()]
+        );
+        assert!(!sync.source_misbehaved(source_id));
+
+        // Only the block that isn't part of the equivocation is verified.
+        let (mut sync, events) = process_until_idle(sync, now, |_| None);
+        assert_eq!(events, vec![Event::NewBest(1, block1c.hash())]);
+        assert!(!sync.chain.contains_non_finalized_block(&block1a.hash()));
+        assert!(!sync.chain.contains_non_finalized_block(&block1b.hash()));
+
+        // Verified blocks can't be removed from the chain and are refused.
+        assert!(matches!(
+            sync.report_equivocation(source_id, (1, &block1c.hash()), (1, &block1a.hash()), now),
+            Err(EquivocationProofError::AlreadyVerified)
+        ));
+    }
+
+    #[test]
+    fn process_some_is_bounded() {
+        let authority = aura_authority();
+        let (mut sync, genesis) = test_sync(all_forks_config(aura_consensus()));
+
+        let block1 = aura_header(&genesis, 1, [0; 32], &authority);
+        let block2 = aura_header(&block1, 2, [0; 32], &authority);
+        let block3 = aura_header(&block2, 3, [0; 32], &authority);
+
+        let source_id = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+        for block in [&block1, &block2, &block3] {
+            match sync.block_announce(source_id, block.scale_encoding_vec(), true) {
+                BlockAnnounceOutcome::Unknown(unknown) => unknown.insert_and_update_source(()),
+                _ => unreachable!(),
+            }
+        }
+
+        let now = Duration::from_secs(18);
+
+        // Only two of the three blocks are verified.
+        let (sync, summary) = sync.process_some(2, now);
+        assert_eq!(summary.num_steps, 2);
+        assert_eq!(summary.num_verified_headers, 2);
+        assert!(summary.new_best);
+        assert!(!summary.is_idle);
+        assert_eq!(sync.best_block_hash(), block2.hash());
+
+        // The last block is verified, after which there is nothing left to process.
+        let (sync, summary) = sync.process_some(10, now);
+        assert_eq!(summary.num_steps, 1);
+        assert_eq!(summary.num_verified_headers, 1);
+        assert_eq!(summary.num_invalid_headers, 0);
+        assert!(summary.is_idle);
+        assert_eq!(sync.best_block_hash(), block3.hash());
+    }
+
+    #[test]
+    fn block_too_far_in_future() {
+        let authority = aura_authority();
+        let (mut sync, genesis) = test_sync(all_forks_config(aura_consensus()));
+
+        // With a slot duration of 6 seconds and a drift of 30 seconds, the highest slot accepted
+        // at 60 seconds is slot 15.
+        let block1 = aura_header(&genesis, 16, [0; 32], &authority);
+
+        let source_id = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+        match sync.block_announce(source_id, block1.scale_encoding_vec(), true) {
+            BlockAnnounceOutcome::Unknown(unknown) => unknown.insert_and_update_source(()),
+            _ => unreachable!(),
+        }
+
+        match sync.process_one() {
+            ProcessOne::HeaderVerify(verify) => match verify.perform(Duration::from_secs(60)) {
+                HeaderVerifyOutcome::Error {
+                    error:
+                        HeaderVerifyError::TooFarInFuture {
+                            slot: 16,
+                            max_allowed: 15,
+                        },
+                    ..
+                } => {}
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn persistently_too_old_best_announces() {
+        let (mut sync, genesis) = test_sync(Config {
+            max_consecutive_too_old_best_announces: NonZeroU32::new(2),
+            ..all_forks_config(chain_information::ChainInformationConsensus::Unknown)
+        });
+
+        let source_id = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+
+        // Block whose height is equal to the one of the finalized block.
+        let old_block = test_header([1; 32], 0, [0; 32]).scale_encoding_vec();
+        let new_block = test_header(genesis.hash(), 1, [0; 32]).scale_encoding_vec();
+
+        let announce = |sync: &mut AllForksSync<(), (), ()>, header: &[u8], is_best| match sync
+            .block_announce(source_id, header.to_vec(), is_best)
+        {
+            BlockAnnounceOutcome::TooOld {
+                persistently_too_old,
+                ..
+            } => Some(persistently_too_old),
+            BlockAnnounceOutcome::Unknown(unknown) => {
+                unknown.insert_and_update_source(());
+                None
+            }
+            _ => unreachable!(),
+        };
+
+        assert_eq!(announce(&mut sync, &old_block, true), Some(false));
+        assert_eq!(announce(&mut sync, &old_block, true), Some(true));
+        assert_eq!(announce(&mut sync, &old_block, true), Some(true));
+
+        // Non-best announces are never reported.
+        assert_eq!(announce(&mut sync, &old_block, false), Some(false));
+
+        // Announcing a more recent best block resets the counter.
+        assert_eq!(announce(&mut sync, &new_block, true), None);
+        assert_eq!(announce(&mut sync, &old_block, true), Some(false));
+    }
+
+    /// Builds a SCALE-encoded GrandPa commit, without any precommit, targeting the given block.
+    fn grandpa_commit(target_hash: [u8; 32], target_number: u32) -> Vec<u8> {
+        let mut commit = Vec::new();
+        commit.extend_from_slice(&1u64.to_le_bytes()); // Round number.
+        commit.extend_from_slice(&0u64.to_le_bytes()); // Authorities set id.
+        commit.extend_from_slice(&target_hash);
+        commit.extend_from_slice(&target_number.to_le_bytes());
+        commit.push(0); // No precommit.
+        commit.push(0); // No signature.
+        commit
+    }
+
+    #[test]
+    fn pending_grandpa_commits_cap() {
+        let (mut sync, genesis) = test_sync(Config {
+            max_pending_grandpa_commits: 1,
+            ..all_forks_config(chain_information::ChainInformationConsensus::Unknown)
+        });
+
+        let source1 = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+        let source2 = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+
+        let pending_commit = |sync: &AllForksSync<(), (), ()>, source_id| match &sync.inner.blocks
+            [source_id]
+            .pending_finality_proofs
+        {
+            SourcePendingJustificationProofs::None => None,
+            SourcePendingJustificationProofs::One {
+                target_height,
+                proof: FinalityProofs::GrandpaCommit(commit),
+            } => Some((*target_height, commit.clone())),
+            _ => unreachable!(),
+        };
+
+        // The target blocks are unknown, and the commits are stored for later.
+        let commit1 = grandpa_commit([5; 32], 5);
+        sync.grandpa_commit_message(source1, &commit1).unwrap();
+        assert_eq!(pending_commit(&sync, source1), Some((5, commit1)));
+
+        // The limit is reached, and new commits are discarded.
+        sync.grandpa_commit_message(source2, &grandpa_commit([6; 32], 6))
+            .unwrap();
+        assert_eq!(pending_commit(&sync, source2), None);
+
+        // Replacing an existing commit is still possible.
+        let commit2 = grandpa_commit([7; 32], 5);
+        sync.grandpa_commit_message(source1, &commit2).unwrap();
+        assert_eq!(pending_commit(&sync, source1), Some((5, commit2)));
+    }
+
+    #[test]
+    fn reset_to_finalized_checkpoint() {
+        let authority = aura_authority();
+        let (mut sync, genesis) = test_sync(all_forks_config(aura_consensus()));
+
+        let block1 = aura_header(&genesis, 1, [0; 32], &authority);
+        let block2 = aura_header(&block1, 2, [0; 32], &authority);
+
+        let source_id = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+        for (block, now) in [(&block1, 6), (&block2, 12)] {
+            match sync.block_announce(source_id, block.scale_encoding_vec(), true) {
+                BlockAnnounceOutcome::Unknown(unknown) => unknown.insert_and_update_source(()),
+                _ => unreachable!(),
+            }
+            sync = sync.process_some(1, Duration::from_secs(now)).0;
+        }
+        sync.grandpa_commit_message(source_id, &grandpa_commit([5; 32], 5))
+            .unwrap();
+        assert_eq!(sync.best_block_hash(), block2.hash());
+        assert!(sync.blocks_per_second().is_some());
+        assert_eq!(sync.num_pending_finality_proofs(), 1);
+
+        let finalized_chain_information = sync.finalized_chain_information_owned();
+        let checkpoint = |finalized_block_header: header::Header| {
+            chain_information::ValidChainInformation::try_from(
+                chain_information::ChainInformation {
+                    finalized_block_header,
+                    ..finalized_chain_information.clone().into()
+                },
+            )
+            .unwrap()
+        };
+
+        // A block that isn't an ancestor of the finalized block is refused, and the state
+        // machine is left untouched.
+        let mut fork = genesis.clone();
+        fork.state_root = [1; 32];
+        let fork_checkpoint = checkpoint(fork);
+        assert!(matches!(
+            sync.reset_to_finalized_checkpoint(fork_checkpoint, iter::empty::<Vec<u8>>()),
+            Err(super::CheckpointError::NotAncestor)
+        ));
+        assert_eq!(sync.best_block_hash(), block2.hash());
+
+        let genesis_checkpoint = checkpoint(genesis.clone());
+        let discarded = sync
+            .reset_to_finalized_checkpoint(genesis_checkpoint, iter::empty::<Vec<u8>>())
+            .unwrap();
+        assert_eq!(
+            discarded
+                .iter()
+                .map(|(header, ())| header.hash())
+                .collect::<Vec<_>>(),
+            vec![block1.hash(), block2.hash()]
+        );
+        assert_eq!(sync.best_block_hash(), genesis.hash());
+        assert!(sync.blocks_per_second().is_none());
+        assert_eq!(sync.num_pending_finality_proofs(), 0);
+        assert!(sync.inner.announced_siblings.is_empty());
+    }
+
+    #[test]
+    fn oversized_ancestry_search_header() {
+        let (mut sync, genesis) = test_sync(all_forks_config(
+            chain_information::ChainInformationConsensus::Unknown,
+        ));
+        let block1 = test_header(genesis.hash(), 1, [0; 32]).scale_encoding_vec();
+        sync.inner.max_header_size = block1.len() - 1;
+
+        let source_id =
+            match sync.prepare_add_source(1, header::hash_from_scale_encoded_header(&block1)) {
+                AddSource::UnknownBestBlock(add) => add.add_source_and_insert_block((), ()),
+                _ => unreachable!(),
+            };
+        let request_id = sync.add_request(
+            source_id,
+            RequestParams {
+                first_block_height: 1,
+                first_block_hash: header::hash_from_scale_encoded_header(&block1),
+                num_blocks: NonZeroU64::new(1).unwrap(),
+            },
+            (),
+        );
+
+        let ((), finish) = sync.finish_ancestry_search(request_id);
+        let sync = match finish.add_block(&block1, iter::empty::<([u8; 4], Vec<u8>)>()) {
+            Err((AncestrySearchResponseError::InvalidHeader(header::Error::TooLong), sync)) => sync,
+            _ => panic!(),
+        };
+        assert!(sync.source_misbehaved(source_id));
+    }
+
+    #[test]
+    fn max_blocks_ahead_of_finalized() {
+        let authority = aura_authority();
+        let (mut sync, genesis) = test_sync(Config {
+            max_blocks_ahead_of_finalized: NonZeroU64::new(1),
+            ..all_forks_config(aura_consensus())
+        });
+
+        let block1 = aura_header(&genesis, 1, [0; 32], &authority);
+        let block2 = aura_header(&block1, 2, [0; 32], &authority);
+
+        let source_id = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+        for block in [&block1, &block2] {
+            match sync.block_announce(source_id, block.scale_encoding_vec(), true) {
+                BlockAnnounceOutcome::Unknown(unknown) => unknown.insert_and_update_source(()),
+                _ => unreachable!(),
+            }
+        }
+
+        // Only the first block is verified, as the second one is too far ahead of the finalized
+        // block.
+        let (sync, summary) = sync.process_some(10, Duration::from_secs(12));
+        assert_eq!(summary.num_verified_headers, 1);
+        assert!(summary.is_idle);
+        assert_eq!(sync.best_block_hash(), block1.hash());
+        assert!(matches!(
+            sync.process_one(),
+            ProcessOne::WaitingForFinality { .. }
+        ));
+    }
+
+    #[test]
+    fn source_stats_counters() {
+        let (mut sync, genesis) = test_sync(all_forks_config(
+            chain_information::ChainInformationConsensus::Unknown,
+        ));
+        let block1_hash = [1; 32];
+
+        let source_id = match sync.prepare_add_source(1, block1_hash) {
+            AddSource::UnknownBestBlock(add) => add.add_source_and_insert_block((), ()),
+            _ => unreachable!(),
+        };
+        assert_eq!(sync.source_stats(source_id), SourceStats::default());
+
+        let block1_request = RequestParams {
+            first_block_height: 1,
+            first_block_hash: block1_hash,
+            num_blocks: NonZeroU64::new(1).unwrap(),
+        };
+        let succeeded = sync.add_request(source_id, block1_request, ());
+        let failed = sync.add_request(source_id, block1_request, ());
+        // Requests targeting the finalized block are obsolete.
+        let obsolete = sync.add_request(
+            source_id,
+            RequestParams {
+                first_block_height: 0,
+                first_block_hash: genesis.hash(),
+                num_blocks: NonZeroU64::new(1).unwrap(),
+            },
+            (),
+        );
+
+        let ((), finish) = sync.finish_ancestry_search(succeeded);
+        let sync = finish.finish();
+        let ((), sync) = sync.ancestry_search_failed(failed);
+        let ((), sync) = sync.ancestry_search_failed(obsolete);
+
+        assert_eq!(
+            sync.source_stats(source_id),
+            SourceStats {
+                num_requests_succeeded: 1,
+                num_requests_failed: 1,
+                num_requests_obsolete: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn saturated_source_announces_not_inserted() {
+        let (mut sync, genesis) = test_sync(Config {
+            source_saturation_threshold: NonZeroU32::new(1),
+            ..all_forks_config(chain_information::ChainInformationConsensus::Unknown)
+        });
+
+        let source_id = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+        let request_id = sync.add_request(
+            source_id,
+            RequestParams {
+                first_block_height: 5,
+                first_block_hash: [5; 32],
+                num_blocks: NonZeroU64::new(1).unwrap(),
+            },
+            (),
+        );
+
+        let block1 = test_header(genesis.hash(), 1, [0; 32]);
+        let block1_hash = block1.hash();
+
+        // The source is saturated. The block is only recorded as known by the source.
+        assert!(matches!(
+            sync.block_announce(source_id, block1.scale_encoding_vec(), true),
+            BlockAnnounceOutcome::SourceSaturated
+        ));
+        assert!(sync.source_knows_non_finalized_block(source_id, 1, &block1_hash));
+        assert_eq!(sync.source_best_block(source_id), (1, &block1_hash));
+        assert!(!sync.inner.blocks.contains_unverified_block(1, &block1_hash));
+
+        // Once the source has capacity again, the block is inserted.
+        let ((), mut sync) = sync.ancestry_search_failed(request_id);
+        match sync.block_announce(source_id, block1.scale_encoding_vec(), true) {
+            BlockAnnounceOutcome::Unknown(unknown) => unknown.insert_and_update_source(()),
+            _ => panic!(),
+        }
+        assert!(sync.inner.blocks.contains_unverified_block(1, &block1_hash));
+    }
+
+    #[test]
+    fn common_ancestor_of_diverging_forks() {
+        let authority = aura_authority();
+        let (mut sync, genesis) = test_sync(all_forks_config(aura_consensus()));
+
+        //          /-> 2a -> 3a
+        // 0 -> 1 -|
+        //          \-> 2b
+        let block1 = aura_header(&genesis, 1, [0; 32], &authority);
+        let block2a = aura_header(&block1, 2, [0; 32], &authority);
+        let block2b = aura_header(&block1, 3, [0; 32], &authority);
+        let block3a = aura_header(&block2a, 4, [0; 32], &authority);
+
+        let source_id = match sync.prepare_add_source(0, genesis.hash()) {
+            AddSource::OldBestBlock(add) => add.add_source(()),
+            _ => unreachable!(),
+        };
+        for block in [&block1, &block2a, &block2b, &block3a] {
+            match sync.block_announce(source_id, block.scale_encoding_vec(), false) {
+                BlockAnnounceOutcome::Unknown(unknown) => unknown.insert_and_update_source(()),
+                _ => unreachable!(),
+            }
+        }
+        let (sync, summary) = sync.process_some(10, Duration::from_secs(30));
+        assert_eq!(summary.num_verified_headers, 4);
+
+        assert_eq!(
+            sync.common_ancestor((3, block3a.hash()), (2, block2b.hash())),
+            Some((1, block1.hash()))
+        );
+        assert_eq!(
+            sync.common_ancestor((2, block2a.hash()), (3, block3a.hash())),
+            Some((2, block2a.hash()))
+        );
+        assert_eq!(
+            sync.common_ancestor((2, block2b.hash()), (0, genesis.hash())),
+            Some((0, genesis.hash()))
+        );
+
+        // Height not matching the hash, and unknown block.
+        assert_eq!(
+            sync.common_ancestor((3, block2b.hash()), (2, block2a.hash())),
+            None
+        );
+        assert_eq!(
+            sync.common_ancestor((2, [0xff; 32]), (2, block2a.hash())),
+            None
+        );
+    }
+
+    #[test]
+    fn unexpected_ancestry_search_blocks() {
+        let (mut sync, genesis) = test_sync(all_forks_config(
+            chain_information::ChainInformationConsensus::Unknown,
+        ));
+        let block1 = test_header(genesis.hash(), 1, [0; 32]);
+        let block1_hash = block1.hash();
+        let block2 = test_header(block1_hash, 2, [0; 32]);
+        let block2_hash = block2.hash();
+        // Block at height 1 other than `block1`.
+        let other_block1 = header::Header {
+            state_root: [1; 32],
+            ..block1.clone()
+        };
+
+        let source_id = match sync.prepare_add_source(2, block2_hash) {
+            AddSource::UnknownBestBlock(add) => add.add_source_and_insert_block((), ()),
+            _ => unreachable!(),
+        };
+        let params = RequestParams {
+            first_block_height: 2,
+            first_block_hash: block2_hash,
+            num_blocks: NonZeroU64::new(2).unwrap(),
+        };
+
+        // The source answers with the parent of the requested block.
+        let request_id = sync.add_request(source_id, params, ());
+        let ((), finish) = sync.finish_ancestry_search(request_id);
+        let mut sync = match finish.add_block(
+            &block1.scale_encoding_vec(),
+            iter::empty::<([u8; 4], Vec<u8>)>(),
+        ) {
+            Err((AncestrySearchResponseError::UnexpectedFirstBlock, sync)) => sync,
+            _ => panic!(),
+        };
+
+        // The source answers with the requested block, followed by a block that isn't its parent.
+        let request_id = sync.add_request(source_id, params, ());
+        let ((), finish) = sync.finish_ancestry_search(request_id);
+        let finish = match finish.add_block(
+            &block2.scale_encoding_vec(),
+            iter::empty::<([u8; 4], Vec<u8>)>(),
+        ) {
+            Ok(AddBlock::AlreadyPending(occupied)) => occupied.replace(()).0,
+            _ => panic!(),
+        };
+        assert!(matches!(
+            finish.add_block(
+                &other_block1.scale_encoding_vec(),
+                iter::empty::<([u8; 4], Vec<u8>)>(),
+            ),
+            Err((AncestrySearchResponseError::UnexpectedNextBlock, _))
+        ));
+    }
+
+    #[test]
+    fn reserved_source_best_block_updated_by_announce() {
+        let (mut sync, genesis) = test_sync(all_forks_config(
+            chain_information::ChainInformationConsensus::Unknown,
+        ));
+
+        let source_id = sync.reserve_source(());
+        assert_eq!(sync.source_best_block(source_id), (0, &genesis.hash()));
+
+        let block1 = test_header(genesis.hash(), 1, [0; 32]);
+        let block1_hash = block1.hash();
+        match sync.block_announce(source_id, block1.scale_encoding_vec(), true) {
+            BlockAnnounceOutcome::Unknown(unknown) => unknown.insert_and_update_source(()),
+            _ => panic!(),
+        }
+        assert_eq!(sync.source_best_block(source_id), (1, &block1_hash));
+        assert!(sync.source_knows_non_finalized_block(source_id, 1, &block1_hash));
+    }
+
+    #[test]
+    fn validate_ancestry_search_response() {
+        let (mut sync, genesis) = test_sync(all_forks_config(
+            chain_information::ChainInformationConsensus::Unknown,
+        ));
+        let block1 = test_header(genesis.hash(), 1, [0; 32]);
+        let block1_hash = block1.hash();
+        let block2 = test_header(block1_hash, 2, [0; 32]);
+        let block2_hash = block2.hash();
+        // Block at height 1 other than `block1`, whose parent isn't the finalized block.
+        let other_block1 = header::Header {
+            parent_hash: [1; 32],
+            ..block1.clone()
+        };
+        let block1 = block1.scale_encoding_vec();
+        let block2 = block2.scale_encoding_vec();
+        let other_block1 = other_block1.scale_encoding_vec();
+        let encoded_genesis = genesis.scale_encoding_vec();
+        let invalid = vec![0xff; 3];
+
+        let source_id = match sync.prepare_add_source(2, block2_hash) {
+            AddSource::UnknownBestBlock(add) => add.add_source_and_insert_block((), ()),
+            _ => unreachable!(),
+        };
+        let validate = |sync: &mut AllForksSync<(), (), ()>,
+                        first_block_height: u64,
+                        first_block_hash: [u8; 32],
+                        headers: &[&Vec<u8>]| {
+            let request_id = sync.add_request(
+                source_id,
+                RequestParams {
+                    first_block_height,
+                    first_block_hash,
+                    num_blocks: NonZeroU64::new(2).unwrap(),
+                },
+                (),
+            );
+            sync.validate_ancestry_search_response(request_id, headers.iter().map(|h| &h[..]))
+        };
+
+        assert!(validate(&mut sync, 2, block2_hash, &[&block2, &block1]).is_ok());
+
+        assert!(matches!(
+            validate(&mut sync, 2, block2_hash, &[&block1]),
+            Err((0, AncestrySearchResponseError::UnexpectedFirstBlock))
+        ));
+        assert!(matches!(
+            validate(&mut sync, 2, block2_hash, &[&block2, &other_block1]),
+            Err((1, AncestrySearchResponseError::UnexpectedNextBlock))
+        ));
+        assert!(matches!(
+            validate(&mut sync, 2, block1_hash, &[&block1]),
+            Err((0, AncestrySearchResponseError::UnexpectedFirstBlock))
+        ));
+        assert!(matches!(
+            validate(
+                &mut sync,
+                1,
+                header::hash_from_scale_encoded_header(&invalid),
+                &[&invalid]
+            ),
+            Err((0, AncestrySearchResponseError::InvalidHeader(_)))
+        ));
+        assert!(matches!(
+            validate(&mut sync, 0, genesis.hash(), &[&encoded_genesis]),
+            Err((0, AncestrySearchResponseError::TooOld))
+        ));
+        assert!(matches!(
+            validate(
+                &mut sync,
+                1,
+                header::hash_from_scale_encoded_header(&other_block1),
+                &[&other_block1]
+            ),
+            Err((0, AncestrySearchResponseError::NotFinalizedChain { .. }))
+        ));
+        // Note that `SelfParent` can't be tested, as it would require a header whose hash is
+        // equal to its parent hash.
+
+        sync.inner.max_header_size = block1.len() - 1;
+        assert!(matches!(
+            validate(&mut sync, 2, block2_hash, &[&block2, &block1]),
+            Err((
+                0,
+                AncestrySearchResponseError::InvalidHeader(header::Error::TooLong)
+            ))
+        ));
+    }
+
+    #[test]
+    fn unbanned_block_requested_and_verified() {
+        let authority = aura_authority();
+        let (mut sync, genesis) = test_sync(all_forks_config(aura_consensus()));
+
+        let block1 = aura_header(&genesis, 1, [0; 32], &authority);
+        sync.inner.banned_blocks.insert(block1.hash());
+        assert_eq!(
+            sync.banned_blocks().collect::<Vec<_>>(),
+            vec![&block1.hash()]
+        );
+
+        let source_id = match sync.prepare_add_source(1, block1.hash()) {
+            AddSource::UnknownBestBlock(add) => add.add_source_and_insert_block((), ()),
+            _ => unreachable!(),
+        };
+
+        // The banned block is neither requested nor verified.
+        assert_eq!(sync.desired_requests().count(), 0);
+        let now = Duration::from_secs(6);
+        let (mut sync, events) = process_until_idle(sync, now, |_| None);
+        assert!(events.is_empty());
+
+        assert!(sync.unban_block(&block1.hash()));
+        assert!(!sync.unban_block(&block1.hash()));
+        assert_eq!(sync.banned_blocks().count(), 0);
+
+        // Once unbanned, the block is requested again.
+        let (_, _, params) = sync.desired_requests().next().unwrap();
+        assert_eq!(params.first_block_hash, block1.hash());
+        let request_id = sync.add_request(source_id, params, ());
+        let ((), finish) = sync.finish_ancestry_search(request_id);
+        let sync = match finish.add_block(
+            &block1.scale_encoding_vec(),
+            iter::empty::<([u8; 4], Vec<u8>)>(),
+        ) {
+            Ok(AddBlock::AlreadyPending(occupied)) => occupied.replace(()).0.finish(),
+            Ok(AddBlock::UnknownBlock(vacant)) => vacant.insert(()).finish(),
+            _ => panic!(),
+        };
+
+        // The block is then verified.
+        let (sync, events) = process_until_idle(sync, now, |_| None);
+        assert_eq!(events, vec![Event::NewBest(1, block1.hash())]);
+        assert_eq!(sync.best_block_hash(), block1.hash());
+    }
+
+    #[test]
+    fn unverified_block_parent() {
+        let (mut sync, genesis) = test_sync(all_forks_config(
+            chain_information::ChainInformationConsensus::Unknown,
+        ));
+        let block1 = test_header(genesis.hash(), 1, [0; 32]);
+        let block1_hash = block1.hash();
+        let block2 = test_header(block1_hash, 2, [0; 32]);
+        let block2_hash = block2.hash();
+        let block3_hash = test_header(block2_hash, 3, [0; 32]).hash();
+
+        assert_eq!(sync.unverified_block_parent(1, &block1_hash), None);
+
+        // Only the hash of the best block of the source is known.
+        let source_id = match sync.prepare_add_source(3, block3_hash) {
+            AddSource::UnknownBestBlock(add) => add.add_source_and_insert_block((), ()),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            sync.unverified_block_parent(3, &block3_hash),
+            Some(UnverifiedBlockParent::HashUnknown)
+        );
+
+        match sync.block_announce(source_id, block2.scale_encoding_vec(), false) {
+            BlockAnnounceOutcome::Unknown(unknown) => unknown.insert_and_update_source(()),
+            _ => panic!(),
+        }
+        assert_eq!(
+            sync.unverified_block_parent(2, &block2_hash),
+            Some(UnverifiedBlockParent::Unknown(block1_hash))
+        );
+
+        match sync.block_announce(source_id, block1.scale_encoding_vec(), false) {
+            BlockAnnounceOutcome::Unknown(unknown) => unknown.insert_and_update_source(()),
+            _ => panic!(),
+        }
+        assert_eq!(
+            sync.unverified_block_parent(2, &block2_hash),
+            Some(UnverifiedBlockParent::Unverified(block1_hash))
+        );
+        assert_eq!(
+            sync.unverified_block_parent(1, &block1_hash),
+            Some(UnverifiedBlockParent::Verified(genesis.hash()))
+        );
+    }
+}
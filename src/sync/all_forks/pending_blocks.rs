@@ -89,13 +89,17 @@
 #![allow(dead_code)] // TODO: remove this after `all.rs` implements full node; right now many methods here are useless because expected to be used only for full node code
 
 use super::{disjoint, sources};
+use crate::util::SipHasherBuild;
 
 use alloc::{collections::BTreeSet, vec::Vec};
 use core::{
+    hash::BuildHasher as _,
     iter,
     num::{NonZeroU32, NonZeroU64},
     ops,
 };
+use itertools::Itertools as _;
+use rand::{Rng as _, SeedableRng as _};
 
 pub use disjoint::TreeRoot;
 pub use sources::SourceId;
@@ -130,6 +134,10 @@ pub struct Config {
     ///
     /// The higher the value, the more bandwidth is potentially wasted.
     pub max_requests_per_block: NonZeroU32,
+
+    /// Seed used to determine the order in which the sources are returned by
+    /// [`PendingBlocks::desired_requests`].
+    pub randomness_seed: [u8; 32],
 }
 
 /// State of a block in the data structure.
@@ -164,6 +172,16 @@ impl UnverifiedBlockState {
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct RequestId(usize);
 
+impl RequestId {
+    /// Returns the numerical value of this identifier, for example for logging purposes.
+    ///
+    /// > **Note**: The identifier of a request that has been finished can later be re-used for
+    /// >           a different request.
+    pub fn as_u64(&self) -> u64 {
+        u64::try_from(self.0).unwrap()
+    }
+}
+
 /// Collection of pending blocks and requests.
 pub struct PendingBlocks<TBl, TRq, TSrc> {
     /// All sources in the collection.
@@ -204,6 +222,10 @@ pub struct PendingBlocks<TBl, TRq, TSrc> {
     /// See [`Config::max_requests_per_block`].
     /// Since it is always compared with `usize`s, converted to `usize` ahead of time.
     max_requests_per_block: usize,
+
+    /// Hasher used to sort the sources that can be used for a request. Derived from
+    /// [`Config::randomness_seed`].
+    sources_order: SipHasherBuild,
 }
 
 struct UnverifiedBlock<TBl> {
@@ -243,6 +265,10 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
             ),
             max_requests_per_block: usize::try_from(config.max_requests_per_block.get())
                 .unwrap_or(usize::max_value()),
+            sources_order: {
+                let mut randomness = rand_chacha::ChaCha20Rng::from_seed(config.randomness_seed);
+                SipHasherBuild::new(randomness.sample(rand::distributions::Standard))
+            },
         }
     }
 
@@ -328,6 +354,11 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
         self.sources.keys()
     }
 
+    /// Returns the list of all user datas of all sources.
+    pub fn sources_user_data_iter(&'_ self) -> impl ExactSizeIterator<Item = &'_ TSrc> + '_ {
+        self.sources.user_data_iter().map(|s| &s.user_data)
+    }
+
     /// Returns the list of all user datas of all sources.
     pub fn sources_user_data_iter_mut(
         &'_ mut self,
@@ -349,6 +380,25 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
         self.sources.add_known_block(source_id, height, hash);
     }
 
+    /// Registers a list of new blocks that the source is aware of.
+    ///
+    /// Equivalent to calling [`PendingBlocks::add_known_block_to_source`] for each block. Blocks
+    /// whose height is inferior or equal to the finalized block height are ignored.
+    ///
+    /// The blocks do not need to be known by the data structure.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SourceId`] is out of range.
+    ///
+    pub fn add_known_blocks_to_source(
+        &mut self,
+        source_id: SourceId,
+        blocks: impl Iterator<Item = (u64, [u8; 32])>,
+    ) {
+        self.sources.add_known_blocks(source_id, blocks);
+    }
+
     /// Un-registers a new block that the source is aware of.
     ///
     /// Has no effect if the block wasn't marked as being known to this source.
@@ -478,6 +528,19 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
             .map(|(_, _, bl)| bl.user_data)
     }
 
+    /// Lowers the height of the finalized block.
+    ///
+    /// Contrary to [`PendingBlocks::set_finalized_block_height`], no block is removed from the
+    /// collection.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the new height is superior to the previous value.
+    ///
+    pub fn lower_finalized_block_height(&mut self, height: u64) {
+        self.sources.lower_finalized_block_height(height);
+    }
+
     /// Inserts an unverified block in the collection.
     ///
     /// Returns the previous user data associated to this block, if any.
@@ -512,6 +575,16 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
         self.blocks.contains(height, hash)
     }
 
+    /// Returns what is known about the given unverified block, or `None` if the block isn't in
+    /// the collection.
+    pub fn unverified_block_state(
+        &self,
+        height: u64,
+        hash: &[u8; 32],
+    ) -> Option<UnverifiedBlockState> {
+        Some(self.blocks.user_data(height, hash)?.state)
+    }
+
     /// Gives access to the user data stored for this block.
     ///
     /// # Panic
@@ -656,6 +729,26 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
         self.blocks.remove(height, hash).user_data
     }
 
+    /// Returns the list of ancestors of the given block that are in the list of unverified
+    /// blocks, ordered by decreasing height.
+    ///
+    /// The iteration stops at the first ancestor that isn't in the list of unverified blocks or
+    /// whose parent isn't known. If the given block itself isn't in the list of unverified
+    /// blocks, the iterator is empty.
+    pub fn unverified_block_ancestry(
+        &'_ self,
+        height: u64,
+        hash: &[u8; 32],
+    ) -> impl Iterator<Item = (u64, [u8; 32])> + '_ {
+        let parent_of = move |(height, hash): &(u64, [u8; 32])| {
+            let parent_hash = *self.blocks.parent_hash(*height, hash)?;
+            Some((height.checked_sub(1)?, parent_hash))
+        };
+
+        iter::successors(parent_of(&(height, *hash)), parent_of)
+            .take_while(move |(height, hash)| self.blocks.contains(*height, hash))
+    }
+
     /// Marks the given unverified block and all its known children as "bad".
     ///
     /// If a child of this block is later added to the collection, it is also automatically
@@ -675,6 +768,40 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
         self.blocks.len()
     }
 
+    /// Returns a snapshot of the state of all the unverified blocks stored in the data
+    /// structure, ordered by increasing height.
+    ///
+    /// This is meant to be used for diagnostic purposes.
+    pub fn unverified_blocks_snapshot(
+        &'_ self,
+    ) -> impl Iterator<Item = UnverifiedBlockSnapshot> + '_ {
+        self.blocks.iter().map(move |(height, hash, block)| {
+            let is_tree_root = match (height.checked_sub(1), block.state.parent_hash()) {
+                (Some(parent_height), Some(parent_hash)) => {
+                    !self.blocks.contains(parent_height, parent_hash)
+                }
+                _ => true,
+            };
+
+            let num_ongoing_requests = self
+                .blocks_requests
+                .range(
+                    (height, *hash, RequestId(usize::min_value()))
+                        ..=(height, *hash, RequestId(usize::max_value())),
+                )
+                .count();
+
+            UnverifiedBlockSnapshot {
+                height,
+                hash: *hash,
+                state: block.state,
+                is_tree_root,
+                is_bad: self.blocks.is_bad(height, hash).unwrap(),
+                num_ongoing_requests,
+            }
+        })
+    }
+
     /// Returns the list of blocks whose parent hash is known but absent from the list of disjoint
     /// blocks. These blocks can potentially be verified.
     ///
@@ -876,6 +1003,46 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
         self.requests.get(request_id.0).unwrap().source_id
     }
 
+    /// Returns the parameters of the given request, as passed to [`PendingBlocks::add_request`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`RequestId`] is invalid.
+    ///
+    #[track_caller]
+    pub fn request_params(&self, request_id: RequestId) -> &RequestParams {
+        &self.requests.get(request_id.0).unwrap().detail
+    }
+
+    /// Returns the list of all the ongoing requests, with their source and parameters.
+    pub fn requests(
+        &'_ self,
+    ) -> impl Iterator<Item = (RequestId, SourceId, &'_ RequestParams)> + '_ {
+        self.requests
+            .iter()
+            .map(|(id, rq)| (RequestId(id), rq.source_id, &rq.detail))
+    }
+
+    /// Returns the list of blocks, as `(height, hash)`, that are targeted by at least one ongoing
+    /// request, ordered by increasing height. Each block is yielded only once.
+    ///
+    /// This includes the first block of each request and its ancestors that are known to the
+    /// state machine at the time when the request has been added.
+    pub fn requested_blocks(&'_ self) -> impl Iterator<Item = (u64, &'_ [u8; 32])> + '_ {
+        self.blocks_requests
+            .iter()
+            .map(|(height, hash, _)| (*height, hash))
+            .dedup()
+    }
+
+    /// Returns the source and parameters of the given request, or `None` if the [`RequestId`] is
+    /// invalid.
+    pub fn request_detail(&self, request_id: RequestId) -> Option<(SourceId, RequestParams)> {
+        self.requests
+            .get(request_id.0)
+            .map(|rq| (rq.source_id, rq.detail))
+    }
+
     /// Returns a list of requests that are considered obsolete and can be removed using
     /// [`PendingBlocks::finish_request`].
     ///
@@ -1041,7 +1208,15 @@ impl<TBl, TRq, TSrc> PendingBlocks<TBl, TRq, TSrc> {
                     )
                 };
 
+                // The sources are ordered in a way that depends on the randomness seed and on
+                // the block, in order to not always favor the same source.
+                let mut possible_sources = possible_sources.collect::<Vec<_>>();
+                possible_sources.sort_by_key(|source_id| {
+                    self.sources_order.hash_one((source_id, unknown_block_hash))
+                });
+
                 possible_sources
+                    .into_iter()
                     .filter(move |source_id| {
                         // Don't start any request towards this source if there's another request
                         // for the same block from the same source.
@@ -1103,6 +1278,24 @@ impl<TBl, TRq, TSrc> ops::IndexMut<SourceId> for PendingBlocks<TBl, TRq, TSrc> {
     }
 }
 
+/// See [`PendingBlocks::unverified_blocks_snapshot`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct UnverifiedBlockSnapshot {
+    /// Height of the block.
+    pub height: u64,
+    /// Hash of the block.
+    pub hash: [u8; 32],
+    /// What is known about the block.
+    pub state: UnverifiedBlockState,
+    /// `true` if the parent of the block isn't in the list of unverified blocks, either because
+    /// it is unknown or because it has already been verified.
+    pub is_tree_root: bool,
+    /// `true` if the block or one of its ancestors is known to be bad.
+    pub is_bad: bool,
+    /// Number of requests currently in progress that target this block.
+    pub num_ongoing_requests: usize,
+}
+
 /// See [`PendingBlocks::desired_requests`].
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct DesiredRequest {
@@ -35,6 +35,16 @@ use core::{fmt, ops};
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct SourceId(u64);
 
+impl SourceId {
+    /// Returns the numerical value of this identifier, for example for logging purposes.
+    ///
+    /// Identifiers are never re-used, meaning that two different sources of the same state
+    /// machine always have two different values.
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
 /// Collection of sources and which blocks they know about.
 pub struct AllForksSources<TSrc> {
     /// Actual list of sources.
@@ -47,6 +57,10 @@ pub struct AllForksSources<TSrc> {
     /// Stores `(source, block hash)` tuples. Each tuple is an information about the fact that
     /// this source knows about the given block. Only contains blocks whose height is strictly
     /// superior to [`AllForksSources::finalized_block_height`].
+    ///
+    /// The known blocks of all sources are stored in the same container rather than in a
+    /// container per source. `BTreeSet`s allocate their nodes one by one and can't be
+    /// pre-allocated, which is why there isn't any capacity configuration for them.
     known_blocks1: BTreeSet<(SourceId, u64, [u8; 32])>,
 
     /// Contains the same entries as [`AllForksSources::known_blocks1`], but in reverse.
@@ -97,6 +111,11 @@ impl<TSrc> AllForksSources<TSrc> {
         self.sources.len()
     }
 
+    /// Returns the list of all user datas of all sources.
+    pub fn user_data_iter(&'_ self) -> impl ExactSizeIterator<Item = &'_ TSrc> + '_ {
+        self.sources.values().map(|s| &s.user_data)
+    }
+
     /// Returns the list of all user datas of all sources.
     pub fn user_data_iter_mut(&'_ mut self) -> impl ExactSizeIterator<Item = &'_ mut TSrc> + '_ {
         self.sources.values_mut().map(|s| &mut s.user_data)
@@ -231,6 +250,20 @@ impl<TSrc> AllForksSources<TSrc> {
         self.finalized_block_height = height;
     }
 
+    /// Lowers the height of the finalized block.
+    ///
+    /// Contrary to [`AllForksSources::set_finalized_block_height`], no block is removed from the
+    /// data structure.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the new height is superior to the previous value.
+    ///
+    pub fn lower_finalized_block_height(&mut self, height: u64) {
+        assert!(height <= self.finalized_block_height);
+        self.finalized_block_height = height;
+    }
+
     /// Registers a new block that the source is aware of.
     ///
     /// Has no effect if `height` is inferior or equal to the finalized block height.
@@ -246,6 +279,31 @@ impl<TSrc> AllForksSources<TSrc> {
         }
     }
 
+    /// Registers a list of new blocks that the source is aware of.
+    ///
+    /// Equivalent to calling [`AllForksSources::add_known_block`] for each block, but the source
+    /// is looked up only once. Blocks whose height is inferior or equal to the finalized block
+    /// height are ignored.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SourceId`] is out of range.
+    ///
+    pub fn add_known_blocks(
+        &mut self,
+        source_id: SourceId,
+        blocks: impl Iterator<Item = (u64, [u8; 32])>,
+    ) {
+        assert!(self.sources.contains_key(&source_id));
+
+        for (height, hash) in blocks {
+            if height > self.finalized_block_height {
+                self.known_blocks1.insert((source_id, height, hash));
+                self.known_blocks2.insert((height, hash, source_id));
+            }
+        }
+    }
+
     /// Removes a block from the list of blocks the sources are aware of.
     ///
     /// > **Note**: Alongside with [`AllForksSources::set_finalized_block_height`], this method
@@ -432,4 +490,19 @@ mod tests {
         assert!(sources.is_empty());
         assert_eq!(sources.len(), 0);
     }
+
+    #[test]
+    fn add_known_blocks_ignores_finalized() {
+        let mut sources = super::AllForksSources::new(256, 10);
+        let source1 = sources.add_source(10, [0; 32], ());
+        assert_eq!(sources.num_blocks(), 0);
+
+        sources.add_known_blocks(
+            source1,
+            [(9, [1; 32]), (10, [2; 32]), (11, [3; 32]), (12, [4; 32])].into_iter(),
+        );
+        assert_eq!(sources.num_blocks(), 2);
+        assert!(sources.source_knows_non_finalized_block(source1, 11, &[3; 32]));
+        assert!(sources.source_knows_non_finalized_block(source1, 12, &[4; 32]));
+    }
 }
@@ -0,0 +1,158 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Verification that a finalized checkpoint is an ancestor of the current finalized block.
+//!
+//! Used by the syncing strategies that can be reset to an older finalized block.
+
+use crate::header;
+
+/// Checks that `checkpoint` is the current finalized block or one of its ancestors.
+///
+/// `ancestry` must yield the SCALE-encoded headers of the blocks between `checkpoint`
+/// (exclusive) and `current_finalized` (exclusive), in decreasing block number order. In other
+/// words, it must start with the parent of `current_finalized` and end with the child of
+/// `checkpoint`.
+pub(super) fn verify_ancestry(
+    checkpoint: header::HeaderRef,
+    current_finalized: header::HeaderRef,
+    ancestry: impl Iterator<Item = impl AsRef<[u8]>>,
+) -> Result<(), CheckpointError> {
+    if checkpoint.number > current_finalized.number {
+        return Err(CheckpointError::NotAncestor);
+    }
+
+    // Walk down the ancestry, starting from the current finalized block. `expected` contains
+    // the height and hash of the block currently being looked at.
+    let mut ancestry = ancestry;
+    let mut expected = (current_finalized.number, current_finalized.hash());
+    let mut parent_hash = *current_finalized.parent_hash;
+
+    while expected.0 > checkpoint.number {
+        expected = (expected.0 - 1, parent_hash);
+        if expected.0 == checkpoint.number {
+            break;
+        }
+
+        let scale_encoded = ancestry.next().ok_or(CheckpointError::MissingAncestry)?;
+        let decoded =
+            header::decode(scale_encoded.as_ref()).map_err(CheckpointError::InvalidHeader)?;
+        if decoded.number != expected.0
+            || header::hash_from_scale_encoded_header(scale_encoded.as_ref()) != expected.1
+        {
+            return Err(CheckpointError::AncestryMismatch);
+        }
+        parent_hash = *decoded.parent_hash;
+    }
+
+    if expected.1 != checkpoint.hash() {
+        return Err(CheckpointError::NotAncestor);
+    }
+
+    if ancestry.next().is_some() {
+        return Err(CheckpointError::AncestryMismatch);
+    }
+
+    Ok(())
+}
+
+/// Error that can happen when resetting a syncing state machine to a finalized checkpoint.
+#[derive(Debug, derive_more::Display)]
+pub enum CheckpointError {
+    /// The checkpoint isn't the current finalized block or one of its ancestors.
+    NotAncestor,
+    /// The ancestry of the current finalized block doesn't reach the checkpoint.
+    MissingAncestry,
+    /// Failed to decode one of the headers of the ancestry.
+    #[display(fmt = "Failed to decode ancestry header: {}", _0)]
+    InvalidHeader(header::Error),
+    /// The headers of the ancestry aren't the ancestors of the current finalized block, or too
+    /// many headers have been provided.
+    AncestryMismatch,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_ancestry, CheckpointError};
+    use crate::header;
+
+    fn child(parent: &header::Header) -> header::Header {
+        header::Header {
+            parent_hash: parent.hash(),
+            number: parent.number + 1,
+            state_root: [0; 32],
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::empty().into(),
+        }
+    }
+
+    #[test]
+    fn ancestry_verified() {
+        let block0 = header::Header {
+            parent_hash: [0; 32],
+            number: 0,
+            state_root: [0; 32],
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::empty().into(),
+        };
+        let block1 = child(&block0);
+        let block2 = child(&block1);
+        let block3 = child(&block2);
+        let ancestry = [block2.scale_encoding_vec(), block1.scale_encoding_vec()];
+
+        assert!(verify_ancestry((&block0).into(), (&block3).into(), ancestry.iter()).is_ok());
+        assert!(verify_ancestry((&block3).into(), (&block3).into(), [[0u8; 0]; 0].iter()).is_ok());
+        assert!(verify_ancestry((&block2).into(), (&block3).into(), [[0u8; 0]; 0].iter()).is_ok());
+        assert!(matches!(
+            verify_ancestry(
+                (&block2).into(),
+                (&block3).into(),
+                [block2.scale_encoding_vec()].iter()
+            ),
+            Err(CheckpointError::AncestryMismatch)
+        ));
+
+        assert!(matches!(
+            verify_ancestry((&block3).into(), (&block2).into(), [[0u8; 0]; 0].iter()),
+            Err(CheckpointError::NotAncestor)
+        ));
+        assert!(matches!(
+            verify_ancestry((&block0).into(), (&block3).into(), ancestry[..1].iter()),
+            Err(CheckpointError::MissingAncestry)
+        ));
+        assert!(matches!(
+            verify_ancestry((&block0).into(), (&block3).into(), ancestry.iter().rev()),
+            Err(CheckpointError::AncestryMismatch)
+        ));
+        assert!(matches!(
+            verify_ancestry(
+                (&block0).into(),
+                (&block3).into(),
+                [&[1u8, 2, 3][..]].iter()
+            ),
+            Err(CheckpointError::InvalidHeader(_))
+        ));
+
+        // A block with the right height but on a different fork.
+        let mut fork0 = block0.clone();
+        fork0.state_root = [1; 32];
+        assert!(matches!(
+            verify_ancestry((&fork0).into(), (&block3).into(), ancestry.iter()),
+            Err(CheckpointError::NotAncestor)
+        ));
+    }
+}
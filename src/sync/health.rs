@@ -0,0 +1,114 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Summary of the state of a syncing state machine.
+//!
+//! See [`AllForksSync::health`](super::all_forks::AllForksSync::health) and
+//! [`OptimisticSync::health`](super::optimistic::OptimisticSync::health).
+
+/// Maximum number of blocks the local best block can be behind the best block of the sources
+/// while still being considered as synced. See [`SyncHealth::is_synced`].
+pub const SYNCED_THRESHOLD: u64 = 2;
+
+/// Summary of the state of a syncing state machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncHealth {
+    /// Height of the current best block.
+    pub best_block_number: u64,
+    /// Hash of the current best block.
+    pub best_block_hash: [u8; 32],
+    /// Height of the current finalized block.
+    pub finalized_block_number: u64,
+    /// Hash of the current finalized block.
+    pub finalized_block_hash: [u8; 32],
+    /// Number of sources in the state machine.
+    pub num_sources: usize,
+    /// Number of requests that have been started and haven't been finished yet.
+    pub num_ongoing_requests: usize,
+    /// `true` if there is at least one source, and the local best block is at most
+    /// [`SYNCED_THRESHOLD`] blocks behind the highest best block reported by a source.
+    pub is_synced: bool,
+}
+
+impl SyncHealth {
+    /// Builds a [`SyncHealth`]. `sources_best_block_numbers` must yield the height of the best
+    /// block of each source.
+    pub(super) fn new(
+        (best_block_number, best_block_hash): (u64, [u8; 32]),
+        (finalized_block_number, finalized_block_hash): (u64, [u8; 32]),
+        num_sources: usize,
+        num_ongoing_requests: usize,
+        sources_best_block_numbers: impl Iterator<Item = u64>,
+    ) -> Self {
+        SyncHealth {
+            best_block_number,
+            best_block_hash,
+            finalized_block_number,
+            finalized_block_hash,
+            num_sources,
+            num_ongoing_requests,
+            is_synced: sources_best_block_numbers.max().map_or(false, |highest| {
+                best_block_number.saturating_add(SYNCED_THRESHOLD) >= highest
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SyncHealth, SYNCED_THRESHOLD};
+    use core::iter;
+
+    #[test]
+    fn is_synced_threshold() {
+        let is_synced = |sources: &[u64]| {
+            SyncHealth::new(
+                (10, [0; 32]),
+                (5, [1; 32]),
+                sources.len(),
+                0,
+                sources.iter().copied(),
+            )
+            .is_synced
+        };
+
+        assert!(!is_synced(&[]));
+        assert!(is_synced(&[10]));
+        assert!(is_synced(&[3, 10 + SYNCED_THRESHOLD]));
+        assert!(!is_synced(&[3, 10 + SYNCED_THRESHOLD + 1]));
+        assert!(
+            !SyncHealth::new(
+                (u64::max_value(), [0; 32]),
+                (0, [1; 32]),
+                0,
+                0,
+                iter::empty()
+            )
+            .is_synced
+        );
+        assert!(
+            SyncHealth::new(
+                (u64::max_value(), [0; 32]),
+                (0, [1; 32]),
+                1,
+                0,
+                iter::once(u64::max_value())
+            )
+            .is_synced
+        );
+    }
+}
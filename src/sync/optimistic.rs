@@ -41,15 +41,28 @@
 //! The *optimism* aspect comes from the fact that, while a bad source can't corrupt the state of
 //! the local chain, and can't stall the syncing process (unless there isn't any other source
 //! available), it can still slow it down.
+//!
+//! # Full mode
+//!
+//! When [`Config::full`] is `Some`, the body of each block is executed at the same time as its
+//! header is verified, and a block is only added to the chain once both verifications have
+//! succeeded. In other words, the header chain can never be ahead of the fully-verified chain.
+//! When executing blocks is the bottleneck, the speed at which the chain is followed is limited
+//! by the speed of the execution.
 
 // TODO: document better
+// TODO: full mode could verify headers ahead of bodies, but this requires tracking a header chain
+//       and a fully-verified chain separately
 // TODO: this entire module needs clean up
 
 use crate::{
     chain::{blocks_tree, chain_information},
-    executor::{host, storage_diff},
+    chain_spec,
+    executor::{self, host, storage_diff},
     header,
-    trie::calculate_root,
+    trie::{self, calculate_root},
+    util::SipHasherBuild,
+    verify,
 };
 
 use alloc::{
@@ -59,15 +72,21 @@ use alloc::{
     vec::{self, Vec},
 };
 use core::{
-    cmp, fmt, iter, mem,
+    cmp, fmt,
+    hash::BuildHasher as _,
+    iter, mem,
     num::{NonZeroU32, NonZeroU64},
     ops,
     time::Duration,
 };
 use hashbrown::HashMap;
+use rand::{Rng as _, SeedableRng as _};
 
 mod verification_queue;
 
+pub use super::finalized_checkpoint::CheckpointError;
+pub use super::health::{SyncHealth, SYNCED_THRESHOLD};
+
 /// Configuration for the [`OptimisticSync`].
 #[derive(Debug)]
 pub struct Config {
@@ -87,6 +106,14 @@ pub struct Config {
     /// Should be set to the maximum number of block between two consecutive justifications.
     pub blocks_capacity: usize,
 
+    /// Pre-allocated capacity for the queue of blocks being downloaded or waiting to be
+    /// verified.
+    ///
+    /// This is independent of [`Config::blocks_capacity`]. Setting it to a value close to
+    /// [`Config::download_ahead_blocks`] avoids reallocations when syncing a large range of
+    /// blocks.
+    pub verification_queue_capacity: usize,
+
     /// Number of blocks to download ahead of the best block.
     ///
     /// Whenever the latest best block is updated, the state machine will start block
@@ -98,6 +125,41 @@ pub struct Config {
     /// block requests.
     pub download_ahead_blocks: NonZeroU32,
 
+    /// Seed used to determine the order in which the sources are returned by
+    /// [`OptimisticSync::desired_requests`]. For each block height, the sources are ordered
+    /// differently, so that requests are spread between sources rather than always targeting
+    /// the same source.
+    ///
+    /// Passing the same seed and performing the same operations always leads to the same
+    /// requests being returned, which can be useful for example for testing purposes.
+    pub randomness_seed: [u8; 32],
+
+    /// If `Some`, blocks whose height is more than this number of blocks above the height of
+    /// the finalized block aren't verified. Verifying blocks is then paused until the finalized
+    /// block catches up, and [`OptimisticSync::process_one`] returns
+    /// [`ProcessOne::WaitingForFinality`].
+    ///
+    /// > **Note**: Justifications are only ever found in blocks that have been verified. This
+    /// >           value must be larger than the distance between two consecutive blocks
+    /// >           containing a justification, otherwise syncing stalls forever.
+    pub max_blocks_ahead_of_finalized: Option<NonZeroU64>,
+
+    /// Number of consecutive failed requests after which a source is banned. See
+    /// [`OptimisticSync::finish_request_failed`].
+    ///
+    /// A value of 1 means that a source is banned as soon as one of its requests fails. Higher
+    /// values make it possible to tolerate occasional failures, such as timeouts, without taking
+    /// the source out of rotation.
+    pub request_failures_before_ban: NonZeroU32,
+
+    /// Maximum duration by which the slot of a block is allowed to be in the future compared to
+    /// the current time, in order to tolerate clock drifts. Blocks further in the future fail to
+    /// verify with [`ResetCause::TooFarInFuture`].
+    ///
+    /// Only applies to chains that use the Aura consensus engine. The slot of blocks using the
+    /// Babe consensus engine isn't compared with the current time.
+    pub aura_max_future_drift: Duration,
+
     /// If `Some`, the block bodies and storage are also synchronized. Contains the extra
     /// configuration.
     pub full: Option<ConfigFull>,
@@ -107,7 +169,86 @@ pub struct Config {
 #[derive(Debug)]
 pub struct ConfigFull {
     /// Compiled runtime code of the finalized block.
-    pub finalized_runtime: host::HostVmPrototype,
+    ///
+    /// If `None`, the runtime must later be provided by calling
+    /// [`OptimisticSync::set_finalized_runtime`]. Blocks can't be verified until this is done.
+    pub finalized_runtime: Option<host::HostVmPrototype>,
+
+    /// List of storage keys, in addition to `:code` and `:heappages`, whose modification
+    /// affects the runtime environment of the chain. Typically empty.
+    ///
+    /// Modifying `:code` or `:heappages` automatically leads to the runtime being recompiled.
+    /// This isn't the case for the keys in this list. After a block that modifies one of them
+    /// has been verified, [`OptimisticSync::process_one`] returns
+    /// [`ProcessOne::BestRuntimeOutdated`] until the API user rebuilds the runtime and passes it
+    /// to [`OptimisticSync::set_best_runtime`]. Finalizing such a block is reported through
+    /// [`JustificationVerification::Finalized`]. The runtime of the finalized block is then
+    /// considered as outdated as well, and if the chain is later reset to the finalized block,
+    /// [`OptimisticSync::process_one`] returns [`ProcessOne::BestRuntimeOutdated`] until either
+    /// [`OptimisticSync::set_best_runtime`] or [`OptimisticSync::set_finalized_runtime`] is
+    /// called.
+    pub extra_runtime_environment_keys: Vec<Vec<u8>>,
+}
+
+impl Config {
+    /// Builds a [`Config`] whose finalized block is the genesis block of the given chain
+    /// specification.
+    ///
+    /// [`Config::chain_information`] and [`Config::block_number_bytes`] are extracted from the
+    /// chain specification. If `full` is `true`, [`Config::full`] is filled with the runtime of
+    /// the genesis block and no [`ConfigFull::extra_runtime_environment_keys`].
+    ///
+    /// [`Config::max_blocks_ahead_of_finalized`] is set to `None`,
+    /// [`Config::request_failures_before_ban`] to 1, [`Config::aura_max_future_drift`] to 30
+    /// seconds, and [`Config::verification_queue_capacity`] to 0. The other fields are passed as
+    /// parameter.
+    pub fn from_chain_spec(
+        chain_spec: &chain_spec::ChainSpec,
+        full: bool,
+        sources_capacity: usize,
+        blocks_capacity: usize,
+        download_ahead_blocks: NonZeroU32,
+        randomness_seed: [u8; 32],
+    ) -> Result<Self, FromChainSpecError> {
+        let (chain_information, genesis_runtime) = chain_spec
+            .as_chain_information()
+            .map_err(FromChainSpecError::GenesisStorage)?;
+        let chain_information =
+            chain_information::ValidChainInformation::try_from(chain_information)
+                .map_err(FromChainSpecError::InvalidChainInformation)?;
+
+        Ok(Config {
+            chain_information,
+            block_number_bytes: usize::from(chain_spec.block_number_bytes()),
+            sources_capacity,
+            blocks_capacity,
+            verification_queue_capacity: 0,
+            download_ahead_blocks,
+            randomness_seed,
+            max_blocks_ahead_of_finalized: None,
+            request_failures_before_ban: NonZeroU32::new(1).unwrap(),
+            aura_max_future_drift: Duration::from_secs(30),
+            full: if full {
+                Some(ConfigFull {
+                    finalized_runtime: Some(genesis_runtime),
+                    extra_runtime_environment_keys: Vec::new(),
+                })
+            } else {
+                None
+            },
+        })
+    }
+}
+
+/// Error potentially returned by [`Config::from_chain_spec`].
+#[derive(Debug, derive_more::Display)]
+pub enum FromChainSpecError {
+    /// Failed to build the information about the genesis block.
+    #[display(fmt = "{}", _0)]
+    GenesisStorage(chain_spec::FromGenesisStorageError),
+    /// Information about the genesis block is invalid.
+    #[display(fmt = "Invalid genesis chain information: {}", _0)]
+    InvalidChainInformation(chain_information::ValidityError),
 }
 
 /// Identifier for an ongoing request in the [`OptimisticSync`].
@@ -124,12 +265,30 @@ impl RequestId {
     pub fn max_value() -> Self {
         Self(u64::max_value())
     }
+
+    /// Returns the numerical value of this identifier, for example for logging purposes.
+    ///
+    /// Identifiers are never re-used, meaning that two different requests of the same state
+    /// machine always have two different values.
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
 }
 
 /// Identifier for a source in the [`OptimisticSync`].
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct SourceId(u64);
 
+impl SourceId {
+    /// Returns the numerical value of this identifier, for example for logging purposes.
+    ///
+    /// Identifiers are never re-used, meaning that two different sources of the same state
+    /// machine always have two different values.
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
 /// Optimistic headers-only syncing.
 pub struct OptimisticSync<TRq, TSrc, TBl> {
     /// Data structure containing the blocks.
@@ -151,18 +310,45 @@ struct OptimisticSyncInner<TRq, TSrc, TBl> {
     /// Used if the `chain` field needs to be recreated.
     finalized_chain_information: blocks_tree::Config,
 
-    /// See [`ConfigFull::finalized_runtime`]. `None` in non-full mode.
+    /// `true` if [`Config::full`] was `Some`.
+    full_mode: bool,
+
+    /// See [`ConfigFull::finalized_runtime`]. `None` in non-full mode, or if the runtime hasn't
+    /// been provided yet.
     finalized_runtime: Option<host::HostVmPrototype>,
 
+    /// Storage keys whose modification affects the runtime environment. Contains `:code` and
+    /// `:heappages`, in that order, followed with [`ConfigFull::extra_runtime_environment_keys`].
+    /// Empty in non-full mode.
+    runtime_environment_keys: Vec<Vec<u8>>,
+
     /// Changes in the storage of the best block compared to the finalized block.
     /// The `BTreeMap`'s keys are storage keys, and its values are new values or `None` if the
     /// value has been erased from the storage.
     best_to_finalized_storage_diff: storage_diff::StorageDiff,
 
+    /// `true` if a finalized block has modified one of the
+    /// [`ConfigFull::extra_runtime_environment_keys`] and the runtime of the finalized block
+    /// hasn't been provided through [`OptimisticSync::set_finalized_runtime`] since then.
+    finalized_runtime_outdated: bool,
+
     /// Compiled runtime code of the best block. `None` if it is the same as
     /// [`OptimisticSyncInner::finalized_runtime`].
     best_runtime: Option<host::HostVmPrototype>,
 
+    /// Compiled runtime codes that have been replaced with a new one as the best block, alongside
+    /// with the height of the last block that uses each of them. Ordered by increasing height.
+    ///
+    /// These runtimes are neither the one of the finalized block nor the one of the best block,
+    /// and are kept because they become the runtime of the finalized block if one of the blocks
+    /// that use them is finalized.
+    intermediate_runtimes: Vec<(u64, host::HostVmPrototype)>,
+
+    /// `true` if a block between the finalized block and the best block modifies one of the
+    /// [`ConfigFull::extra_runtime_environment_keys`] and the runtime of the best block hasn't
+    /// been provided through [`OptimisticSync::set_best_runtime`] since then.
+    best_runtime_outdated: bool,
+
     /// Cache of calculation for the storage trie of the best block.
     /// Providing this value when verifying a block considerably speeds up the verification.
     top_trie_root_calculation_cache: Option<calculate_root::CalculationCache>,
@@ -170,6 +356,22 @@ struct OptimisticSyncInner<TRq, TSrc, TBl> {
     /// See [`Config::download_ahead_blocks`].
     download_ahead_blocks: NonZeroU32,
 
+    /// See [`Config::verification_queue_capacity`].
+    verification_queue_capacity: usize,
+
+    /// See [`Config::max_blocks_ahead_of_finalized`].
+    max_blocks_ahead_of_finalized: Option<NonZeroU64>,
+
+    /// See [`Config::request_failures_before_ban`].
+    request_failures_before_ban: NonZeroU32,
+
+    /// See [`Config::randomness_seed`].
+    randomness_seed: [u8; 32],
+
+    /// Hasher used to sort the sources in [`OptimisticSync::desired_requests`]. Derived from
+    /// [`Config::randomness_seed`].
+    sources_order: SipHasherBuild,
+
     /// List of sources of blocks.
     sources: HashMap<SourceId, Source<TSrc>, fnv::FnvBuildHasher>,
 
@@ -188,6 +390,23 @@ struct OptimisticSyncInner<TRq, TSrc, TBl> {
     /// Identifier to assign to the next request.
     next_request_id: RequestId,
 
+    /// If `true`, [`OptimisticSync::desired_requests`] doesn't return anything. See
+    /// [`OptimisticSync::set_paused`].
+    paused: bool,
+
+    /// Time, as provided by the API user, when the most recent blocks have been successfully
+    /// verified.
+    recent_verifications: super::verification_rate::VerificationRate,
+
+    /// Justifications, in the form `(consensus_engine_id, justification)`, that have finalized
+    /// the current finalized block. Empty if the current finalized block hasn't been finalized
+    /// through a justification verified by this state machine.
+    finalized_block_justifications: Vec<([u8; 4], Vec<u8>)>,
+
+    /// Details of all the requests that have been started and haven't been finished yet,
+    /// including obsolete requests.
+    requests_details: HashMap<RequestId, RequestDetail, fnv::FnvBuildHasher>,
+
     /// Requests that have been started but whose answers are no longer desired.
     obsolete_requests: HashMap<RequestId, (SourceId, TRq), fnv::FnvBuildHasher>,
 
@@ -199,7 +418,10 @@ impl<TRq, TSrc, TBl> OptimisticSyncInner<TRq, TSrc, TBl> {
     fn make_requests_obsolete(&mut self, chain: &blocks_tree::NonFinalizedTree<Block<TBl>>) {
         let former_queue = mem::replace(
             &mut self.verification_queue,
-            verification_queue::VerificationQueue::new(chain.best_block_header().number + 1),
+            verification_queue::VerificationQueue::new(
+                chain.best_block_header().number + 1,
+                self.verification_queue_capacity,
+            ),
         );
 
         for ((request_id, user_data), source) in former_queue.into_requests() {
@@ -218,6 +440,80 @@ impl<TRq, TSrc, TBl> OptimisticSyncInner<TRq, TSrc, TBl> {
         }
     }
 
+    /// Returns `true` if the given storage diff modifies one of the keys of
+    /// [`OptimisticSyncInner::runtime_environment_keys`].
+    fn modifies_runtime_environment(&self, diff: &storage_diff::StorageDiff) -> bool {
+        self.runtime_environment_keys
+            .iter()
+            .any(|key| diff.diff_get(key).is_some())
+    }
+
+    /// Returns `true` if the given diff modifies `:code` or `:heappages`, in which case the
+    /// runtime is recompiled.
+    fn modifies_runtime_code(&self, diff: &storage_diff::StorageDiff) -> bool {
+        self.runtime_environment_keys
+            .iter()
+            .take(2)
+            .any(|key| diff.diff_get(key).is_some())
+    }
+
+    /// Returns `true` if the given diff modifies one of the
+    /// [`ConfigFull::extra_runtime_environment_keys`].
+    fn modifies_extra_runtime_environment_keys(&self, diff: &storage_diff::StorageDiff) -> bool {
+        self.runtime_environment_keys
+            .iter()
+            .skip(2)
+            .any(|key| diff.diff_get(key).is_some())
+    }
+
+    /// Stores back the runtime that was extracted from [`OptimisticSyncInner::best_runtime`] or
+    /// [`OptimisticSyncInner::finalized_runtime`] in order to verify a block, now that this
+    /// block has been verified and is the new best block.
+    ///
+    /// `parent_height` is the height of the parent of the verified block.
+    fn store_verified_block_runtime(
+        &mut self,
+        storage_top_trie_changes: &storage_diff::StorageDiff,
+        parent_height: u64,
+        parent_runtime: host::HostVmPrototype,
+        new_runtime: Option<host::HostVmPrototype>,
+    ) {
+        debug_assert_eq!(
+            new_runtime.is_some(),
+            storage_top_trie_changes.diff_get(&b":code"[..]).is_some()
+                || storage_top_trie_changes
+                    .diff_get(&b":heappages"[..])
+                    .is_some()
+        );
+
+        // The runtime isn't recompiled if only one of the extra runtime environment keys has
+        // been modified. The API user must provide it.
+        if new_runtime.is_none() && self.modifies_runtime_environment(storage_top_trie_changes) {
+            self.best_runtime_outdated = true;
+        }
+
+        // Before the verification, we extracted the runtime either from `finalized_runtime` or
+        // `best_runtime`.
+        if self.finalized_runtime.is_some() {
+            // If `finalized_runtime` is still `Some` now, that means we have extracted from
+            // `best_runtime`.
+            self.best_runtime = if let Some(new_runtime) = new_runtime {
+                self.intermediate_runtimes
+                    .push((parent_height, parent_runtime));
+                Some(new_runtime)
+            } else {
+                Some(parent_runtime)
+            };
+        } else {
+            self.finalized_runtime = Some(parent_runtime);
+
+            debug_assert!(self.best_runtime.is_none());
+            if let Some(new_runtime) = new_runtime {
+                self.best_runtime = Some(new_runtime);
+            }
+        }
+    }
+
     fn with_requests_obsoleted(
         mut self: Box<Self>,
         chain: &blocks_tree::NonFinalizedTree<Block<TBl>>,
@@ -234,16 +530,38 @@ struct Source<TSrc> {
     /// Best block that the source has reported having.
     best_block_number: u64,
 
+    /// Value of `now_from_unix_epoch` passed to [`OptimisticSync::raise_source_best_block`]
+    /// the last time the best block of this source has been raised. Also set the first time
+    /// [`OptimisticSync::raise_source_best_block`] is called for this source. `None` if
+    /// [`OptimisticSync::raise_source_best_block`] has never been called for this source.
+    best_block_last_raised: Option<Duration>,
+
+    /// Value passed to [`OptimisticSync::add_source`]. If `true`, the source can't provide block
+    /// bodies.
+    header_only: bool,
+
     /// If `true`, this source is banned and shouldn't use be used to request blocks.
     /// Note that the ban is lifted if the source is removed. This ban isn't meant to be a line of
     /// defense against malicious peers but rather an optimization.
     banned: bool,
 
+    /// Reason why this source has been banned the most recent time, even if the ban has been
+    /// lifted since then. See [`OptimisticSync::source_ban_reason`].
+    last_ban_reason: Option<BanReason>,
+
     /// Number of requests that use this source.
     num_ongoing_requests: u32,
+
+    /// Number of requests of this source that have failed since the last successful one.
+    /// Compared with [`Config::request_failures_before_ban`].
+    num_consecutive_failures: u32,
+
+    /// See [`OptimisticSync::source_stats`].
+    stats: SourceStats,
 }
 
 // TODO: doc
+#[derive(Debug)]
 pub struct Block<TBl> {
     /// Header of the block.
     pub header: header::Header,
@@ -259,6 +577,7 @@ pub struct Block<TBl> {
 }
 
 // TODO: doc
+#[derive(Debug)]
 pub struct BlockFull {
     /// List of SCALE-encoded extrinsics that form the block's body.
     pub body: Vec<Vec<u8>>,
@@ -283,18 +602,36 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
             // a malicious node could send non-finalized blocks. Accepting blocks with an
             // unrecognized consensus engine doesn't add any additional risk.
             allow_unknown_consensus_engines: true,
+            aura_max_future_drift: config.aura_max_future_drift,
         };
 
         let chain = blocks_tree::NonFinalizedTree::new(blocks_tree_config.clone());
         let best_block_header_num = chain.best_block_header().number;
 
+        let full_mode = config.full.is_some();
+        let (finalized_runtime, runtime_environment_keys) = match config.full {
+            Some(full) => {
+                let mut keys = Vec::with_capacity(2 + full.extra_runtime_environment_keys.len());
+                keys.push(b":code".to_vec());
+                keys.push(b":heappages".to_vec());
+                keys.extend(full.extra_runtime_environment_keys);
+                (full.finalized_runtime, keys)
+            }
+            None => (None, Vec::new()),
+        };
+
         OptimisticSync {
             chain,
             inner: Box::new(OptimisticSyncInner {
                 finalized_chain_information: blocks_tree_config,
-                finalized_runtime: config.full.map(|f| f.finalized_runtime),
+                full_mode,
+                finalized_runtime,
+                runtime_environment_keys,
                 best_to_finalized_storage_diff: storage_diff::StorageDiff::empty(),
+                finalized_runtime_outdated: false,
                 best_runtime: None,
+                intermediate_runtimes: Vec::new(),
+                best_runtime_outdated: false,
                 top_trie_root_calculation_cache: None,
                 sources: HashMap::with_capacity_and_hasher(
                     config.sources_capacity,
@@ -303,10 +640,24 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
                 next_source_id: SourceId(0),
                 verification_queue: verification_queue::VerificationQueue::new(
                     best_block_header_num + 1,
+                    config.verification_queue_capacity,
                 ),
                 pending_encoded_justifications: Vec::new().into_iter(),
                 download_ahead_blocks: config.download_ahead_blocks,
+                verification_queue_capacity: config.verification_queue_capacity,
+                max_blocks_ahead_of_finalized: config.max_blocks_ahead_of_finalized,
+                request_failures_before_ban: config.request_failures_before_ban,
+                randomness_seed: config.randomness_seed,
+                sources_order: {
+                    let mut randomness =
+                        rand_chacha::ChaCha20Rng::from_seed(config.randomness_seed);
+                    SipHasherBuild::new(randomness.sample(rand::distributions::Standard))
+                },
                 next_request_id: RequestId(0),
+                paused: false,
+                recent_verifications: super::verification_rate::VerificationRate::new(),
+                finalized_block_justifications: Vec::new(),
+                requests_details: HashMap::with_capacity_and_hasher(0, Default::default()),
                 obsolete_requests: HashMap::with_capacity_and_hasher(0, Default::default()),
                 obsolete_requests_by_source: BTreeSet::new(),
             }),
@@ -319,6 +670,102 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
         self.chain.as_chain_information()
     }
 
+    /// Similar to [`OptimisticSync::as_chain_information`], but returns an owned value.
+    ///
+    /// This is typically useful in order to initialize a new state machine.
+    pub fn finalized_chain_information_owned(&self) -> chain_information::ValidChainInformation {
+        self.as_chain_information().into()
+    }
+
+    /// Resets the state machine so that its finalized block is the one described by
+    /// `chain_information`, which must be the current finalized block or one of its ancestors.
+    ///
+    /// `ancestry` must yield the SCALE-encoded headers of the blocks between the finalized block
+    /// of `chain_information` (exclusive) and the current finalized block (exclusive), in
+    /// decreasing block number order. On error, the state machine is left untouched.
+    ///
+    /// All the non-finalized blocks are discarded and returned, in an order in which parents are
+    /// found before their children. All ongoing requests become obsolete, and the pending
+    /// justifications and the verification speed estimation are discarded.
+    ///
+    /// If [`Config::full`] was `Some`, the runtime of the new finalized block must then be
+    /// provided by calling [`OptimisticSync::set_finalized_runtime`].
+    ///
+    /// > **Warning**: This operation goes against the very definition of finality, and should
+    /// >              only be used in order to recover from a situation where blocks have been
+    /// >              wrongly finalized, for example because of a bug or of a compromised
+    /// >              trusted setup. Apart from its ancestry, the validity of
+    /// >              `chain_information` isn't verified.
+    pub fn reset_to_finalized_checkpoint(
+        &mut self,
+        chain_information: chain_information::ValidChainInformation,
+        ancestry: impl Iterator<Item = impl AsRef<[u8]>>,
+    ) -> Result<Vec<Block<TBl>>, CheckpointError> {
+        super::finalized_checkpoint::verify_ancestry(
+            chain_information.as_ref().finalized_block_header,
+            self.chain.finalized_block_header(),
+            ancestry,
+        )?;
+
+        self.inner.finalized_chain_information.chain_information = chain_information;
+        let former_chain = mem::replace(
+            &mut self.chain,
+            blocks_tree::NonFinalizedTree::new(self.inner.finalized_chain_information.clone()),
+        );
+
+        self.inner.make_requests_obsolete(&self.chain);
+        self.inner.best_to_finalized_storage_diff.clear();
+        self.inner.best_runtime = None;
+        self.inner.intermediate_runtimes.clear();
+        self.inner.best_runtime_outdated = false;
+        self.inner.finalized_runtime = None;
+        self.inner.finalized_runtime_outdated = false;
+        self.inner.top_trie_root_calculation_cache = None;
+        self.inner.pending_encoded_justifications = Vec::new().into_iter();
+        self.inner.finalized_block_justifications.clear();
+        self.inner.recent_verifications.clear();
+
+        Ok(former_chain
+            .into_iter_ancestry_order()
+            .map(|(_, block)| block)
+            .collect())
+    }
+
+    /// Returns the justifications, in the form `(consensus_engine_id, justification)`, that
+    /// prove the finality of the current finalized block.
+    ///
+    /// The returned list is empty if the current finalized block hasn't been finalized by
+    /// verifying a justification, for example if it is the block the state machine has been
+    /// initialized with.
+    pub fn finalized_block_justifications(&self) -> &[([u8; 4], Vec<u8>)] {
+        &self.inner.finalized_block_justifications
+    }
+
+    /// Returns the list of GrandPa authorities that must finalize the children of the latest
+    /// finalized block, or `None` if the chain doesn't use GrandPa for its finality.
+    pub fn current_grandpa_authorities(&self) -> Option<&[header::GrandpaAuthority]> {
+        match self.chain.as_chain_information().as_ref().finality {
+            chain_information::ChainInformationFinalityRef::Grandpa {
+                finalized_triggered_authorities,
+                ..
+            } => Some(finalized_triggered_authorities),
+            chain_information::ChainInformationFinalityRef::Outsourced => None,
+        }
+    }
+
+    /// Returns the identifier of the GrandPa authorities set returned by
+    /// [`OptimisticSync::current_grandpa_authorities`], or `None` if the chain doesn't use
+    /// GrandPa for its finality.
+    pub fn current_authority_set_id(&self) -> Option<u64> {
+        match self.chain.as_chain_information().as_ref().finality {
+            chain_information::ChainInformationFinalityRef::Grandpa {
+                after_finalized_block_authorities_set_id,
+                ..
+            } => Some(after_finalized_block_authorities_set_id),
+            chain_information::ChainInformationFinalityRef::Outsourced => None,
+        }
+    }
+
     /// Returns the header of the finalized block.
     pub fn finalized_block_header(&self) -> header::HeaderRef {
         self.inner
@@ -352,16 +799,77 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
         self.chain.best_block_hash()
     }
 
+    /// Returns `true` if the block with the given height and hash is the current best block.
+    ///
+    /// Returns `false` if the hash matches the best block but the height doesn't.
+    pub fn is_best_block(&self, height: u64, hash: &[u8; 32]) -> bool {
+        self.chain.best_block_header().number == height && self.chain.best_block_hash() == *hash
+    }
+
     /// Returns consensus information about the current best block of the chain.
     pub fn best_block_consensus(&self) -> chain_information::ChainInformationConsensusRef {
         self.chain.best_block_consensus()
     }
 
+    /// Sets the compiled runtime code of the finalized block.
+    ///
+    /// Must be called if [`ConfigFull::finalized_runtime`] was `None`, otherwise blocks can't be
+    /// verified. Calling this method again replaces the runtime.
+    ///
+    /// The runtime must be built against the storage of the finalized block. If the finalized
+    /// block is also the best block, this clears [`ProcessOne::BestRuntimeOutdated`].
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`Config::full`] was `None`.
+    ///
+    pub fn set_finalized_runtime(&mut self, runtime: host::HostVmPrototype) {
+        assert!(self.inner.full_mode);
+        self.inner.finalized_runtime = Some(runtime);
+        self.inner.finalized_runtime_outdated = false;
+        if self.chain.is_empty() && self.inner.best_runtime.is_none() {
+            self.inner.best_runtime_outdated = false;
+        }
+    }
+
+    /// Sets the compiled runtime code of the best block.
+    ///
+    /// Must be called after [`OptimisticSync::process_one`] has returned
+    /// [`ProcessOne::BestRuntimeOutdated`], otherwise blocks can't be verified. The runtime must
+    /// be built against the storage of the current best block.
+    ///
+    /// # Panic
+    ///
+    /// Panics if [`Config::full`] was `None`.
+    ///
+    pub fn set_best_runtime(&mut self, runtime: host::HostVmPrototype) {
+        assert!(self.inner.full_mode);
+        self.inner.best_runtime = Some(runtime);
+        self.inner.best_runtime_outdated = false;
+    }
+
+    /// Returns the runtime of the finalized block.
+    ///
+    /// Returns `None` if [`Config::full`] was `None`, or if the runtime of the finalized block
+    /// hasn't been provided yet. See [`OptimisticSync::set_finalized_runtime`].
+    pub fn finalized_block_runtime(&self) -> Option<&host::HostVmPrototype> {
+        if self.inner.full_mode {
+            self.inner.finalized_runtime.as_ref()
+        } else {
+            None
+        }
+    }
+
     /// Returns access to the storage of the best block.
     ///
-    /// Returns `None` if [`Config::full`] was `None`.
+    /// Returns `None` if [`Config::full`] was `None`, if the runtime of the finalized block
+    /// hasn't been provided yet, or if the runtime of the best block is outdated. See
+    /// [`ProcessOne::BestRuntimeOutdated`].
     pub fn best_block_storage(&self) -> Option<BlockStorage<TRq, TSrc, TBl>> {
-        if self.inner.finalized_runtime.is_some() {
+        if self.inner.full_mode
+            && !self.inner.best_runtime_outdated
+            && (self.inner.best_runtime.is_some() || self.inner.finalized_runtime.is_some())
+        {
             Some(BlockStorage { inner: self })
         } else {
             None
@@ -387,9 +895,14 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
     }
 
     /// Disassembles the state machine into its raw components.
-    pub fn disassemble(self) -> Disassemble<TRq, TSrc> {
+    pub fn disassemble(self) -> Disassemble<TRq, TSrc, TBl> {
         Disassemble {
             chain_information: self.inner.finalized_chain_information.chain_information,
+            non_finalized_blocks: self
+                .chain
+                .into_iter_ancestry_order()
+                .map(|(_, block)| block)
+                .collect(),
             sources: self
                 .inner
                 .sources
@@ -398,6 +911,7 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
                     id,
                     user_data: source.user_data,
                     best_block_number: source.best_block_number,
+                    header_only: source.header_only,
                 })
                 .collect(),
             requests: self
@@ -410,7 +924,18 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
     }
 
     /// Inform the [`OptimisticSync`] of a new potential source of blocks.
-    pub fn add_source(&mut self, source: TSrc, best_block_number: u64) -> SourceId {
+    ///
+    /// If `header_only` is `true`, the source is assumed to be unable to provide block bodies.
+    /// If [`Config::full`] is `Some`, block bodies are necessary in order to verify blocks, and
+    /// such a source is never returned by [`OptimisticSync::desired_requests`]. If
+    /// [`Config::full`] is `None`, only headers and justifications are ever requested and this
+    /// flag has no effect.
+    pub fn add_source(
+        &mut self,
+        source: TSrc,
+        best_block_number: u64,
+        header_only: bool,
+    ) -> SourceId {
         let new_id = {
             let id = self.inner.next_source_id;
             self.inner.next_source_id.0 += 1;
@@ -422,14 +947,99 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
             Source {
                 user_data: source,
                 best_block_number,
+                best_block_last_raised: None,
+                header_only,
                 banned: false,
+                last_ban_reason: None,
                 num_ongoing_requests: 0,
+                num_consecutive_failures: 0,
+                stats: SourceStats::default(),
             },
         );
 
         new_id
     }
 
+    /// Similar to [`OptimisticSync::add_source`], but first checks whether one of the existing
+    /// sources refers to the same underlying source, for example the same peer that has been
+    /// added twice.
+    ///
+    /// `is_same` is called with the user data of each existing source and the user data of the
+    /// new source, and must return `true` if they refer to the same source. If it returns `true`
+    /// for one of the sources, no new source is added. Instead, the ability to provide block
+    /// bodies of the existing source is updated to the best of the two, and its best block is
+    /// raised as if [`OptimisticSync::raise_source_best_block`] was called with
+    /// `now_from_unix_epoch`.
+    ///
+    /// Use [`OptimisticSync::add_source`] if the API user already guarantees that the same
+    /// source isn't added multiple times.
+    pub fn add_source_dedup(
+        &mut self,
+        source: TSrc,
+        best_block_number: u64,
+        header_only: bool,
+        now_from_unix_epoch: Duration,
+        mut is_same: impl FnMut(&TSrc, &TSrc) -> bool,
+    ) -> AddSourceDedup<TSrc> {
+        let existing = self
+            .inner
+            .sources
+            .iter_mut()
+            .find(|(_, existing)| is_same(&existing.user_data, &source));
+
+        if let Some((source_id, existing)) = existing {
+            let source_id = *source_id;
+            existing.header_only &= header_only;
+            self.raise_source_best_block(source_id, best_block_number, now_from_unix_epoch);
+            return AddSourceDedup::Existing {
+                source_id,
+                user_data: source,
+            };
+        }
+
+        AddSourceDedup::New(self.add_source(source, best_block_number, header_only))
+    }
+
+    /// Inserts back in the [`OptimisticSync`] sources that were obtained through
+    /// [`OptimisticSync::disassemble`], while preserving their [`SourceId`].
+    ///
+    /// Contrary to [`OptimisticSync::add_source`], which always allocates a new [`SourceId`],
+    /// this guarantees that the identifiers held by the API user, for example in the user data
+    /// of requests, remain valid. New sources added afterwards are guaranteed to not collide
+    /// with these identifiers.
+    ///
+    /// # Panic
+    ///
+    /// Panics if one of the [`SourceId`]s is already in use in this state machine.
+    ///
+    pub fn add_disassembled_sources(
+        &mut self,
+        sources: impl IntoIterator<Item = DisassembleSource<TSrc>>,
+    ) {
+        for source in sources {
+            assert!(!self.inner.sources.contains_key(&source.id));
+
+            if source.id.0 >= self.inner.next_source_id.0 {
+                self.inner.next_source_id = SourceId(source.id.0 + 1);
+            }
+
+            self.inner.sources.insert(
+                source.id,
+                Source {
+                    user_data: source.user_data,
+                    best_block_number: source.best_block_number,
+                    best_block_last_raised: None,
+                    header_only: source.header_only,
+                    banned: false,
+                    last_ban_reason: None,
+                    num_ongoing_requests: 0,
+                    num_consecutive_failures: 0,
+                    stats: SourceStats::default(),
+                },
+            );
+        }
+    }
+
     /// Returns the current best block of the given source.
     ///
     /// This corresponds either the latest call to [`OptimisticSync::raise_source_best_block`],
@@ -451,17 +1061,53 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
     ///
     /// Has no effect if the previously-known best block is lower than the new one.
     ///
+    /// The `now_from_unix_epoch` parameter is used to track how long ago the best block of the
+    /// source has last been raised. See [`OptimisticSync::stale_sources`].
+    ///
     /// # Panic
     ///
     /// Panics if the [`SourceId`] is invalid.
     ///
-    pub fn raise_source_best_block(&mut self, id: SourceId, best_block_number: u64) {
-        let current = &mut self.inner.sources.get_mut(&id).unwrap().best_block_number;
-        if *current < best_block_number {
-            *current = best_block_number;
+    pub fn raise_source_best_block(
+        &mut self,
+        id: SourceId,
+        best_block_number: u64,
+        now_from_unix_epoch: Duration,
+    ) {
+        let source = self.inner.sources.get_mut(&id).unwrap();
+        if source.best_block_number < best_block_number {
+            source.best_block_number = best_block_number;
+            source.best_block_last_raised = Some(now_from_unix_epoch);
+        } else if source.best_block_last_raised.is_none() {
+            source.best_block_last_raised = Some(now_from_unix_epoch);
         }
     }
 
+    /// Returns the list of sources whose best block hasn't been raised for at least `threshold`.
+    ///
+    /// The moment when the best block of a source has last been raised is determined from the
+    /// `now_from_unix_epoch` passed to [`OptimisticSync::raise_source_best_block`]. Sources for
+    /// which [`OptimisticSync::raise_source_best_block`] has never been called are never
+    /// returned.
+    ///
+    /// The API user is encouraged to use this function in order to find sources that have
+    /// fallen behind the rest of the network, and for example replace them with other sources.
+    pub fn stale_sources(
+        &'_ self,
+        now_from_unix_epoch: Duration,
+        threshold: Duration,
+    ) -> impl Iterator<Item = SourceId> + '_ {
+        self.inner
+            .sources
+            .iter()
+            .filter(move |(_, source)| {
+                source.best_block_last_raised.map_or(false, |last_raised| {
+                    now_from_unix_epoch.saturating_sub(last_raised) >= threshold
+                })
+            })
+            .map(|(id, _)| *id)
+    }
+
     /// Inform the [`OptimisticSync`] that a source of blocks is no longer available.
     ///
     /// This automatically cancels all the requests that have been emitted for this source.
@@ -497,6 +1143,10 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
             self.inner.obsolete_requests_by_source.len()
         );
 
+        self.inner
+            .requests_details
+            .retain(|_, detail| detail.source_id != source_id);
+
         let src_user_data = self.inner.sources.remove(&source_id).unwrap().user_data;
         let drain = RequestsDrain {
             iter: self.inner.verification_queue.drain_source(source_id),
@@ -509,6 +1159,18 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
         self.inner.sources.keys().copied()
     }
 
+    /// Returns the list of sources whose best block, as tracked by this state machine, has a
+    /// height superior or equal to the given height.
+    ///
+    /// See also [`OptimisticSync::source_best_block`].
+    pub fn sources_at_or_above(&'_ self, height: u64) -> impl Iterator<Item = SourceId> + '_ {
+        self.inner
+            .sources
+            .iter()
+            .filter(move |(_, source)| source.best_block_number >= height)
+            .map(|(source_id, _)| *source_id)
+    }
+
     /// Returns the number of ongoing requests that concern this source.
     ///
     /// # Panic
@@ -528,6 +1190,30 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
         num_obsolete + num_regular
     }
 
+    /// Returns the counters of the requests that have been finished for this source.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SourceId`] is invalid.
+    ///
+    pub fn source_stats(&self, source_id: SourceId) -> SourceStats {
+        self.inner.sources.get(&source_id).unwrap().stats.clone()
+    }
+
+    /// Returns the reason why the given source has been banned the most recent time, or `None`
+    /// if it has never been banned.
+    ///
+    /// Banned sources aren't used for requests. Since all sources are unbanned when all of them
+    /// are banned, the returned value might concern a ban that has been lifted since then.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`SourceId`] is invalid.
+    ///
+    pub fn source_ban_reason(&self, source_id: SourceId) -> Option<BanReason> {
+        self.inner.sources.get(&source_id).unwrap().last_ban_reason
+    }
+
     /// Returns an iterator that yields all the requests whose outcome is no longer desired.
     pub fn obsolete_requests(&'_ self) -> impl Iterator<Item = (RequestId, &'_ TRq)> + '_ {
         self.inner
@@ -536,13 +1222,86 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
             .map(|(id, (_, ud))| (*id, ud))
     }
 
+    /// Removes from the state machine all the requests whose outcome is no longer desired, in
+    /// other words the requests that [`OptimisticSync::obsolete_requests`] would yield, and
+    /// returns their user data.
+    ///
+    /// This is equivalent to calling [`OptimisticSync::finish_request_failed`] for each of these
+    /// requests. The removed requests are counted in [`SourceStats::num_requests_obsolete`].
+    pub fn drain_obsolete_requests(&mut self) -> impl Iterator<Item = (RequestId, TRq)> {
+        for (request_id, (source_id, _)) in &self.inner.obsolete_requests {
+            self.inner.requests_details.remove(request_id);
+            let source = self.inner.sources.get_mut(source_id).unwrap();
+            source.num_ongoing_requests -= 1;
+            source.stats.num_requests_obsolete += 1;
+        }
+
+        self.inner.obsolete_requests_by_source.clear();
+        mem::take(&mut self.inner.obsolete_requests)
+            .into_iter()
+            .map(|(request_id, (_, user_data))| (request_id, user_data))
+    }
+
+    /// Returns `true` if the outcome of the given request is no longer desired, in other words
+    /// if it would be yielded by [`OptimisticSync::obsolete_requests`].
+    ///
+    /// Contrary to [`OptimisticSync::finish_request_success`], this function doesn't modify the
+    /// state machine. It can be used in order to discard a response early.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the [`RequestId`] is invalid.
+    ///
+    pub fn is_request_obsolete(&self, request_id: RequestId) -> bool {
+        assert!(self.inner.requests_details.contains_key(&request_id));
+        self.inner.obsolete_requests.contains_key(&request_id)
+    }
+
+    /// Pauses or resumes the generation of new requests.
+    ///
+    /// While paused, [`OptimisticSync::desired_requests`] doesn't return any request. Requests
+    /// that are already in progress aren't affected, and their responses can still be processed
+    /// normally.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.inner.paused = paused;
+    }
+
+    /// Returns the value that was passed as [`Config::randomness_seed`].
+    ///
+    /// The order in which sources are picked for the requests of each block height is derived
+    /// from this seed. Two [`OptimisticSync`]s built with the same seed and that are passed the
+    /// same sequence of calls and responses emit the same requests to the same sources.
+    pub fn randomness_seed(&self) -> [u8; 32] {
+        self.inner.randomness_seed
+    }
+
+    /// Returns `true` if [`OptimisticSync::set_paused`] has last been called with `true`.
+    pub fn is_paused(&self) -> bool {
+        self.inner.paused
+    }
+
     /// Returns an iterator that yields all requests that could be started.
+    ///
+    /// Always empty if the state machine is paused. See [`OptimisticSync::set_paused`].
     pub fn desired_requests(&'_ self) -> impl Iterator<Item = RequestDetail> + '_ {
         let sources = &self.inner.sources;
+        let sources_order = &self.inner.sources_order;
+        let full_mode = self.inner.full_mode;
         self.inner
             .verification_queue
             .desired_requests(self.inner.download_ahead_blocks)
-            .flat_map(move |e| sources.iter().map(move |s| (e, s)))
+            .filter(move |_| !self.inner.paused)
+            .flat_map(move |e| {
+                // The sources are ordered in a way that depends on the randomness seed and on
+                // the block height, in order to not always favor the same source.
+                // In full mode, sources that can't provide block bodies are useless.
+                let mut candidates = sources
+                    .iter()
+                    .filter(|(_, source)| !(full_mode && source.header_only))
+                    .collect::<Vec<_>>();
+                candidates.sort_by_key(|(source_id, _)| sources_order.hash_one((source_id, e.0)));
+                candidates.into_iter().map(move |s| (e, s))
+            })
             .filter_map(|((block_height, num_blocks), (source_id, source))| {
                 let source_avail_blocks = NonZeroU32::new(
                     u32::try_from(source.best_block_number.checked_sub(block_height.get())? + 1)
@@ -557,6 +1316,70 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
             })
     }
 
+    /// Returns the details of the given request, as passed to [`OptimisticSync::insert_request`].
+    ///
+    /// Returns `None` if the [`RequestId`] is invalid, for example because the request has
+    /// already been finished.
+    pub fn request_detail(&self, request_id: RequestId) -> Option<&RequestDetail> {
+        self.inner.requests_details.get(&request_id)
+    }
+
+    /// Returns an estimation of the number of blocks successfully verified per second, based on
+    /// the time passed to [`BlockVerify::start`] for the most recently verified blocks.
+    ///
+    /// Returns `None` if not enough blocks have been verified yet to give an estimate.
+    pub fn blocks_per_second(&self) -> Option<f64> {
+        self.inner.recent_verifications.blocks_per_second()
+    }
+
+    /// Returns a summary of the state of the state machine.
+    pub fn health(&self) -> SyncHealth {
+        SyncHealth::new(
+            (
+                self.chain.best_block_header().number,
+                self.chain.best_block_hash(),
+            ),
+            (
+                self.chain.finalized_block_header().number,
+                self.chain.finalized_block_hash(),
+            ),
+            self.inner.sources.len(),
+            self.inner.requests_details.len(),
+            self.inner
+                .sources
+                .values()
+                .map(|source| source.best_block_number),
+        )
+    }
+
+    /// Returns the number of blocks, out of the [`Config::download_ahead_blocks`] blocks
+    /// following the current best block, that haven't been requested or downloaded yet.
+    ///
+    /// This corresponds to the number of blocks that can still be covered by the requests
+    /// returned by [`OptimisticSync::desired_requests`]. It can be used in order to pace the
+    /// emission of new requests.
+    pub fn download_ahead_blocks_remaining(&self) -> u32 {
+        self.inner
+            .verification_queue
+            .num_missing_blocks(self.inner.download_ahead_blocks)
+    }
+
+    /// Returns `true` if no source has a best block high enough to provide the last block of
+    /// the [`Config::download_ahead_blocks`] blocks following the current best block.
+    ///
+    /// If `true`, [`OptimisticSync::desired_requests`] can't fill the download-ahead window
+    /// because of the sources, and increasing [`Config::download_ahead_blocks`] wouldn't lead
+    /// to more blocks being downloaded. If `false`, the number of blocks being downloaded is
+    /// limited by [`Config::download_ahead_blocks`].
+    pub fn is_download_ahead_source_limited(&self) -> bool {
+        let window_end = self
+            .chain
+            .best_block_header()
+            .number
+            .saturating_add(u64::from(self.inner.download_ahead_blocks.get()));
+        self.sources_at_or_above(window_end).next().is_none()
+    }
+
     /// Updates the [`OptimisticSync`] with the fact that a request has been started.
     ///
     /// Returns the identifier for the request that must later be passed back to
@@ -576,6 +1399,12 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
         let request_id = self.inner.next_request_id;
         self.inner.next_request_id.0 += 1;
 
+        let _prev_value = self
+            .inner
+            .requests_details
+            .insert(request_id, detail.clone());
+        debug_assert!(_prev_value.is_none());
+
         match self.inner.verification_queue.insert_request(
             detail.block_height,
             detail.num_blocks,
@@ -622,6 +1451,8 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
         request_id: RequestId,
         blocks: impl Iterator<Item = RequestSuccessBlock<TBl>>,
     ) -> (TRq, FinishRequestOutcome) {
+        self.inner.requests_details.remove(&request_id);
+
         if let Some((source_id, user_data)) = self.inner.obsolete_requests.remove(&request_id) {
             self.inner.obsolete_requests.shrink_to_fit();
             let _was_in = self
@@ -633,11 +1464,9 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
                 self.inner.obsolete_requests.len(),
                 self.inner.obsolete_requests_by_source.len()
             );
-            self.inner
-                .sources
-                .get_mut(&source_id)
-                .unwrap()
-                .num_ongoing_requests -= 1;
+            let source = self.inner.sources.get_mut(&source_id).unwrap();
+            source.num_ongoing_requests -= 1;
+            source.stats.num_requests_obsolete += 1;
             return (user_data, FinishRequestOutcome::Obsolete);
         }
 
@@ -646,17 +1475,19 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
             .verification_queue
             .finish_request(|(rq, _)| *rq == request_id, Ok(blocks));
 
-        self.inner
-            .sources
-            .get_mut(&source_id)
-            .unwrap()
-            .num_ongoing_requests -= 1;
+        let source = self.inner.sources.get_mut(&source_id).unwrap();
+        source.num_ongoing_requests -= 1;
+        source.num_consecutive_failures = 0;
+        source.stats.num_requests_succeeded += 1;
 
         (user_data, FinishRequestOutcome::Queued)
     }
 
     /// Update the [`OptimisticSync`] with the information that the given request has failed.
     ///
+    /// The source the request was targeting is banned if this failure brings its number of
+    /// consecutive failed requests to [`Config::request_failures_before_ban`].
+    ///
     /// Returns the user data that was associated to that request.
     ///
     /// # Panic
@@ -664,6 +1495,8 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
     /// Panics if the [`RequestId`] is invalid.
     ///
     pub fn finish_request_failed(&mut self, request_id: RequestId) -> TRq {
+        self.inner.requests_details.remove(&request_id);
+
         if let Some((source_id, user_data)) = self.inner.obsolete_requests.remove(&request_id) {
             self.inner.obsolete_requests.shrink_to_fit();
             let _was_in = self
@@ -675,11 +1508,9 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
                 self.inner.obsolete_requests.len(),
                 self.inner.obsolete_requests_by_source.len()
             );
-            self.inner
-                .sources
-                .get_mut(&source_id)
-                .unwrap()
-                .num_ongoing_requests -= 1;
+            let source = self.inner.sources.get_mut(&source_id).unwrap();
+            source.num_ongoing_requests -= 1;
+            source.stats.num_requests_obsolete += 1;
             return user_data;
         }
 
@@ -688,13 +1519,19 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
             Result::<iter::Empty<_>, _>::Err(()),
         );
 
-        self.inner
-            .sources
-            .get_mut(&source_id)
-            .unwrap()
-            .num_ongoing_requests -= 1;
+        let source = self.inner.sources.get_mut(&source_id).unwrap();
+        source.num_ongoing_requests -= 1;
+        source.stats.num_requests_failed += 1;
+        source.num_consecutive_failures = source.num_consecutive_failures.saturating_add(1);
 
-        self.inner.sources.get_mut(&source_id).unwrap().banned = true;
+        // The source is only banned after too many consecutive failures, in order to tolerate
+        // transient problems.
+        if source.num_consecutive_failures < self.inner.request_failures_before_ban.get() {
+            return user_data;
+        }
+
+        source.banned = true;
+        source.last_ban_reason = Some(BanReason::RequestFailed);
 
         // If all sources are banned, unban them.
         if self.inner.sources.iter().all(|(_, s)| s.banned) {
@@ -727,6 +1564,27 @@ impl<TRq, TSrc, TBl> OptimisticSync<TRq, TSrc, TBl> {
         // confirms that a block is ready. If the `Verify` is dropped without `start` being called,
         // the block stays in the list.
         if self.inner.verification_queue.blocks_ready() {
+            // The block ready to be verified is always the child of the current best block.
+            if let Some(max) = self.inner.max_blocks_ahead_of_finalized {
+                let next_height = self.chain.best_block_header().number + 1;
+                let finalized_height = self.chain.finalized_block_header().number;
+                if next_height > finalized_height.saturating_add(max.get()) {
+                    return ProcessOne::WaitingForFinality { sync: self };
+                }
+            }
+
+            // In full mode, verifying the block requires the runtime of its parent.
+            if self.inner.full_mode
+                && self.inner.best_runtime.is_none()
+                && self.inner.finalized_runtime.is_none()
+            {
+                return ProcessOne::FinalizedRuntimeMissing { sync: self };
+            }
+
+            if self.inner.best_runtime_outdated {
+                return ProcessOne::BestRuntimeOutdated { sync: self };
+            }
+
             ProcessOne::VerifyBlock(BlockVerify {
                 inner: self.inner,
                 chain: self.chain,
@@ -772,12 +1630,51 @@ pub enum ProcessOne<TRq, TSrc, TBl> {
         sync: OptimisticSync<TRq, TSrc, TBl>,
     },
 
+    /// A block is ready to be verified, but [`ConfigFull::finalized_runtime`] was `None` and
+    /// [`OptimisticSync::set_finalized_runtime`] hasn't been called yet.
+    ///
+    /// [`OptimisticSync::set_finalized_runtime`] must be called before blocks can be verified.
+    FinalizedRuntimeMissing {
+        /// The state machine.
+        /// The [`OptimisticSync::process_one`] method takes ownership of the
+        /// [`OptimisticSync`]. This field yields it back.
+        sync: OptimisticSync<TRq, TSrc, TBl>,
+    },
+
+    /// A block is ready to be verified, but a previously-verified block has modified one of the
+    /// [`ConfigFull::extra_runtime_environment_keys`].
+    ///
+    /// [`OptimisticSync::set_best_runtime`] must be called with a runtime rebuilt against the
+    /// storage of the best block before blocks can be verified.
+    BestRuntimeOutdated {
+        /// The state machine.
+        /// The [`OptimisticSync::process_one`] method takes ownership of the
+        /// [`OptimisticSync`]. This field yields it back.
+        sync: OptimisticSync<TRq, TSrc, TBl>,
+    },
+
+    /// A block is ready to be verified, but it is too far ahead of the finalized block. See
+    /// [`Config::max_blocks_ahead_of_finalized`].
+    ///
+    /// Calling [`OptimisticSync::process_one`] again is unnecessary until a new block has been
+    /// finalized.
+    WaitingForFinality {
+        /// The state machine.
+        /// The [`OptimisticSync::process_one`] method takes ownership of the
+        /// [`OptimisticSync`]. This field yields it back.
+        sync: OptimisticSync<TRq, TSrc, TBl>,
+    },
+
     VerifyBlock(BlockVerify<TRq, TSrc, TBl>),
 
     VerifyJustification(JustificationVerify<TRq, TSrc, TBl>),
 }
 
 /// See [`OptimisticSync::best_block_storage`].
+///
+/// > **Note**: Only the differences between the storage of the best block and the storage of
+/// >           the finalized block are stored in the [`OptimisticSync`]. Methods of this struct
+/// >           that need the storage of the finalized block must be passed it by the caller.
 pub struct BlockStorage<'a, TRq, TSrc, TBl> {
     inner: &'a OptimisticSync<TRq, TSrc, TBl>,
 }
@@ -814,6 +1711,64 @@ impl<'a, TRq, TSrc, TBl> BlockStorage<'a, TRq, TSrc, TBl> {
             .best_to_finalized_storage_diff
             .storage_prefix_keys_ordered(prefix, in_finalized_ordered)
     }
+
+    /// Builds a Merkle proof of the storage values of the given keys in this block.
+    ///
+    /// Must be passed the list of all the storage entries of the finalized block, in no specific
+    /// order. The proof is built over the trie of the finalized block modified with the
+    /// differences between the best and the finalized block.
+    ///
+    /// The trie nodes of the finalized block aren't kept in memory, and the Merkle values of the
+    /// nodes of the proof are calculated from all the entries of the storage. Passing only some
+    /// of the entries of the finalized block leads to an invalid proof. Building a proof is
+    /// consequently expensive, and API users should cache the proof if they need it multiple
+    /// times.
+    ///
+    /// The trie entries are encoded using the state version of [`BlockStorage::runtime`], which
+    /// is obtained by calling `Core_version`.
+    ///
+    /// Returns a list of trie node values. See [`trie::proof_encode::build_proof`].
+    pub fn storage_proof<'f>(
+        &'f self,
+        finalized_storage: impl Iterator<Item = (&'f [u8], &'f [u8])>,
+        keys: &[&[u8]],
+    ) -> Result<Vec<Vec<u8>>, StorageProofError> {
+        let state_version = match executor::core_version(self.runtime().clone()).0 {
+            Ok(runtime_spec) => match runtime_spec.decode().state_version {
+                Some(0) | None => trie::TrieEntryVersion::V0,
+                Some(1) => trie::TrieEntryVersion::V1,
+                Some(_) => return Err(StorageProofError::UnknownStateVersion),
+            },
+            Err(err) => return Err(StorageProofError::CoreVersion(err)),
+        };
+
+        let diff = &self.inner.inner.best_to_finalized_storage_diff;
+
+        let entries = finalized_storage
+            .filter(|(key, _)| diff.diff_get(key).is_none())
+            .chain(
+                diff.diff_iter_unordered()
+                    .filter_map(|(key, value)| Some((key, value?))),
+            );
+
+        Ok(trie::proof_encode::build_proof(
+            trie::proof_encode::Config {
+                entries,
+                requested_keys: keys.iter().copied(),
+                version: state_version,
+            },
+        ))
+    }
+}
+
+/// Error potentially returned by [`BlockStorage::storage_proof`].
+#[derive(Debug, derive_more::Display, Clone)]
+pub enum StorageProofError {
+    /// Error while obtaining the runtime version of the runtime of the block.
+    #[display(fmt = "Failed to obtain the runtime version: {}", _0)]
+    CoreVersion(executor::CoreVersionError),
+    /// The runtime of the block uses an unknown state version.
+    UnknownStateVersion,
 }
 
 /// Start the processing of a block verification.
@@ -836,7 +1791,7 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
 
     /// Returns true if [`Config::full`] was `Some` at initialization.
     pub fn is_full_verification(&self) -> bool {
-        self.inner.finalized_runtime.is_some()
+        self.inner.full_mode
     }
 
     /// Returns the SCALE-encoded header of the block about to be verified.
@@ -872,7 +1827,7 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
             .collect::<Vec<_>>()
             .into_iter();
 
-        if self.inner.finalized_runtime.is_some() {
+        if self.inner.full_mode {
             BlockVerification::from(
                 Inner::Step1(
                     self.chain
@@ -883,6 +1838,7 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
                     block_body: block.scale_encoded_extrinsics,
                     block_user_data: Some(block.user_data),
                     source_id,
+                    now_from_unix_epoch,
                 },
             )
         } else {
@@ -910,12 +1866,16 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
                         is_new_best: false, ..
                     },
                 ) => Some(ResetCause::NonCanonical),
+                Err(blocks_tree::HeaderVerifyError::VerificationFailed(
+                    verify::header_only::Error::TooFarInFuture { slot, max_allowed },
+                )) => Some(ResetCause::TooFarInFuture { slot, max_allowed }),
                 Err(err) => Some(ResetCause::HeaderError(err)),
             };
 
             if let Some(reason) = error {
                 if let Some(src) = self.inner.sources.get_mut(&source_id) {
                     src.banned = true;
+                    src.last_ban_reason = Some(BanReason::from_reset_cause(&reason));
                 }
 
                 // If all sources are banned, unban them.
@@ -928,6 +1888,8 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
                 self.inner.make_requests_obsolete(&self.chain);
                 self.inner.best_to_finalized_storage_diff = Default::default();
                 self.inner.best_runtime = None;
+                self.inner.intermediate_runtimes.clear();
+                self.inner.best_runtime_outdated = self.inner.finalized_runtime_outdated;
                 self.inner.top_trie_root_calculation_cache = None;
 
                 let previous_best_height = self.chain.best_block_header().number;
@@ -940,6 +1902,8 @@ impl<TRq, TSrc, TBl> BlockVerify<TRq, TSrc, TBl> {
                     reason,
                 }
             } else {
+                self.inner.recent_verifications.record(now_from_unix_epoch);
+
                 let new_best_hash = self.chain.best_block_hash();
                 let new_best_number = self.chain.best_block_header().number;
 
@@ -1012,6 +1976,8 @@ struct BlockVerificationShared<TRq, TSrc, TBl> {
     block_user_data: Option<TBl>,
     /// Source the block has been downloaded from. Might be obsolete.
     source_id: SourceId,
+    /// Time passed to [`BlockVerify::start`].
+    now_from_unix_epoch: Duration,
 }
 
 impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
@@ -1052,35 +2018,19 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                 }) => {
                     // Successfully verified block!
 
-                    debug_assert_eq!(
-                        new_runtime.is_some(),
-                        storage_top_trie_changes.diff_get(&b":code"[..]).is_some()
-                            || storage_top_trie_changes
-                                .diff_get(&b":heappages"[..])
-                                .is_some()
+                    shared.inner.store_verified_block_runtime(
+                        &storage_top_trie_changes,
+                        insert.header().number - 1,
+                        parent_runtime,
+                        new_runtime,
                     );
 
-                    // Before the verification, we extracted the runtime either from
-                    // `finalized_runtime` or `best_runtime`.
-                    if shared.inner.finalized_runtime.is_some() {
-                        // If `finalized_runtime` is still `Some` now, that means we have
-                        // extracted from `best_runtime`.
-                        shared.inner.best_runtime = if let Some(new_runtime) = new_runtime {
-                            Some(new_runtime)
-                        } else {
-                            Some(parent_runtime)
-                        };
-                    } else {
-                        shared.inner.finalized_runtime = Some(parent_runtime);
-
-                        debug_assert!(shared.inner.best_runtime.is_none());
-                        if let Some(new_runtime) = new_runtime {
-                            shared.inner.best_runtime = Some(new_runtime);
-                        }
-                    }
-
                     shared.inner.top_trie_root_calculation_cache =
                         Some(top_trie_root_calculation_cache);
+                    shared
+                        .inner
+                        .recent_verifications
+                        .record(shared.now_from_unix_epoch);
                     shared
                         .inner
                         .best_to_finalized_storage_diff
@@ -1179,6 +2129,7 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                 Inner::Step1(blocks_tree::BodyVerifyStep1::InvalidHeader(old_chain, error)) => {
                     if let Some(source) = shared.inner.sources.get_mut(&shared.source_id) {
                         source.banned = true;
+                        source.last_ban_reason = Some(BanReason::InvalidHeader);
                     }
 
                     // If all sources are banned, unban them.
@@ -1195,6 +2146,8 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                     let mut inner = shared.inner.with_requests_obsoleted(&chain);
                     inner.best_to_finalized_storage_diff = Default::default();
                     inner.best_runtime = None;
+                    inner.intermediate_runtimes.clear();
+                    inner.best_runtime_outdated = inner.finalized_runtime_outdated;
                     inner.top_trie_root_calculation_cache = None;
 
                     break BlockVerification::Reset {
@@ -1211,6 +2164,7 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                 ) => {
                     if let Some(source) = shared.inner.sources.get_mut(&shared.source_id) {
                         source.banned = true;
+                        source.last_ban_reason = Some(BanReason::NonCanonical);
                     }
                     // If all sources are banned, unban them.
                     if shared.inner.sources.iter().all(|(_, s)| s.banned) {
@@ -1226,6 +2180,8 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                     let mut inner = shared.inner.with_requests_obsoleted(&chain);
                     inner.best_to_finalized_storage_diff = Default::default();
                     inner.best_runtime = None;
+                    inner.intermediate_runtimes.clear();
+                    inner.best_runtime_outdated = inner.finalized_runtime_outdated;
                     inner.top_trie_root_calculation_cache = None;
 
                     break BlockVerification::Reset {
@@ -1242,8 +2198,15 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                     if shared.inner.finalized_runtime.is_none() {
                         shared.inner.finalized_runtime = Some(parent_runtime);
                     }
+                    let reason = match error {
+                        blocks_tree::BodyVerifyError::Consensus(
+                            verify::header_body::Error::TooFarInFuture { slot, max_allowed },
+                        ) => ResetCause::TooFarInFuture { slot, max_allowed },
+                        error => ResetCause::HeaderBodyError(error),
+                    };
                     if let Some(source) = shared.inner.sources.get_mut(&shared.source_id) {
                         source.banned = true;
+                        source.last_ban_reason = Some(BanReason::from_reset_cause(&reason));
                     }
                     // If all sources are banned, unban them.
                     if shared.inner.sources.iter().all(|(_, s)| s.banned) {
@@ -1259,12 +2222,14 @@ impl<TRq, TSrc, TBl> BlockVerification<TRq, TSrc, TBl> {
                     let mut inner = shared.inner.with_requests_obsoleted(&chain);
                     inner.best_to_finalized_storage_diff = Default::default();
                     inner.best_runtime = None;
+                    inner.intermediate_runtimes.clear();
+                    inner.best_runtime_outdated = inner.finalized_runtime_outdated;
                     inner.top_trie_root_calculation_cache = None;
 
                     break BlockVerification::Reset {
                         previous_best_height: old_chain.best_block_header().number,
                         sync: OptimisticSync { chain, inner },
-                        reason: ResetCause::HeaderBodyError(error),
+                        reason,
                     };
                 }
             }
@@ -1297,6 +2262,7 @@ impl<TRq, TSrc, TBl> JustificationVerify<TRq, TSrc, TBl> {
             Err(error) => {
                 if let Some(source) = self.inner.sources.get_mut(&source_id) {
                     source.banned = true;
+                    source.last_ban_reason = Some(BanReason::JustificationError);
                 }
 
                 // If all sources are banned, unban them.
@@ -1313,6 +2279,8 @@ impl<TRq, TSrc, TBl> JustificationVerify<TRq, TSrc, TBl> {
                 let mut inner = self.inner.with_requests_obsoleted(&chain);
                 inner.best_to_finalized_storage_diff = Default::default();
                 inner.best_runtime = None;
+                inner.intermediate_runtimes.clear();
+                inner.best_runtime_outdated = inner.finalized_runtime_outdated;
                 inner.top_trie_root_calculation_cache = None;
 
                 let previous_best_height = chain.best_block_header().number;
@@ -1326,7 +2294,10 @@ impl<TRq, TSrc, TBl> JustificationVerify<TRq, TSrc, TBl> {
             }
         };
 
-        assert!(apply.is_current_best_block()); // TODO: can legitimately fail in case of malicious node
+        // The chain is linear, so a justification that doesn't target the best block
+        // necessarily targets one of its ancestors. The blocks above the target remain in the
+        // chain.
+        let finalizes_best_block = apply.is_current_best_block();
 
         // As part of the finalization, put the justification in the chain that's
         // going to be reported to the user.
@@ -1338,24 +2309,132 @@ impl<TRq, TSrc, TBl> JustificationVerify<TRq, TSrc, TBl> {
         // Applying the finalization and iterating over the now-finalized block.
         // Since `apply()` returns the blocks in decreasing block number, we have
         // to revert the list in order to get them in increasing block number
-        // instead.
-        // While this intermediary buffering is an overhead, the increased code
-        // complexity to avoid it is probably not worth the speed gain.
-        let finalized_blocks = apply
-            .apply()
-            .collect::<Vec<_>>()
-            .into_iter()
-            .rev()
-            .collect();
+        // instead. This is done in place in order to not hold two lists of blocks
+        // at the same time, as a single justification can finalize a large number
+        // of blocks.
+        let mut finalized_blocks: Vec<Block<TBl>> = apply.apply().collect();
+        finalized_blocks.reverse();
+
+        self.inner.finalized_block_justifications =
+            finalized_blocks.last().unwrap().justifications.clone();
+
+        let new_finalized_height = finalized_blocks.last().unwrap().header.number;
+
+        let finalized_modifies_runtime = finalized_blocks.iter().any(|b| {
+            b.header.digest.has_runtime_environment_updated()
+                || b.full.as_ref().map_or(false, |full| {
+                    self.inner
+                        .modifies_runtime_environment(&full.storage_top_trie_changes)
+                })
+        });
 
-        // Since the best block is now the finalized block, reset the storage
-        // diff.
-        debug_assert!(self.chain.is_empty());
-        self.inner.best_to_finalized_storage_diff.clear();
+        // In full mode, whether the finalized blocks have modified the runtime code, and whether
+        // they have modified one of the extra runtime environment keys.
+        let (finalized_modifies_code, finalized_modifies_extra_keys) = finalized_blocks
+            .iter()
+            .filter_map(|b| b.full.as_ref())
+            .fold((false, false), |(code, extra_keys), full| {
+                (
+                    code || self
+                        .inner
+                        .modifies_runtime_code(&full.storage_top_trie_changes),
+                    extra_keys
+                        || self.inner.modifies_extra_runtime_environment_keys(
+                            &full.storage_top_trie_changes,
+                        ),
+                )
+            });
 
-        if let Some(runtime) = self.inner.best_runtime.take() {
-            self.inner.finalized_runtime = Some(runtime);
-        }
+        let updates_finalized_runtime = if finalizes_best_block {
+            // Since the best block is now the finalized block, reset the storage
+            // diff.
+            debug_assert!(self.chain.is_empty());
+            self.inner.best_to_finalized_storage_diff.clear();
+            self.inner.intermediate_runtimes.clear();
+
+            // In full mode, `best_runtime` is `Some` only if one of the blocks between the
+            // finalized block and the best block has modified the runtime, or if it has been
+            // provided through `set_best_runtime`.
+            let runtime_replaced = if let Some(runtime) = self.inner.best_runtime.take() {
+                self.inner.finalized_runtime = Some(runtime);
+                true
+            } else {
+                false
+            };
+
+            // The runtime of the best block is now the runtime of the finalized block, and is
+            // thus outdated if and only if the runtime of the best block is.
+            self.inner.finalized_runtime_outdated = self.inner.best_runtime_outdated;
+
+            runtime_replaced || finalized_modifies_runtime
+        } else {
+            // The storage diff is rebuilt from the blocks that remain between the new
+            // finalized block and the best block.
+            let mut remaining_diff = storage_diff::StorageDiff::empty();
+            let mut remaining_modifies_extra_keys = false;
+            for header in self.chain.iter_ancestry_order() {
+                let block = self
+                    .chain
+                    .non_finalized_block_user_data(&header.hash())
+                    .unwrap();
+                if let Some(full) = &block.full {
+                    remaining_modifies_extra_keys |= self
+                        .inner
+                        .modifies_extra_runtime_environment_keys(&full.storage_top_trie_changes);
+                    remaining_diff.merge(&full.storage_top_trie_changes);
+                }
+            }
+            self.inner.best_to_finalized_storage_diff = remaining_diff;
+
+            if self.inner.full_mode {
+                // If the finalized blocks have modified the runtime code, the runtime of the new
+                // finalized block is the one that was used in order to verify its child. This is
+                // either one of the intermediate runtimes, or the runtime of the best block.
+                let runtime_replaced = if finalized_modifies_code {
+                    match self
+                        .inner
+                        .intermediate_runtimes
+                        .iter()
+                        .position(|(height, _)| *height >= new_finalized_height)
+                    {
+                        Some(position) => {
+                            let (_, runtime) = self
+                                .inner
+                                .intermediate_runtimes
+                                .drain(..=position)
+                                .next_back()
+                                .unwrap();
+                            self.inner.finalized_runtime = Some(runtime);
+                        }
+                        None => {
+                            // The runtime code has been modified since the previously-finalized
+                            // block, thus `best_runtime` is necessarily `Some`.
+                            self.inner.intermediate_runtimes.clear();
+                            self.inner.finalized_runtime =
+                                Some(self.inner.best_runtime.take().unwrap());
+                        }
+                    }
+                    true
+                } else {
+                    self.inner
+                        .intermediate_runtimes
+                        .retain(|(height, _)| *height > new_finalized_height);
+                    false
+                };
+
+                // The runtime of the new finalized block doesn't reflect the modifications of the
+                // extra runtime environment keys, unless it has been provided through
+                // `set_best_runtime` after these modifications. This isn't tracked, and the
+                // runtime is conservatively considered as outdated.
+                if finalized_modifies_extra_keys
+                    || (runtime_replaced && remaining_modifies_extra_keys)
+                {
+                    self.inner.finalized_runtime_outdated = true;
+                }
+            }
+
+            finalized_modifies_runtime
+        };
 
         self.inner.finalized_chain_information.chain_information =
             self.chain.as_chain_information().into();
@@ -1365,7 +2444,10 @@ impl<TRq, TSrc, TBl> JustificationVerify<TRq, TSrc, TBl> {
                 chain: self.chain,
                 inner: self.inner,
             },
-            JustificationVerification::Finalized { finalized_blocks },
+            JustificationVerification::Finalized {
+                finalized_blocks,
+                updates_finalized_runtime,
+            },
         )
     }
 }
@@ -1382,12 +2464,30 @@ pub enum JustificationVerification<TBl> {
         error: blocks_tree::JustificationVerifyError,
     },
 
-    /// Processing of the justification is over. The best block has now been finalized.
+    /// Processing of the justification is over. The target of the justification has now been
+    /// finalized. This is either the best block or, if the justification targets an ancestor
+    /// of the best block, one of its ancestors, in which case the blocks above the target
+    /// remain in the chain.
     ///
     /// There might be more blocks remaining. Call [`OptimisticSync::process_one`] again.
     Finalized {
-        /// Blocks that have been finalized.
+        /// Blocks that have been finalized, in increasing block number.
+        ///
+        /// The blocks are moved out of the state machine rather than copied. Yielding them
+        /// lazily isn't possible, as that would require keeping the chain borrowed while the
+        /// [`OptimisticSync`] is given back.
         finalized_blocks: Vec<Block<TBl>>,
+
+        /// If `true`, at least one of the finalized blocks has modified the runtime, either
+        /// according to its header or, if [`Config::full`] was `Some`, according to its
+        /// modifications to the storage, including the keys in
+        /// [`ConfigFull::extra_runtime_environment_keys`]. The runtime of the finalized block is
+        /// thus different from the runtime of the previously-finalized block.
+        ///
+        /// The state machine keeps track of the compiled runtime of the finalized block, and
+        /// calling [`OptimisticSync::set_finalized_runtime`] is only necessary if one of the
+        /// [`ConfigFull::extra_runtime_environment_keys`] has been modified.
+        updates_finalized_runtime: bool,
     },
 }
 
@@ -1515,8 +2615,22 @@ impl<TRq, TSrc, TBl> StorageNextKey<TRq, TSrc, TBl> {
     }
 }
 
+/// Counters of the requests of a source. See [`OptimisticSync::source_stats`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SourceStats {
+    /// Number of requests passed to [`OptimisticSync::finish_request_success`] that weren't
+    /// obsolete.
+    pub num_requests_succeeded: u64,
+    /// Number of requests passed to [`OptimisticSync::finish_request_failed`] that weren't
+    /// obsolete.
+    pub num_requests_failed: u64,
+    /// Number of requests that were obsolete when they have been finished, no matter whether
+    /// they succeeded or failed. See [`OptimisticSync::obsolete_requests`].
+    pub num_requests_obsolete: u64,
+}
+
 /// Request that should be emitted towards a certain source.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RequestDetail {
     /// Source where to request blocks from.
     pub source_id: SourceId,
@@ -1578,20 +2692,83 @@ pub enum ResetCause {
     HeaderBodyError(blocks_tree::BodyVerifyError),
     /// Received block isn't a child of the current best block.
     NonCanonical,
+    /// The slot of the received block is further in the future than allowed by
+    /// [`Config::aura_max_future_drift`].
+    #[display(
+        fmt = "Slot number {} is too far in the future. Maximum allowed: {}",
+        slot,
+        max_allowed
+    )]
+    TooFarInFuture {
+        /// Slot number of the block.
+        slot: u64,
+        /// Highest slot number that is currently accepted.
+        max_allowed: u64,
+    },
+}
+
+/// Reason why a source has been banned. See [`OptimisticSync::source_ban_reason`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum BanReason {
+    /// A request has failed. See [`OptimisticSync::finish_request_failed`].
+    RequestFailed,
+    /// The source has provided a header that couldn't be decoded.
+    InvalidHeader,
+    /// The source has provided a header that has failed to verify.
+    HeaderError,
+    /// The source has provided a block whose header and body have failed to verify.
+    HeaderBodyError,
+    /// The source has provided a block that isn't a child of the current best block.
+    NonCanonical,
+    /// The source has provided a block whose slot is too far in the future.
+    TooFarInFuture,
+    /// The source has provided a justification that has failed to verify.
+    JustificationError,
+}
+
+impl BanReason {
+    fn from_reset_cause(cause: &ResetCause) -> Self {
+        match cause {
+            ResetCause::InvalidHeader(_) => BanReason::InvalidHeader,
+            ResetCause::HeaderError(_) => BanReason::HeaderError,
+            ResetCause::HeaderBodyError(_) => BanReason::HeaderBodyError,
+            ResetCause::NonCanonical => BanReason::NonCanonical,
+            ResetCause::TooFarInFuture { .. } => BanReason::TooFarInFuture,
+        }
+    }
+}
+
+/// Outcome of [`OptimisticSync::add_source_dedup`].
+#[derive(Debug)]
+pub enum AddSourceDedup<TSrc> {
+    /// No existing source matched, and a new source has been added.
+    New(SourceId),
+    /// An existing source refers to the same underlying source. No new source has been added.
+    Existing {
+        /// Identifier of the existing source.
+        source_id: SourceId,
+        /// User data that has been passed to [`OptimisticSync::add_source_dedup`], given back.
+        user_data: TSrc,
+    },
 }
 
 /// Output of [`OptimisticSync::disassemble`].
 #[derive(Debug)]
-pub struct Disassemble<TRq, TSrc> {
+pub struct Disassemble<TRq, TSrc, TBl> {
     /// Information about the latest finalized block and its ancestors.
     pub chain_information: chain_information::ValidChainInformation,
 
+    /// List of the blocks that have been verified but not finalized yet.
+    ///
+    /// The blocks are guaranteed to be in an order in which the parents are found before their
+    /// children.
+    pub non_finalized_blocks: Vec<Block<TBl>>,
+
     /// List of sources that were within the state machine.
     pub sources: Vec<DisassembleSource<TSrc>>,
 
     /// List of the requests that were active.
     pub requests: Vec<(RequestId, TRq)>,
-    // TODO: add non-finalized blocks?
 }
 
 /// See [`Disassemble::sources`].
@@ -1605,4 +2782,593 @@ pub struct DisassembleSource<TSrc> {
 
     /// Best block that the source has reported having.
     pub best_block_number: u64,
+
+    /// Value passed to [`OptimisticSync::add_source`].
+    pub header_only: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        AddSourceDedup, BanReason, Config, ConfigFull, OptimisticSync, ProcessOne,
+        RequestSuccessBlock, StorageProofError,
+    };
+    use crate::{
+        chain::chain_information,
+        executor::{self, host, vm},
+        header,
+        sync::test_utils::{
+            aura_authority, aura_consensus, aura_header, genesis_header, grandpa_authority,
+            process_until_idle, test_runtime, Event,
+        },
+        trie,
+    };
+    use core::{
+        iter,
+        num::{NonZeroU32, NonZeroU64},
+        time::Duration,
+    };
+
+    /// Builds a [`Config`] whose finalized block is the given genesis block, with
+    /// [`aura_authority`] as the only Aura authority and [`grandpa_authority`] as the only
+    /// GrandPa authority. Blocks are only verified in full if [`Config::full`] is later modified.
+    fn aura_test_config(genesis: header::Header) -> Config {
+        Config {
+            chain_information: chain_information::ValidChainInformation::try_from(
+                chain_information::ChainInformation {
+                    finalized_block_header: genesis,
+                    consensus: aura_consensus(),
+                    finality: chain_information::ChainInformationFinality::Grandpa {
+                        after_finalized_block_authorities_set_id: 0,
+                        finalized_triggered_authorities: vec![header::GrandpaAuthority {
+                            public_key: ed25519_zebra::VerificationKey::from(&grandpa_authority())
+                                .into(),
+                            weight: NonZeroU64::new(1).unwrap(),
+                        }],
+                        finalized_scheduled_change: None,
+                    },
+                },
+            )
+            .unwrap(),
+            block_number_bytes: 4,
+            sources_capacity: 16,
+            blocks_capacity: 16,
+            verification_queue_capacity: 16,
+            download_ahead_blocks: NonZeroU32::new(16).unwrap(),
+            randomness_seed: [0; 32],
+            max_blocks_ahead_of_finalized: None,
+            request_failures_before_ban: NonZeroU32::new(1).unwrap(),
+            aura_max_future_drift: Duration::from_secs(30),
+            full: None,
+        }
+    }
+
+    /// Builds a Grandpa justification, with a block number encoded on 4 bytes, signed by the
+    /// given authority of the authorities set 0.
+    fn grandpa_justification(
+        target: &header::Header,
+        authority: &ed25519_zebra::SigningKey,
+    ) -> Vec<u8> {
+        let round = 1u64;
+        let target_hash = target.hash();
+        let target_number = u32::try_from(target.number).unwrap().to_le_bytes();
+
+        let mut message = Vec::new();
+        message.push(1u8);
+        message.extend_from_slice(&target_hash);
+        message.extend_from_slice(&target_number);
+        message.extend_from_slice(&round.to_le_bytes());
+        message.extend_from_slice(&0u64.to_le_bytes());
+        let signature: [u8; 64] = authority.sign(&message).into();
+        let public_key: [u8; 32] = ed25519_zebra::VerificationKey::from(authority).into();
+
+        let mut justification = Vec::new();
+        justification.extend_from_slice(&round.to_le_bytes());
+        justification.extend_from_slice(&target_hash);
+        justification.extend_from_slice(&target_number);
+        justification.push(1 << 2); // One precommit.
+        justification.extend_from_slice(&target_hash);
+        justification.extend_from_slice(&target_number);
+        justification.extend_from_slice(&signature);
+        justification.extend_from_slice(&public_key);
+        justification.push(0); // No votes ancestry.
+        justification
+    }
+
+    #[test]
+    fn justification_finalizes_ancestor_of_best() {
+        let aura_authority = aura_authority();
+        let genesis = genesis_header();
+        let block1 = aura_header(&genesis, 1, [0; 32], &aura_authority);
+        let block2 = aura_header(&block1, 2, [0; 32], &aura_authority);
+
+        let mut sync = OptimisticSync::<(), (), ()>::new(aura_test_config(genesis));
+
+        let source_id = sync.add_source((), 2, true);
+        let request = sync.desired_requests().next().unwrap();
+        assert_eq!(request.source_id, source_id);
+        assert_eq!(request.block_height.get(), 1);
+        assert_eq!(request.num_blocks.get(), 2);
+        let request_id = sync.insert_request(request, ());
+
+        // Block 2 carries a justification that targets block 1.
+        let justification = grandpa_justification(&block1, &grandpa_authority());
+        let _ = sync.finish_request_success(
+            request_id,
+            [
+                RequestSuccessBlock {
+                    scale_encoded_header: block1.scale_encoding_vec(),
+                    scale_encoded_justifications: Vec::new(),
+                    scale_encoded_extrinsics: Vec::new(),
+                    user_data: (),
+                },
+                RequestSuccessBlock {
+                    scale_encoded_header: block2.scale_encoding_vec(),
+                    scale_encoded_justifications: vec![(*b"FRNK", justification)],
+                    scale_encoded_extrinsics: Vec::new(),
+                    user_data: (),
+                },
+            ]
+            .into_iter(),
+        );
+
+        let (sync, events) = process_until_idle(sync, Duration::from_secs(60), |_| None);
+        assert_eq!(
+            events,
+            vec![
+                Event::NewBest(1, block1.hash()),
+                Event::NewBest(2, block2.hash()),
+                Event::Finalized(vec![block1.hash()]),
+            ]
+        );
+
+        assert_eq!(sync.finalized_block_header().hash(), block1.hash());
+        assert_eq!(sync.best_block_hash(), block2.hash());
+        assert!(matches!(sync.process_one(), ProcessOne::Idle { .. }));
+    }
+
+    #[test]
+    fn finalized_runtime_provided_after_construction() {
+        let aura_authority = aura_authority();
+        let genesis = genesis_header();
+        let block1 = aura_header(&genesis, 1, [0; 32], &aura_authority);
+
+        let mut sync = OptimisticSync::<(), (), ()>::new(Config {
+            full: Some(ConfigFull {
+                finalized_runtime: None,
+                extra_runtime_environment_keys: Vec::new(),
+            }),
+            ..aura_test_config(genesis)
+        });
+        assert!(sync.best_block_storage().is_none());
+
+        let source_id = sync.add_source((), 1, false);
+        let request = sync.desired_requests().next().unwrap();
+        assert_eq!(request.source_id, source_id);
+        let request_id = sync.insert_request(request, ());
+        let _ = sync.finish_request_success(
+            request_id,
+            iter::once(RequestSuccessBlock {
+                scale_encoded_header: block1.scale_encoding_vec(),
+                scale_encoded_justifications: Vec::new(),
+                scale_encoded_extrinsics: Vec::new(),
+                user_data: (),
+            }),
+        );
+
+        // The block is ready to be verified, but can't be without the runtime.
+        let mut sync = match sync.process_one() {
+            ProcessOne::FinalizedRuntimeMissing { sync } => sync,
+            _ => panic!(),
+        };
+
+        sync.set_finalized_runtime(
+            host::HostVmPrototype::new(host::Config {
+                module: &include_bytes!("../executor/vm/test-polkadot-runtime-v9160.wasm")[..],
+                heap_pages: executor::DEFAULT_HEAP_PAGES,
+                exec_hint: vm::ExecHint::Oneshot,
+                allow_unresolved_imports: true,
+            })
+            .unwrap(),
+        );
+        assert!(sync.best_block_storage().is_some());
+
+        // The verification can now resume.
+        match sync.process_one() {
+            ProcessOne::VerifyBlock(verify) => {
+                assert!(verify.is_full_verification());
+                assert_eq!(verify.hash(), block1.hash());
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn header_only_sources_not_used_in_full_mode() {
+        let mut sync = OptimisticSync::<(), (), ()>::new(Config {
+            full: Some(ConfigFull {
+                finalized_runtime: None,
+                extra_runtime_environment_keys: Vec::new(),
+            }),
+            ..aura_test_config(genesis_header())
+        });
+
+        sync.add_source((), 100, true);
+        assert_eq!(sync.desired_requests().count(), 0);
+
+        let full_source = sync.add_source((), 100, false);
+        assert_ne!(sync.desired_requests().count(), 0);
+        assert!(sync
+            .desired_requests()
+            .all(|rq| rq.source_id == full_source));
+
+        let _ = sync.remove_source(full_source);
+        assert_eq!(sync.desired_requests().count(), 0);
+    }
+
+    #[test]
+    fn extra_runtime_environment_key_modified() {
+        let aura_authority = aura_authority();
+        let genesis = genesis_header();
+        let block1 = aura_header(&genesis, 1, [0; 32], &aura_authority);
+        let block2 = aura_header(&block1, 2, [0; 32], &aura_authority);
+
+        // Executing a block with the test runtime modifies the extra key.
+        let mut sync = OptimisticSync::<(), (), ()>::new(Config {
+            full: Some(ConfigFull {
+                finalized_runtime: Some(test_runtime()),
+                extra_runtime_environment_keys: vec![b":extra".to_vec()],
+            }),
+            ..aura_test_config(genesis)
+        });
+        assert!(sync.best_block_storage().is_some());
+
+        let source_id = sync.add_source((), 2, false);
+        let request = sync.desired_requests().next().unwrap();
+        assert_eq!(request.source_id, source_id);
+        let request_id = sync.insert_request(request, ());
+        let _ = sync.finish_request_success(
+            request_id,
+            [&block1, &block2]
+                .into_iter()
+                .map(|block| RequestSuccessBlock {
+                    scale_encoded_header: block.scale_encoding_vec(),
+                    scale_encoded_justifications: Vec::new(),
+                    scale_encoded_extrinsics: Vec::new(),
+                    user_data: (),
+                }),
+        );
+
+        let now = Duration::from_secs(60);
+        let (sync, events) = process_until_idle(sync, now, |_| None);
+        assert_eq!(events, vec![Event::NewBest(1, block1.hash())]);
+        assert!(sync.best_block_storage().is_none());
+
+        // The runtime of the best block must now be provided before verifying further blocks.
+        let mut sync = match sync.process_one() {
+            ProcessOne::BestRuntimeOutdated { sync } => sync,
+            _ => panic!(),
+        };
+        sync.set_best_runtime(test_runtime());
+        assert!(sync
+            .best_block_storage()
+            .unwrap()
+            .get(b":extra", || None)
+            .is_some());
+
+        match sync.process_one() {
+            ProcessOne::VerifyBlock(verify) => assert_eq!(verify.hash(), block2.hash()),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn consecutive_request_failures_before_ban() {
+        let mut sync = OptimisticSync::<(), (), ()>::new(Config {
+            request_failures_before_ban: NonZeroU32::new(3).unwrap(),
+            ..aura_test_config(genesis_header())
+        });
+        let source_id = sync.add_source((), 100, false);
+        // Second source, in order to prevent all the sources from being unbanned.
+        let _ = sync.add_source((), 100, false);
+
+        let start_request = |sync: &mut OptimisticSync<(), (), ()>| {
+            let request = sync
+                .desired_requests()
+                .find(|rq| rq.source_id == source_id)
+                .unwrap();
+            sync.insert_request(request, ())
+        };
+        let is_banned =
+            |sync: &OptimisticSync<(), (), ()>| sync.inner.sources.get(&source_id).unwrap().banned;
+
+        // Two failures, then a success that resets the counter.
+        for _ in 0..2 {
+            let request_id = start_request(&mut sync);
+            sync.finish_request_failed(request_id);
+            assert!(!is_banned(&sync));
+        }
+        let request_id = start_request(&mut sync);
+        let _ = sync.finish_request_success(request_id, iter::empty());
+        assert!(!is_banned(&sync));
+
+        // The source is banned exactly at the third consecutive failure.
+        for _ in 0..2 {
+            let request_id = start_request(&mut sync);
+            sync.finish_request_failed(request_id);
+            assert!(!is_banned(&sync));
+        }
+        let request_id = start_request(&mut sync);
+        sync.finish_request_failed(request_id);
+        assert!(is_banned(&sync));
+        assert!(matches!(
+            sync.source_ban_reason(source_id),
+            Some(BanReason::RequestFailed)
+        ));
+    }
+
+    #[test]
+    fn disassembled_sources_keep_their_id() {
+        let genesis = genesis_header();
+
+        let mut sync = OptimisticSync::<(), u32, ()>::new(aura_test_config(genesis.clone()));
+        let source1 = sync.add_source(1, 10, false);
+        let source2 = sync.add_source(2, 20, true);
+        let source3 = sync.add_source(3, 30, false);
+        let _ = sync.remove_source(source2);
+
+        let disassembled = sync.disassemble();
+
+        let mut sync = OptimisticSync::<(), u32, ()>::new(aura_test_config(genesis));
+        sync.add_disassembled_sources(disassembled.sources);
+
+        let mut sources = sync.sources().collect::<Vec<_>>();
+        sources.sort_unstable();
+        assert_eq!(sources, vec![source1, source3]);
+        assert_eq!(sync[source1], 1);
+        assert_eq!(sync.source_best_block(source1), 10);
+        assert_eq!(sync[source3], 3);
+        assert_eq!(sync.source_best_block(source3), 30);
+
+        // New sources don't collide with the re-inserted ones.
+        let source4 = sync.add_source(4, 40, false);
+        assert!(source4 != source1 && source4 != source3);
+        assert_eq!(sync[source3], 3);
+    }
+
+    #[test]
+    fn verification_queue_capacity() {
+        let mut sync = OptimisticSync::<(), (), ()>::new(Config {
+            verification_queue_capacity: 64,
+            blocks_capacity: 0,
+            ..aura_test_config(genesis_header())
+        });
+        assert!(sync.inner.verification_queue.capacity() >= 64);
+
+        // The capacity is preserved when the queue is rebuilt.
+        sync.inner.make_requests_obsolete(&sync.chain);
+        assert!(sync.inner.verification_queue.capacity() >= 64);
+    }
+
+    #[test]
+    fn add_source_dedup() {
+        let mut sync = OptimisticSync::<(), u32, ()>::new(aura_test_config(genesis_header()));
+
+        let source_id =
+            match sync.add_source_dedup(1, 10, true, Duration::from_secs(0), |a, b| a == b) {
+                AddSourceDedup::New(id) => id,
+                _ => panic!(),
+            };
+        assert!(sync
+            .stale_sources(Duration::MAX, Duration::ZERO)
+            .next()
+            .is_none());
+
+        // The same source, with a lower best block, doesn't lower the best block, but its best
+        // block is now considered as raised.
+        match sync.add_source_dedup(1, 5, false, Duration::from_secs(10), |a, b| a == b) {
+            AddSourceDedup::Existing {
+                source_id: id,
+                user_data: 1,
+            } => assert_eq!(id, source_id),
+            _ => panic!(),
+        }
+        assert_eq!(sync.sources().len(), 1);
+        assert_eq!(sync.source_best_block(source_id), 10);
+        assert!(!sync.inner.sources.get(&source_id).unwrap().header_only);
+        assert_eq!(
+            sync.stale_sources(Duration::from_secs(20), Duration::from_secs(10))
+                .collect::<Vec<_>>(),
+            vec![source_id]
+        );
+
+        // A higher best block raises the best block of the existing source.
+        match sync.add_source_dedup(1, 20, true, Duration::from_secs(20), |a, b| a == b) {
+            AddSourceDedup::Existing { .. } => {}
+            _ => panic!(),
+        }
+        assert_eq!(sync.source_best_block(source_id), 20);
+        assert!(sync
+            .stale_sources(Duration::from_secs(20), Duration::from_secs(10))
+            .next()
+            .is_none());
+
+        // A different source is added separately.
+        match sync.add_source_dedup(2, 10, false, Duration::from_secs(20), |a, b| a == b) {
+            AddSourceDedup::New(id) => assert_ne!(id, source_id),
+            _ => panic!(),
+        }
+        assert_eq!(sync.sources().len(), 2);
+    }
+
+    #[test]
+    fn finalized_runtime_kept_after_finalizing_ancestor() {
+        let aura_authority = aura_authority();
+        let genesis = genesis_header();
+        let block1 = aura_header(&genesis, 1, [0; 32], &aura_authority);
+        let block2 = aura_header(&block1, 2, [0; 32], &aura_authority);
+        // Block with the same slot as its parent, which fails to verify.
+        let invalid_block3 = aura_header(&block2, 2, [0; 32], &aura_authority);
+
+        // Each block modifies the extra key, and thus the runtime environment.
+        let mut sync = OptimisticSync::<(), (), ()>::new(Config {
+            full: Some(ConfigFull {
+                finalized_runtime: Some(test_runtime()),
+                extra_runtime_environment_keys: vec![b":extra".to_vec()],
+            }),
+            ..aura_test_config(genesis)
+        });
+
+        let source_id = sync.add_source((), 2, false);
+        let request = sync.desired_requests().next().unwrap();
+        let request_id = sync.insert_request(request, ());
+        let justification = grandpa_justification(&block1, &grandpa_authority());
+        let _ = sync.finish_request_success(
+            request_id,
+            [
+                RequestSuccessBlock {
+                    scale_encoded_header: block1.scale_encoding_vec(),
+                    scale_encoded_justifications: Vec::new(),
+                    scale_encoded_extrinsics: Vec::new(),
+                    user_data: (),
+                },
+                RequestSuccessBlock {
+                    scale_encoded_header: block2.scale_encoding_vec(),
+                    scale_encoded_justifications: vec![(*b"FRNK", justification)],
+                    scale_encoded_extrinsics: Vec::new(),
+                    user_data: (),
+                },
+            ]
+            .into_iter(),
+        );
+
+        let now = Duration::from_secs(60);
+        let (sync, events) = process_until_idle(sync, now, |_| None);
+        assert_eq!(events, vec![Event::NewBest(1, block1.hash())]);
+        let mut sync = match sync.process_one() {
+            ProcessOne::BestRuntimeOutdated { sync } => sync,
+            _ => panic!(),
+        };
+        sync.set_best_runtime(test_runtime());
+
+        // Block 1 is finalized while block 2 remains above it, both having modified the runtime
+        // environment.
+        let (mut sync, events) = process_until_idle(sync, now, |_| None);
+        assert_eq!(
+            events,
+            vec![
+                Event::NewBest(2, block2.hash()),
+                Event::Finalized(vec![block1.hash()]),
+                Event::FinalizedRuntimeUpdated,
+            ]
+        );
+        assert_eq!(sync.finalized_block_header().hash(), block1.hash());
+        assert!(sync.finalized_block_runtime().is_some());
+
+        // Reset the chain to the finalized block by providing an invalid block.
+        sync.raise_source_best_block(source_id, 3, now);
+        let request = sync.desired_requests().next().unwrap();
+        let request_id = sync.insert_request(request, ());
+        let _ = sync.finish_request_success(
+            request_id,
+            iter::once(RequestSuccessBlock {
+                scale_encoded_header: invalid_block3.scale_encoding_vec(),
+                scale_encoded_justifications: Vec::new(),
+                scale_encoded_extrinsics: Vec::new(),
+                user_data: (),
+            }),
+        );
+        let mut sync = match sync.process_one() {
+            ProcessOne::BestRuntimeOutdated { sync } => sync,
+            _ => panic!(),
+        };
+        sync.set_best_runtime(test_runtime());
+        let (mut sync, events) = process_until_idle(sync, now, |_| None);
+        assert_eq!(events, vec![Event::Reset]);
+        assert_eq!(sync.best_block_hash(), block1.hash());
+
+        // The finalized runtime is still known, but is outdated, as block 1 has modified the
+        // runtime environment.
+        assert!(sync.finalized_block_runtime().is_some());
+        let request = sync
+            .desired_requests()
+            .find(|rq| rq.source_id == source_id)
+            .unwrap();
+        assert_eq!(request.block_height.get(), 2);
+        let request_id = sync.insert_request(request, ());
+        let _ = sync.finish_request_success(
+            request_id,
+            iter::once(RequestSuccessBlock {
+                scale_encoded_header: block2.scale_encoding_vec(),
+                scale_encoded_justifications: Vec::new(),
+                scale_encoded_extrinsics: Vec::new(),
+                user_data: (),
+            }),
+        );
+        let mut sync = match sync.process_one() {
+            ProcessOne::BestRuntimeOutdated { sync } => sync,
+            _ => panic!(),
+        };
+        sync.set_finalized_runtime(test_runtime());
+        let (_, events) = process_until_idle(sync, now, |_| None);
+        assert_eq!(events, vec![Event::NewBest(2, block2.hash())]);
+    }
+
+    #[test]
+    fn best_block_storage_proof() {
+        let runtime = host::HostVmPrototype::new(host::Config {
+            module: &include_bytes!("../executor/vm/test-polkadot-runtime-v9160.wasm")[..],
+            heap_pages: executor::DEFAULT_HEAP_PAGES,
+            exec_hint: vm::ExecHint::Oneshot,
+            allow_unresolved_imports: true,
+        })
+        .unwrap();
+
+        let sync = OptimisticSync::<(), (), ()>::new(Config {
+            full: Some(ConfigFull {
+                finalized_runtime: Some(runtime),
+                extra_runtime_environment_keys: Vec::new(),
+            }),
+            ..aura_test_config(genesis_header())
+        });
+
+        // The runtime doesn't specify a state version, and the value of `abc` is long enough to
+        // be encoded differently depending on the state version.
+        let finalized_storage = [
+            (&b"foo"[..], &b"bar"[..]),
+            (&b"fooz"[..], &b"baz"[..]),
+            (&b"abc"[..], &[5; 40][..]),
+        ];
+        let trie_root = trie::trie_root(trie::TrieEntryVersion::V0, &finalized_storage);
+
+        let proof = sync
+            .best_block_storage()
+            .unwrap()
+            .storage_proof(finalized_storage.iter().copied(), &[&b"abc"[..]])
+            .unwrap();
+        assert_eq!(
+            trie::proof_verify::verify_proof(trie::proof_verify::VerifyProofConfig {
+                requested_key: b"abc",
+                trie_root_hash: &trie_root,
+                proof: proof.iter().map(|node| &node[..]),
+            })
+            .unwrap(),
+            Some(&[5; 40][..])
+        );
+
+        // The test runtime doesn't provide its runtime version.
+        let sync = OptimisticSync::<(), (), ()>::new(Config {
+            full: Some(ConfigFull {
+                finalized_runtime: Some(test_runtime()),
+                extra_runtime_environment_keys: Vec::new(),
+            }),
+            ..aura_test_config(genesis_header())
+        });
+        assert!(matches!(
+            sync.best_block_storage()
+                .unwrap()
+                .storage_proof(finalized_storage.iter().copied(), &[&b"abc"[..]]),
+            Err(StorageProofError::CoreVersion(_))
+        ));
+    }
 }
@@ -39,8 +39,11 @@ pub(super) struct VerificationQueue<TRq, TBl> {
 
 impl<TRq, TBl> VerificationQueue<TRq, TBl> {
     /// Creates a new queue.
-    pub fn new(base_block_number: u64) -> Self {
-        let mut verification_queue = VecDeque::new();
+    ///
+    /// `capacity` is the number of entries to pre-allocate. An entry corresponds either to a
+    /// range of blocks that are missing or being requested, or to a downloaded block.
+    pub fn new(base_block_number: u64, capacity: usize) -> Self {
+        let mut verification_queue = VecDeque::with_capacity(capacity);
         verification_queue.push_back(VerificationQueueEntry {
             block_height: NonZeroU64::new(base_block_number).unwrap(),
             ty: VerificationQueueEntryTy::Missing,
@@ -49,6 +52,12 @@ impl<TRq, TBl> VerificationQueue<TRq, TBl> {
         VerificationQueue { verification_queue }
     }
 
+    /// Returns the number of entries that the queue can hold without reallocating.
+    #[cfg(test)]
+    pub fn capacity(&self) -> usize {
+        self.verification_queue.capacity()
+    }
+
     /// Returns true if the queue starts with ready blocks.
     ///
     /// This is equivalent to calling `is_some` on the `Option` returned by
@@ -149,6 +158,34 @@ impl<TRq, TBl> VerificationQueue<TRq, TBl> {
         iter1.chain(iter2)
     }
 
+    /// Returns the number of blocks between the first block queued in this queue and
+    /// `download_ahead_blocks` blocks later that are neither requested nor queued.
+    ///
+    /// This is the total number of blocks covered by the requests returned by
+    /// [`VerificationQueue::desired_requests`], except that the last range is capped.
+    pub fn num_missing_blocks(&self, download_ahead_blocks: NonZeroU32) -> u32 {
+        // Highest block number to request.
+        let max_block_number = self.verification_queue.front().unwrap().block_height.get()
+            + u64::from(download_ahead_blocks.get());
+
+        let in_between = self
+            .verification_queue
+            .iter()
+            .tuple_windows::<(_, _)>()
+            .filter(|(e, _)| matches!(e.ty, VerificationQueueEntryTy::Missing))
+            .filter(|(entry, _)| entry.block_height.get() < max_block_number)
+            .map(|(entry, next_entry)| {
+                cmp::min(max_block_number, next_entry.block_height.get()) - entry.block_height.get()
+            })
+            .sum::<u64>();
+
+        let verif_queue_last = self.verification_queue.back().unwrap();
+        let at_the_end = max_block_number.saturating_sub(verif_queue_last.block_height.get());
+
+        // The result can't be above `download_ahead_blocks`.
+        u32::try_from(in_between + at_the_end).unwrap()
+    }
+
     /// Updates the queue with the fact that a request has been started.
     ///
     /// Returns `Ok` if the request has updated the queue, and `Err` if the request isn't relevant
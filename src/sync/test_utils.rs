@@ -0,0 +1,384 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Utilities shared between the tests of the syncing state machines.
+//!
+//! [`process_until_idle`] drives either an [`AllForksSync`](all_forks::AllForksSync) or an
+//! [`OptimisticSync`](optimistic::OptimisticSync) until there isn't anything left to process,
+//! and returns the list of [`Event`]s that have happened.
+
+use super::{all_forks, optimistic};
+use crate::{
+    chain::chain_information,
+    executor::{self, host, vm},
+    header,
+};
+
+use alloc::vec::Vec;
+use core::{
+    iter,
+    num::{NonZeroU32, NonZeroU64},
+    ops::Bound,
+    time::Duration,
+};
+
+/// Function giving access to the storage of the finalized block. See [`process_until_idle`].
+pub(super) type FinalizedStorage<'a> =
+    &'a mut dyn FnMut(Bound<&[u8]>) -> Option<(Vec<u8>, Vec<u8>)>;
+
+/// Event produced by [`process_until_idle`].
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Event {
+    /// A block has been verified and is the new best block.
+    NewBest(u64, [u8; 32]),
+    /// A block has failed to verify.
+    VerifyError(u64, [u8; 32]),
+    /// The chain has been reset to the finalized block after an error.
+    Reset,
+    /// A finality proof has finalized the given blocks.
+    Finalized(Vec<[u8; 32]>),
+    /// The runtime of the finalized block has been modified by the blocks of the latest
+    /// [`Event::Finalized`].
+    FinalizedRuntimeUpdated,
+}
+
+/// Syncing state machine that can be driven by [`process_until_idle`].
+pub(super) trait Process: Sized {
+    /// Processes the next item in the queue of the state machine and pushes the resulting
+    /// events to `events`.
+    ///
+    /// Returns `Err` if there wasn't anything to process.
+    fn process_next(
+        self,
+        now_from_unix_epoch: Duration,
+        finalized_storage: FinalizedStorage,
+        events: &mut Vec<Event>,
+    ) -> Result<Self, Self>;
+}
+
+/// Processes the content of `sync` until there isn't anything left to process, and returns the
+/// list of events that have happened.
+///
+/// `finalized_storage` is used in order to answer the accesses to the storage of the finalized
+/// block. It must return the first entry whose key is after the given bound, if any. For
+/// example, if the storage is a `BTreeMap`, it can be implemented by calling `range` and
+/// `next`.
+pub(super) fn process_until_idle<T: Process>(
+    mut sync: T,
+    now_from_unix_epoch: Duration,
+    mut finalized_storage: impl FnMut(Bound<&[u8]>) -> Option<(Vec<u8>, Vec<u8>)>,
+) -> (T, Vec<Event>) {
+    let mut events = Vec::new();
+
+    loop {
+        sync = match sync.process_next(now_from_unix_epoch, &mut finalized_storage, &mut events) {
+            Ok(sync) => sync,
+            Err(sync) => return (sync, events),
+        };
+    }
+}
+
+/// Returns the keypair of the Aura authority used by the tests.
+pub(super) fn aura_authority() -> schnorrkel::Keypair {
+    schnorrkel::MiniSecretKey::from_bytes(&[1; 32])
+        .unwrap()
+        .expand_to_keypair(schnorrkel::ExpansionMode::Ed25519)
+}
+
+/// Returns the key of the GrandPa authority used by the tests.
+pub(super) fn grandpa_authority() -> ed25519_zebra::SigningKey {
+    ed25519_zebra::SigningKey::from([2; 32])
+}
+
+/// Returns a genesis block header without any digest item.
+pub(super) fn genesis_header() -> header::Header {
+    header::Header {
+        parent_hash: [0; 32],
+        number: 0,
+        state_root: [0; 32],
+        extrinsics_root: [0; 32],
+        digest: header::DigestRef::empty().into(),
+    }
+}
+
+/// Returns an Aura consensus with [`aura_authority`] as the only authority.
+pub(super) fn aura_consensus() -> chain_information::ChainInformationConsensus {
+    chain_information::ChainInformationConsensus::Aura {
+        finalized_authorities_list: vec![header::AuraAuthority {
+            public_key: aura_authority().public.to_bytes(),
+        }],
+        slot_duration: NonZeroU64::new(6000).unwrap(),
+    }
+}
+
+/// Builds an [`all_forks::Config`] whose finalized block is [`genesis_header`], using the given
+/// consensus and GrandPa without any authority.
+///
+/// Tests can override individual fields with the struct update syntax.
+pub(super) fn all_forks_config(
+    consensus: chain_information::ChainInformationConsensus,
+) -> all_forks::Config<iter::Empty<[u8; 32]>> {
+    all_forks::Config {
+        chain_information: chain_information::ValidChainInformation::try_from(
+            chain_information::ChainInformation {
+                finalized_block_header: genesis_header(),
+                consensus,
+                finality: chain_information::ChainInformationFinality::Grandpa {
+                    after_finalized_block_authorities_set_id: 0,
+                    finalized_triggered_authorities: Vec::new(),
+                    finalized_scheduled_change: None,
+                },
+            },
+        )
+        .unwrap(),
+        block_number_bytes: 4,
+        allow_unknown_consensus_engines: true,
+        aura_max_future_drift: Duration::from_secs(30),
+        sources_capacity: 4,
+        blocks_capacity: 4,
+        max_disjoint_headers: 4,
+        max_requests_per_block: NonZeroU32::new(1).unwrap(),
+        max_fork_ancestry_searches: NonZeroU32::new(1).unwrap(),
+        max_consecutive_too_old_best_announces: None,
+        max_pending_grandpa_commits: 4,
+        max_header_size: 1024,
+        max_blocks_ahead_of_finalized: None,
+        max_queued_block_announces: 0,
+        source_saturation_threshold: None,
+        full: false,
+        banned_blocks: iter::empty(),
+        banned_blocks_hasher: all_forks::BannedBlocksHasher::Fnv,
+        randomness_seed: [0; 32],
+    }
+}
+
+/// Minimal runtime that accepts any block. Executing a block stores the parameter passed to
+/// `Core_execute_block`, in other words the header and body of the block, under the `:extra`
+/// storage key. It corresponds to the following module:
+///
+/// ```wat
+/// (module
+///   (import "env" "ext_storage_set_version_1" (func $set (param i64 i64)))
+///   (memory (export "memory") 2)
+///   (global (export "__heap_base") i32 (i32.const 1024))
+///   (data (i32.const 0) ":extra")
+///   (data (i32.const 8) "\01\00\00")
+///   (func (export "BlockBuilder_check_inherents") (param i32 i32) (result i64)
+///     (i64.const 0x3_0000_0008))
+///   (func (export "Core_execute_block") (param i32 i32) (result i64)
+///     (call $set
+///       (i64.const 0x6_0000_0000)
+///       (i64.or
+///         (i64.shl (i64.extend_i32_u (local.get 1)) (i64.const 32))
+///         (i64.extend_i32_u (local.get 0))))
+///     (i64.const 0)))
+/// ```
+pub(super) fn test_runtime() -> host::HostVmPrototype {
+    let module = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x0c, 0x02, 0x60, 0x02, 0x7e, 0x7e,
+        0x00, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7e, 0x02, 0x21, 0x01, 0x03, 0x65, 0x6e, 0x76, 0x19,
+        0x65, 0x78, 0x74, 0x5f, 0x73, 0x74, 0x6f, 0x72, 0x61, 0x67, 0x65, 0x5f, 0x73, 0x65, 0x74,
+        0x5f, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6f, 0x6e, 0x5f, 0x31, 0x00, 0x00, 0x03, 0x03, 0x02,
+        0x01, 0x01, 0x05, 0x03, 0x01, 0x00, 0x02, 0x06, 0x07, 0x01, 0x7f, 0x00, 0x41, 0x80, 0x08,
+        0x0b, 0x07, 0x4c, 0x04, 0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00, 0x0b, 0x5f,
+        0x5f, 0x68, 0x65, 0x61, 0x70, 0x5f, 0x62, 0x61, 0x73, 0x65, 0x03, 0x00, 0x1c, 0x42, 0x6c,
+        0x6f, 0x63, 0x6b, 0x42, 0x75, 0x69, 0x6c, 0x64, 0x65, 0x72, 0x5f, 0x63, 0x68, 0x65, 0x63,
+        0x6b, 0x5f, 0x69, 0x6e, 0x68, 0x65, 0x72, 0x65, 0x6e, 0x74, 0x73, 0x00, 0x01, 0x12, 0x43,
+        0x6f, 0x72, 0x65, 0x5f, 0x65, 0x78, 0x65, 0x63, 0x75, 0x74, 0x65, 0x5f, 0x62, 0x6c, 0x6f,
+        0x63, 0x6b, 0x00, 0x02, 0x0a, 0x22, 0x02, 0x08, 0x00, 0x42, 0x88, 0x80, 0x80, 0x80, 0x30,
+        0x0b, 0x17, 0x00, 0x42, 0x80, 0x80, 0x80, 0x80, 0xe0, 0x00, 0x20, 0x01, 0xad, 0x42, 0x20,
+        0x86, 0x20, 0x00, 0xad, 0x84, 0x10, 0x00, 0x42, 0x00, 0x0b, 0x0b, 0x14, 0x02, 0x00, 0x41,
+        0x00, 0x0b, 0x06, 0x3a, 0x65, 0x78, 0x74, 0x72, 0x61, 0x00, 0x41, 0x08, 0x0b, 0x03, 0x01,
+        0x00, 0x00,
+    ];
+
+    host::HostVmPrototype::new(host::Config {
+        module: &module[..],
+        heap_pages: executor::DEFAULT_HEAP_PAGES,
+        exec_hint: vm::ExecHint::Oneshot,
+        allow_unresolved_imports: false,
+    })
+    .unwrap()
+}
+
+/// Builds a header without any digest item. Such a header can't be verified, as it doesn't
+/// contain any consensus information.
+pub(super) fn test_header(
+    parent_hash: [u8; 32],
+    number: u64,
+    state_root: [u8; 32],
+) -> header::Header {
+    header::Header {
+        parent_hash,
+        number,
+        state_root,
+        extrinsics_root: [0; 32],
+        digest: header::DigestRef::empty().into(),
+    }
+}
+
+/// Builds a header signed by the given Aura authority.
+pub(super) fn aura_header(
+    parent: &header::Header,
+    slot_number: u64,
+    state_root: [u8; 32],
+    authority: &schnorrkel::Keypair,
+) -> header::Header {
+    let mut header = header::Header {
+        parent_hash: parent.hash(),
+        number: parent.number + 1,
+        state_root,
+        extrinsics_root: [0; 32],
+        digest: header::DigestRef::from_slice(&[header::DigestItem::AuraPreDigest(
+            header::AuraPreDigest { slot_number },
+        )])
+        .unwrap()
+        .into(),
+    };
+
+    let signature = authority.sign_simple(b"substrate", &header.hash());
+    header.digest.push_aura_seal(signature.to_bytes()).unwrap();
+    header
+}
+
+impl<TBl, TRq, TSrc> Process for all_forks::AllForksSync<TBl, TRq, TSrc> {
+    fn process_next(
+        self,
+        now_from_unix_epoch: Duration,
+        _: FinalizedStorage,
+        events: &mut Vec<Event>,
+    ) -> Result<Self, Self> {
+        match self.process_one() {
+            all_forks::ProcessOne::AllSync { sync }
+            | all_forks::ProcessOne::WaitingForFinality { sync } => Err(sync),
+            all_forks::ProcessOne::BlockAnnouncesProcessed { sync } => Ok(sync),
+            all_forks::ProcessOne::HeaderVerify(verify) => {
+                let (height, hash) = (verify.height(), *verify.hash());
+                match verify.perform(now_from_unix_epoch) {
+                    all_forks::HeaderVerifyOutcome::Success { is_new_best, sync } => {
+                        if is_new_best {
+                            events.push(Event::NewBest(height, hash));
+                        }
+                        Ok(sync)
+                    }
+                    all_forks::HeaderVerifyOutcome::Error { sync, .. } => {
+                        events.push(Event::VerifyError(height, hash));
+                        Ok(sync)
+                    }
+                }
+            }
+            all_forks::ProcessOne::FinalityProofVerify(verify) => match verify.perform() {
+                (
+                    sync,
+                    all_forks::FinalityProofVerifyOutcome::NewFinalized {
+                        finalized_blocks, ..
+                    },
+                ) => {
+                    events.push(Event::Finalized(
+                        finalized_blocks.iter().map(|(h, _)| h.hash()).collect(),
+                    ));
+                    Ok(sync)
+                }
+                (sync, _) => Ok(sync),
+            },
+        }
+    }
+}
+
+impl<TRq, TSrc, TBl> Process for optimistic::OptimisticSync<TRq, TSrc, TBl> {
+    fn process_next(
+        self,
+        now_from_unix_epoch: Duration,
+        finalized_storage: FinalizedStorage,
+        events: &mut Vec<Event>,
+    ) -> Result<Self, Self> {
+        match self.process_one() {
+            optimistic::ProcessOne::Idle { sync }
+            | optimistic::ProcessOne::FinalizedRuntimeMissing { sync }
+            | optimistic::ProcessOne::BestRuntimeOutdated { sync }
+            | optimistic::ProcessOne::WaitingForFinality { sync } => Err(sync),
+            optimistic::ProcessOne::VerifyBlock(verify) => {
+                let mut verification = verify.start(now_from_unix_epoch);
+                loop {
+                    verification = match verification {
+                        optimistic::BlockVerification::NewBest {
+                            sync,
+                            new_best_number,
+                            new_best_hash,
+                        } => {
+                            events.push(Event::NewBest(new_best_number, new_best_hash));
+                            return Ok(sync);
+                        }
+                        optimistic::BlockVerification::Reset { sync, .. } => {
+                            events.push(Event::Reset);
+                            return Ok(sync);
+                        }
+                        optimistic::BlockVerification::FinalizedStorageGet(req) => {
+                            let key = req.key_as_vec();
+                            let value = match finalized_storage(Bound::Included(&key[..])) {
+                                Some((k, value)) if k == key => Some(value),
+                                _ => None,
+                            };
+                            req.inject_value(value.as_deref())
+                        }
+                        optimistic::BlockVerification::FinalizedStoragePrefixKeys(req) => {
+                            let prefix = req.prefix().as_ref().to_vec();
+                            let mut keys = Vec::<Vec<u8>>::new();
+                            while let Some((key, _)) = finalized_storage(match keys.last() {
+                                Some(last) => Bound::Excluded(&last[..]),
+                                None => Bound::Included(&prefix[..]),
+                            }) {
+                                if !key.starts_with(&prefix) {
+                                    break;
+                                }
+                                keys.push(key);
+                            }
+                            req.inject_keys_ordered(keys.into_iter())
+                        }
+                        optimistic::BlockVerification::FinalizedStorageNextKey(req) => {
+                            let key = req.key().as_ref().to_vec();
+                            let next_key =
+                                finalized_storage(Bound::Excluded(&key[..])).map(|(k, _)| k);
+                            req.inject_key(next_key)
+                        }
+                    };
+                }
+            }
+            optimistic::ProcessOne::VerifyJustification(verify) => match verify.perform() {
+                (
+                    sync,
+                    optimistic::JustificationVerification::Finalized {
+                        finalized_blocks,
+                        updates_finalized_runtime,
+                    },
+                ) => {
+                    events.push(Event::Finalized(
+                        finalized_blocks.iter().map(|b| b.header.hash()).collect(),
+                    ));
+                    if updates_finalized_runtime {
+                        events.push(Event::FinalizedRuntimeUpdated);
+                    }
+                    Ok(sync)
+                }
+                (sync, optimistic::JustificationVerification::Reset { .. }) => {
+                    events.push(Event::Reset);
+                    Ok(sync)
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,99 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Estimation of the number of blocks verified per second.
+//!
+//! The [`VerificationRate`] keeps track of the time at which the most recent blocks have been
+//! verified. It is shared between the syncing strategies that verify blocks one by one.
+
+use alloc::collections::VecDeque;
+use core::time::Duration;
+
+/// Maximum number of verifications that are kept track of.
+const WINDOW: usize = 32;
+
+/// Time, as provided by the API user, when the most recent blocks have been successfully
+/// verified.
+pub(super) struct VerificationRate {
+    /// Times of the verifications, from the oldest to the newest. Contains at most [`WINDOW`]
+    /// entries.
+    recent_verifications: VecDeque<Duration>,
+}
+
+impl VerificationRate {
+    /// Initializes a new empty [`VerificationRate`].
+    pub(super) fn new() -> Self {
+        VerificationRate {
+            recent_verifications: VecDeque::with_capacity(WINDOW),
+        }
+    }
+
+    /// Records that a block has been successfully verified at the given time.
+    pub(super) fn record(&mut self, now_from_unix_epoch: Duration) {
+        if self.recent_verifications.len() >= WINDOW {
+            self.recent_verifications.pop_front();
+        }
+        self.recent_verifications.push_back(now_from_unix_epoch);
+    }
+
+    /// Forgets all the verifications recorded so far.
+    pub(super) fn clear(&mut self) {
+        self.recent_verifications.clear();
+    }
+
+    /// Returns an estimation of the number of blocks verified per second.
+    ///
+    /// Returns `None` if not enough blocks have been verified yet to give an estimate.
+    pub(super) fn blocks_per_second(&self) -> Option<f64> {
+        let first = self.recent_verifications.front()?;
+        let last = self.recent_verifications.back()?;
+        let elapsed = last.checked_sub(*first)?.as_secs_f64();
+        if elapsed <= 0.0 {
+            return None;
+        }
+
+        Some((self.recent_verifications.len() - 1) as f64 / elapsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{VerificationRate, WINDOW};
+    use core::time::Duration;
+
+    #[test]
+    fn blocks_per_second() {
+        let mut rate = VerificationRate::new();
+        assert_eq!(rate.blocks_per_second(), None);
+
+        // A single verification, or verifications all at the same time, don't give any estimate.
+        rate.record(Duration::from_secs(10));
+        assert_eq!(rate.blocks_per_second(), None);
+        rate.record(Duration::from_secs(10));
+        assert_eq!(rate.blocks_per_second(), None);
+
+        // Three blocks in two seconds, the first one being at the start of the measurement.
+        rate.record(Duration::from_secs(12));
+        assert_eq!(rate.blocks_per_second(), Some(1.0));
+
+        // Only the most recent verifications are taken into account.
+        for n in 0..WINDOW {
+            rate.record(Duration::from_secs(100) + Duration::from_millis(250) * n as u32);
+        }
+        assert_eq!(rate.blocks_per_second(), Some(4.0));
+    }
+}
@@ -147,6 +147,10 @@ pub struct SourceId(usize);
 /// The result of a successful warp sync.
 pub struct Success<TSrc> {
     /// The synced chain information.
+    ///
+    /// Can be passed as [`crate::sync::optimistic::Config::chain_information`] or
+    /// [`crate::sync::all_forks::Config::chain_information`] in order to continue syncing
+    /// from the head of the chain.
     pub chain_information: ValidChainInformation,
 
     /// The runtime constructed in `VirtualMachineParamsGet`. Corresponds to the runtime of the
@@ -71,6 +71,7 @@
 use super::{nibble::Nibble, TrieEntryVersion};
 use crate::util;
 
+use alloc::vec::Vec;
 use arrayvec::ArrayVec;
 use core::fmt;
 
@@ -132,6 +133,51 @@ where
     TChIter: ExactSizeIterator<Item = Option<&'a Output>> + Clone,
     TPKey: ExactSizeIterator<Item = Nibble>,
     TVal: AsRef<[u8]>,
+{
+    // This value will be used as the sink for all the components of the merkle value.
+    let mut merkle_value_sink = if matches!(config.ty, NodeTy::Root { .. }) {
+        HashOrInline::Hasher(blake2_rfc::blake2b::Blake2b::new(32))
+    } else {
+        HashOrInline::Inline(ArrayVec::new())
+    };
+
+    write_node_value(config, &mut merkle_value_sink);
+    merkle_value_sink.finalize()
+}
+
+/// Calculates the node value of a node given the information about this node.
+///
+/// Contrary to [`calculate_merkle_value`], the output is never hashed. This is the format in
+/// which nodes are found in Merkle proofs.
+///
+/// # Panic
+///
+/// Panics if `config.children.len() != 16`.
+///
+pub fn calculate_node_value<'a, TChIter, TPKey, TVal>(
+    config: Config<TChIter, TPKey, TVal>,
+) -> Vec<u8>
+where
+    TChIter: ExactSizeIterator<Item = Option<&'a Output>> + Clone,
+    TPKey: ExactSizeIterator<Item = Nibble>,
+    TVal: AsRef<[u8]>,
+{
+    let mut node_value_sink = HashOrInline::Unhashed(Vec::new());
+    write_node_value(config, &mut node_value_sink);
+    match node_value_sink {
+        HashOrInline::Unhashed(node_value) => node_value,
+        _ => unreachable!(),
+    }
+}
+
+/// Pushes all the components of the node value to `merkle_value_sink`.
+fn write_node_value<'a, TChIter, TPKey, TVal>(
+    config: Config<TChIter, TPKey, TVal>,
+    merkle_value_sink: &mut HashOrInline,
+) where
+    TChIter: ExactSizeIterator<Item = Option<&'a Output>> + Clone,
+    TPKey: ExactSizeIterator<Item = Nibble>,
+    TVal: AsRef<[u8]>,
 {
     assert_eq!(config.children.len(), 16);
 
@@ -142,13 +188,6 @@ where
         .as_ref()
         .map(|value| matches!(config.version, TrieEntryVersion::V1) && value.as_ref().len() >= 33);
 
-    // This value will be used as the sink for all the components of the merkle value.
-    let mut merkle_value_sink = if matches!(config.ty, NodeTy::Root { .. }) {
-        HashOrInline::Hasher(blake2_rfc::blake2b::Blake2b::new(32))
-    } else {
-        HashOrInline::Inline(ArrayVec::new())
-    };
-
     // For node value calculation purposes, the root key is treated the same as the partial key.
     let mut partial_key = match config.ty {
         NodeTy::Root { key } => key,
@@ -231,7 +270,7 @@ where
             }
         }
 
-        return merkle_value_sink.finalize();
+        return;
     }
 
     // If there is any child, we a `u16` where each bit is `1` if there exists a child there.
@@ -276,8 +315,6 @@ where
             .update(util::encode_scale_compact_usize(child_merkle_value.as_ref().len()).as_ref());
         merkle_value_sink.update(child_merkle_value.as_ref());
     }
-
-    merkle_value_sink.finalize()
 }
 
 /// Output of the calculation.
@@ -342,9 +379,13 @@ impl fmt::Debug for Output {
 /// This struct serves as a helper to handle these situations. Rather than putting intermediary
 /// values in buffers then hashing the node value as a whole, we push the elements of the node
 /// value to this struct which automatically switches to hashing if the value exceeds 32 bytes.
+///
+/// The [`HashOrInline::Unhashed`] variant is used when the caller wants the node value rather
+/// than the Merkle value, and never switches to hashing.
 enum HashOrInline {
     Inline(ArrayVec<u8, 31>),
     Hasher(blake2_rfc::blake2b::Blake2b),
+    Unhashed(Vec<u8>),
 }
 
 impl HashOrInline {
@@ -363,6 +404,9 @@ impl HashOrInline {
             HashOrInline::Hasher(hasher) => {
                 hasher.update(data);
             }
+            HashOrInline::Unhashed(node_value) => {
+                node_value.extend_from_slice(data);
+            }
         }
     }
 
@@ -371,6 +415,7 @@ impl HashOrInline {
             inner: match self {
                 HashOrInline::Inline(b) => OutputInner::Inline(b),
                 HashOrInline::Hasher(h) => OutputInner::Hasher(h.finalize()),
+                HashOrInline::Unhashed(_) => unreachable!(),
             },
         }
     }
@@ -0,0 +1,270 @@
+// Smoldot
+// Copyright (C) 2019-2022  Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Generation of a trie proof.
+//!
+//! See the [`proof_verify`](super::proof_verify) module for an explanation of what a trie proof
+//! is.
+//!
+//! Use the [`build_proof`] function to build a proof. Building a proof requires knowing all the
+//! entries of the trie, as the node values of the nodes of the proof depend on the Merkle values
+//! of their descendants.
+
+use super::{nibble, node_value, trie_structure, TrieEntryVersion};
+
+use alloc::vec::Vec;
+use arrayvec::ArrayVec;
+
+/// Configuration to pass to [`build_proof`].
+pub struct Config<TEntries, TKeys> {
+    /// List of all the entries of the trie, as `(key, value)` tuples. No specific order is
+    /// required.
+    pub entries: TEntries,
+
+    /// List of keys whose storage value, or absence of storage value, the proof must prove.
+    pub requested_keys: TKeys,
+
+    /// Version of the trie entries.
+    pub version: TrieEntryVersion,
+}
+
+/// Builds a trie proof of the keys designated by [`Config::requested_keys`].
+///
+/// Returns a list of node values, in no specific order, that can be passed to
+/// [`proof_verify::verify_proof`](super::proof_verify::verify_proof). The nodes that are shared
+/// between the proofs of multiple keys are only included once.
+///
+/// # Panic
+///
+/// Panics if the same key is found multiple times in [`Config::entries`].
+///
+pub fn build_proof<'a, 'k>(
+    config: Config<impl Iterator<Item = (&'a [u8], &'a [u8])>, impl Iterator<Item = &'k [u8]>>,
+) -> Vec<Vec<u8>> {
+    let mut trie = trie_structure::TrieStructure::<Node<'a>>::new();
+
+    for (key, value) in config.entries {
+        let node = Node {
+            stored_value: Some(value),
+            ..Default::default()
+        };
+
+        match trie.node(nibble::bytes_to_nibbles(key.iter().copied())) {
+            trie_structure::Entry::Vacant(entry) => {
+                entry
+                    .insert_storage_value()
+                    .insert(node, Default::default());
+            }
+            trie_structure::Entry::Occupied(trie_structure::NodeAccess::Branch(entry)) => {
+                *entry.insert_storage_value().user_data() = node;
+            }
+            trie_structure::Entry::Occupied(trie_structure::NodeAccess::Storage(_)) => {
+                panic!("duplicate key in trie entries")
+            }
+        }
+    }
+
+    let mut proof = Vec::new();
+
+    if let Some(root_index) = trie.root_node().map(|n| n.node_index()) {
+        calculate_node_values(&mut trie, root_index, config.version);
+    } else {
+        // The trie is empty. The proof consists only in the node value of the root node.
+        if config.requested_keys.count() != 0 {
+            proof.push(node_value::calculate_node_value(node_value::Config {
+                ty: node_value::NodeTy::Root {
+                    key: core::iter::empty(),
+                },
+                children: (0..16).map(|_| None),
+                stored_value: None::<Vec<u8>>,
+                version: config.version,
+            }));
+        }
+        return proof;
+    }
+
+    for requested_key in config.requested_keys {
+        let mut requested_key = nibble::bytes_to_nibbles(requested_key.iter().copied());
+        let mut current = trie.root_node().unwrap();
+
+        // Iterate down the trie, adding to the proof all the nodes between the root and the node
+        // closest to the requested key.
+        loop {
+            proof.push(current.user_data().node_value.clone());
+
+            if !current
+                .partial_key()
+                .all(|nibble| requested_key.next() == Some(nibble))
+            {
+                // The requested key isn't in the trie.
+                break;
+            }
+
+            match requested_key.next() {
+                None => {
+                    // Found the node of the requested key. If the storage value is hashed
+                    // in the node value, the storage value itself must also be included.
+                    if let Some(stored_value) = current.user_data().stored_value {
+                        if matches!(config.version, TrieEntryVersion::V1)
+                            && stored_value.len() >= 33
+                        {
+                            proof.push(stored_value.to_vec());
+                        }
+                    }
+                    break;
+                }
+                Some(child_index) => match current.into_child(child_index) {
+                    Ok(child) => current = child,
+                    Err(_) => break,
+                },
+            }
+        }
+    }
+
+    proof.sort_unstable();
+    proof.dedup();
+    proof
+}
+
+/// User data associated to each node of the trie during the proof generation.
+#[derive(Default)]
+struct Node<'a> {
+    /// Storage value of the node, or `None` for branch nodes.
+    stored_value: Option<&'a [u8]>,
+    /// Node value of the node. Empty until [`calculate_node_values`] has been called.
+    node_value: Vec<u8>,
+    /// Merkle value of the node. `None` until [`calculate_node_values`] has been called.
+    merkle_value: Option<node_value::Output>,
+}
+
+/// Fills the node value and Merkle value of the node with the given index and of all of its
+/// descendants.
+fn calculate_node_values(
+    trie: &mut trie_structure::TrieStructure<Node>,
+    node_index: trie_structure::NodeIndex,
+    version: TrieEntryVersion,
+) {
+    // The node value of a node depends on the Merkle values of its children, which must
+    // consequently be calculated first.
+    let children = {
+        let mut node = trie.node_by_index(node_index).unwrap();
+        nibble::all_nibbles()
+            .map(|nibble| node.child(nibble).map(|child| child.node_index()))
+            .collect::<ArrayVec<_, 16>>()
+    };
+    for child_index in children.iter().flatten() {
+        calculate_node_values(trie, *child_index, version);
+    }
+
+    let mut node = trie.node_by_index(node_index).unwrap();
+    let stored_value = node.user_data().stored_value;
+    let is_root_node = node.is_root_node();
+
+    let node_value = node_value::calculate_node_value(node_value::Config {
+        ty: if is_root_node {
+            node_value::NodeTy::Root {
+                key: node.partial_key(),
+            }
+        } else {
+            node_value::NodeTy::NonRoot {
+                partial_key: node.partial_key(),
+            }
+        },
+        children: (0..16u8).map(|child_idx| {
+            node.child_user_data(nibble::Nibble::try_from(child_idx).unwrap())
+                .map(|child| child.merkle_value.as_ref().unwrap())
+        }),
+        stored_value,
+        version,
+    });
+
+    // The Merkle value is the hash of the node value, or the node value itself if it is shorter
+    // than 32 bytes. The Merkle value of the root node is always hashed.
+    let merkle_value = if is_root_node || node_value.len() >= 32 {
+        node_value::Output::from_bytes(
+            blake2_rfc::blake2b::blake2b(32, &[], &node_value).as_bytes(),
+        )
+    } else {
+        node_value::Output::from_bytes(&node_value)
+    };
+
+    node.user_data().node_value = node_value;
+    node.user_data().merkle_value = Some(merkle_value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{proof_verify, trie_root, TrieEntryVersion};
+
+    #[test]
+    fn proof_verifies() {
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = vec![
+            (b"foo".to_vec(), b"bar".to_vec()),
+            (b"fob".to_vec(), vec![0xaa; 64]),
+            (b"f".to_vec(), b"branch".to_vec()),
+            (b"hello".to_vec(), vec![0xbb; 40]),
+        ];
+
+        for version in [TrieEntryVersion::V0, TrieEntryVersion::V1] {
+            let root = trie_root(version, &entries);
+            let requested_keys: [&[u8]; 4] = [b"foo", b"fob", b"f", b"absent"];
+
+            let proof = super::build_proof(super::Config {
+                entries: entries.iter().map(|(k, v)| (&k[..], &v[..])),
+                requested_keys: requested_keys.iter().copied(),
+                version,
+            });
+
+            for key in requested_keys {
+                let expected = entries.iter().find(|(k, _)| k == key).map(|(_, v)| &v[..]);
+                let obtained = proof_verify::verify_proof(proof_verify::VerifyProofConfig {
+                    requested_key: key,
+                    trie_root_hash: &root,
+                    proof: proof.iter().map(|p| &p[..]),
+                })
+                .unwrap();
+                assert_eq!(obtained, expected);
+            }
+
+            // Keys that haven't been requested can't be proven, unless their node is inlined in
+            // one of the nodes of the proof, which isn't the case here.
+            assert!(proof_verify::verify_proof(proof_verify::VerifyProofConfig {
+                requested_key: b"hello",
+                trie_root_hash: &root,
+                proof: proof.iter().map(|p| &p[..]),
+            })
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn empty_trie() {
+        let proof = super::build_proof(super::Config {
+            entries: core::iter::empty(),
+            requested_keys: [&b"foo"[..]].into_iter(),
+            version: TrieEntryVersion::V1,
+        });
+
+        // The proof consists in the node value of the root node of an empty trie, whose hash is
+        // the Merkle value of an empty trie.
+        assert_eq!(proof, vec![vec![0]]);
+        assert_eq!(
+            blake2_rfc::blake2b::blake2b(32, &[], &proof[0]).as_bytes(),
+            super::super::empty_trie_merkle_value()
+        );
+    }
+}
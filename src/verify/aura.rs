@@ -65,6 +65,15 @@ pub struct VerifyConfig<'a, TAuthList> {
     /// 00:00:00 UTC on 1 January 1970), ignoring leap seconds.
     pub now_from_unix_epoch: Duration,
 
+    /// Maximum duration by which the slot of the block is allowed to be in the future compared
+    /// to [`VerifyConfig::now_from_unix_epoch`], in order to tolerate clock drifts either
+    /// locally or on the authority that has created the block.
+    ///
+    /// If the local node is an authority itself, and the best block uses a slot number `N`
+    /// seconds in the future, then for the next `N` seconds the local node won't produce any
+    /// block. As such, a high value constitutes an attack vector.
+    pub max_future_drift: Duration,
+
     /// Aura authorities that must validate the block.
     ///
     /// This list is either equal to the parent's list, or, if the parent changes the list of
@@ -95,8 +104,18 @@ pub enum VerifyError {
     ParentIsntAuraConsensus,
     /// Slot number must be strictly increasing between a parent and its child.
     SlotNumberNotIncreasing,
-    /// Slot number starts too far in the future.
-    TooFarInFuture,
+    /// Slot number starts further in the future than [`VerifyConfig::max_future_drift`] allows.
+    #[display(
+        fmt = "Slot number {} is too far in the future. Maximum allowed: {}",
+        slot,
+        max_allowed
+    )]
+    TooFarInFuture {
+        /// Slot number of the block.
+        slot: u64,
+        /// Highest slot number that is currently accepted.
+        max_allowed: u64,
+    },
     /// Block header signature is invalid.
     BadSignature,
     /// Failed to parse Ed25519 public key.
@@ -138,16 +157,19 @@ pub fn verify_header<'a>(
 
     // Check that the slot number isn't a slot in the future.
     // Since there might be a clock drift (either locally or on the authority that created the
-    // block), a tolerance period is added.
-    // If the local node is an authority itself, and the best block uses a slot number `N` seconds
-    // in the future, then for the next `N` seconds the local node won't produce any block. As
-    // such, a high tolerance level constitutes an attack vector.
+    // block), a tolerance period is added. See `VerifyConfig::max_future_drift`.
     {
-        const TOLERANCE: Duration = Duration::from_secs(30);
-        let current_slot =
-            (config.now_from_unix_epoch + TOLERANCE).as_secs() * 1000 / config.slot_duration.get();
+        let current_slot = config
+            .now_from_unix_epoch
+            .saturating_add(config.max_future_drift)
+            .as_secs()
+            .saturating_mul(1000)
+            / config.slot_duration.get();
         if slot_number > current_slot {
-            return Err(VerifyError::TooFarInFuture);
+            return Err(VerifyError::TooFarInFuture {
+                slot: slot_number,
+                max_allowed: current_slot,
+            });
         }
     };
 
@@ -202,3 +224,83 @@ pub fn verify_header<'a>(
     // Success! 🚀
     Ok(VerifySuccess { authorities_change })
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::header;
+    use core::{iter, num::NonZeroU64, time::Duration};
+
+    /// Verifies a block, child of a genesis block, signed by a single authority during the given
+    /// slot.
+    fn verify_at_slot(
+        slot_number: u64,
+        now_from_unix_epoch: Duration,
+        max_future_drift: Duration,
+    ) -> Result<super::VerifySuccess, super::VerifyError> {
+        let authority = schnorrkel::MiniSecretKey::from_bytes(&[1; 32])
+            .unwrap()
+            .expand_to_keypair(schnorrkel::ExpansionMode::Ed25519);
+
+        let parent = header::Header {
+            parent_hash: [0; 32],
+            number: 0,
+            state_root: [0; 32],
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::empty().into(),
+        };
+        let mut header = header::Header {
+            parent_hash: parent.hash(),
+            number: 1,
+            state_root: [0; 32],
+            extrinsics_root: [0; 32],
+            digest: header::DigestRef::from_slice(&[header::DigestItem::AuraPreDigest(
+                header::AuraPreDigest { slot_number },
+            )])
+            .unwrap()
+            .into(),
+        };
+        let signature = authority.sign_simple(b"substrate", &header.hash());
+        header.digest.push_aura_seal(signature.to_bytes()).unwrap();
+
+        let public_key = authority.public.to_bytes();
+        super::verify_header(super::VerifyConfig {
+            header: (&header).into(),
+            parent_block_header: (&parent).into(),
+            now_from_unix_epoch,
+            max_future_drift,
+            current_authorities: iter::once(header::AuraAuthorityRef {
+                public_key: &public_key,
+            }),
+            slot_duration: NonZeroU64::new(6000).unwrap(),
+        })
+    }
+
+    #[test]
+    fn future_drift() {
+        // With a slot duration of 6 seconds, the slot at 60 seconds plus 30 seconds of drift is
+        // slot 15.
+        let now = Duration::from_secs(60);
+        let drift = Duration::from_secs(30);
+
+        assert!(verify_at_slot(15, now, drift).is_ok());
+        assert!(matches!(
+            verify_at_slot(16, now, drift),
+            Err(super::VerifyError::TooFarInFuture {
+                slot: 16,
+                max_allowed: 15
+            })
+        ));
+        assert!(matches!(
+            verify_at_slot(11, now, Duration::from_secs(0)),
+            Err(super::VerifyError::TooFarInFuture {
+                slot: 11,
+                max_allowed: 10
+            })
+        ));
+    }
+
+    #[test]
+    fn future_drift_doesnt_overflow() {
+        assert!(verify_at_slot(15, Duration::MAX, Duration::MAX).is_ok());
+    }
+}
@@ -148,7 +148,7 @@ pub struct VerifyConfig<'a> {
 
     /// Time elapsed since [the Unix Epoch](https://en.wikipedia.org/wiki/Unix_time) (i.e.
     /// 00:00:00 UTC on 1 January 1970), ignoring leap seconds.
-    // TODO: unused, should check against a block's slot
+    // TODO: unused, should check against a block's slot, which requires knowing the slot duration
     pub now_from_unix_epoch: Duration,
 
     /// Number of slots per epoch in the Babe configuration.
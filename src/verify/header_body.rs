@@ -49,6 +49,14 @@ pub struct Config<'a, TBody> {
     /// the network is bounded.
     pub allow_unknown_consensus_engines: bool,
 
+    /// Maximum duration by which the slot of a block using the Aura consensus engine is allowed
+    /// to be in the future compared to the current time. See
+    /// [`aura::VerifyConfig::max_future_drift`].
+    ///
+    /// > **Note**: The slot of blocks using the Babe consensus engine isn't compared with the
+    /// >           current time, and this value is ignored for them.
+    pub aura_max_future_drift: Duration,
+
     /// Time elapsed since [the Unix Epoch](https://en.wikipedia.org/wiki/Unix_time) (i.e.
     /// 00:00:00 UTC on 1 January 1970), ignoring leap seconds.
     pub now_from_unix_epoch: Duration,
@@ -183,6 +191,19 @@ pub enum Error {
         engine
     )]
     UnknownConsensusEngine { engine: [u8; 4] },
+    /// The slot of the block is further in the future than allowed by
+    /// [`Config::aura_max_future_drift`].
+    #[display(
+        fmt = "Slot number {} is too far in the future. Maximum allowed: {}",
+        slot,
+        max_allowed
+    )]
+    TooFarInFuture {
+        /// Slot number of the block.
+        slot: u64,
+        /// Highest slot number that is currently accepted.
+        max_allowed: u64,
+    },
     /// Failed to verify the authenticity of the block with the AURA algorithm.
     #[display(fmt = "{}", _0)]
     AuraVerification(aura::VerifyError),
@@ -242,6 +263,7 @@ pub fn verify(
             let result = aura::verify_header(aura::VerifyConfig {
                 header: config.block_header.clone(),
                 parent_block_header: config.parent_block_header,
+                max_future_drift: config.aura_max_future_drift,
                 now_from_unix_epoch: config.now_from_unix_epoch,
                 current_authorities: current_authorities.clone(),
                 slot_duration: *slot_duration,
@@ -251,6 +273,12 @@ pub fn verify(
                 Ok(s) => SuccessConsensus::Aura {
                     authorities_change: s.authorities_change,
                 },
+                Err(aura::VerifyError::TooFarInFuture { slot, max_allowed }) => {
+                    return Verify::Finished(Err((
+                        Error::TooFarInFuture { slot, max_allowed },
+                        config.parent_runtime,
+                    )))
+                }
                 Err(err) => {
                     return Verify::Finished(Err((
                         Error::AuraVerification(err),
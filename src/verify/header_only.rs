@@ -46,6 +46,14 @@ pub struct Config<'a> {
     /// Consequently, both `true` and `false` guarantee that the number of authorable blocks over
     /// the network is bounded.
     pub allow_unknown_consensus_engines: bool,
+
+    /// Maximum duration by which the slot of a block using the Aura consensus engine is allowed
+    /// to be in the future compared to the current time. See
+    /// [`aura::VerifyConfig::max_future_drift`].
+    ///
+    /// > **Note**: The slot of blocks using the Babe consensus engine isn't compared with the
+    /// >           current time, and this value is ignored for them.
+    pub aura_max_future_drift: Duration,
 }
 
 /// Extra items of [`Config`] that are dependant on the consensus engine of the chain.
@@ -125,6 +133,19 @@ pub enum Error {
     UnknownConsensusEngine { engine: [u8; 4] },
     /// Block header contains items relevant to multiple consensus engines at the same time.
     MultipleConsensusEngines,
+    /// The slot of the block is further in the future than allowed by
+    /// [`Config::aura_max_future_drift`].
+    #[display(
+        fmt = "Slot number {} is too far in the future. Maximum allowed: {}",
+        slot,
+        max_allowed
+    )]
+    TooFarInFuture {
+        /// Slot number of the block.
+        slot: u64,
+        /// Highest slot number that is currently accepted.
+        max_allowed: u64,
+    },
     /// Failed to verify the authenticity of the block with the AURA algorithm.
     #[display(fmt = "{}", _0)]
     AuraVerification(aura::VerifyError),
@@ -189,6 +210,7 @@ pub fn verify(config: Config) -> Result<Success, Error> {
             let result = aura::verify_header(aura::VerifyConfig {
                 header: config.block_header.clone(),
                 parent_block_header: config.parent_block_header,
+                max_future_drift: config.aura_max_future_drift,
                 now_from_unix_epoch,
                 current_authorities,
                 slot_duration,
@@ -198,6 +220,9 @@ pub fn verify(config: Config) -> Result<Success, Error> {
                 Ok(s) => Ok(Success::Aura {
                     authorities_change: s.authorities_change,
                 }),
+                Err(aura::VerifyError::TooFarInFuture { slot, max_allowed }) => {
+                    Err(Error::TooFarInFuture { slot, max_allowed })
+                }
                 Err(err) => Err(Error::AuraVerification(err)),
             }
         }